@@ -0,0 +1,76 @@
+// aggregation.rs
+// Policies deciding when a parent sub-fractal commits despite absent children
+
+use serde::{Deserialize, Serialize};
+
+/// Decides whether enough children committed for their parent to commit without the rest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AggregationPolicy {
+    /// Every child must commit.
+    Unanimous,
+    /// More than half of the children must commit: 2 of 3.
+    #[default]
+    Majority,
+    /// At least `required` children must commit.
+    Threshold { required: usize },
+    /// The children that committed must hold at least `numerator / denominator` of the
+    /// parent's nodes, so a small child failing costs less than a large one.
+    NodeWeighted { numerator: usize, denominator: usize },
+}
+
+impl AggregationPolicy {
+    /// Returns true if the children marked `present` satisfy the policy. `weights` holds
+    /// each child's node count, in the same order. A parent without children is never
+    /// satisfied, since it would commit to nothing.
+    pub fn is_satisfied(&self, present: &[bool], weights: &[usize]) -> bool {
+        let total = present.len();
+        let committed = present.iter().filter(|&&p| p).count();
+        if total == 0 || committed == 0 {
+            return false;
+        }
+        match *self {
+            AggregationPolicy::Unanimous => committed == total,
+            AggregationPolicy::Majority => 2 * committed > total,
+            AggregationPolicy::Threshold { required } => committed >= required.min(total),
+            AggregationPolicy::NodeWeighted { numerator, denominator } => {
+                let all: usize = weights.iter().sum();
+                let held: usize = present.iter().zip(weights).filter(|(&p, _)| p).map(|(_, &w)| w).sum();
+                held * denominator.max(1) >= all * numerator
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_child_count_policies() {
+        let two_of_three = [true, false, true];
+        let one_of_three = [false, false, true];
+        let weights = [4, 4, 4];
+
+        assert!(!AggregationPolicy::Unanimous.is_satisfied(&two_of_three, &weights));
+        assert!(AggregationPolicy::Unanimous.is_satisfied(&[true; 3], &weights));
+        assert!(AggregationPolicy::Majority.is_satisfied(&two_of_three, &weights));
+        assert!(!AggregationPolicy::Majority.is_satisfied(&one_of_three, &weights));
+        assert!(AggregationPolicy::Threshold { required: 1 }.is_satisfied(&one_of_three, &weights));
+        assert!(!AggregationPolicy::Threshold { required: 3 }.is_satisfied(&two_of_three, &weights));
+        // A threshold above the number of children only asks for all of them.
+        assert!(AggregationPolicy::Threshold { required: 5 }.is_satisfied(&[true, true], &[4, 4]));
+        assert!(!AggregationPolicy::Majority.is_satisfied(&[], &[]));
+        assert!(!AggregationPolicy::Threshold { required: 0 }.is_satisfied(&[false; 3], &weights));
+    }
+
+    #[test]
+    fn test_node_weighted_policy() {
+        let two_thirds = AggregationPolicy::NodeWeighted { numerator: 2, denominator: 3 };
+        // Losing the small child keeps 8 of 10 nodes.
+        assert!(two_thirds.is_satisfied(&[true, true, false], &[4, 4, 2]));
+        // Losing a large one keeps only 6 of 10.
+        assert!(!two_thirds.is_satisfied(&[true, false, true], &[4, 4, 2]));
+        // Exactly two thirds is enough.
+        assert!(two_thirds.is_satisfied(&[true, true, false], &[4, 4, 4]));
+    }
+}
@@ -0,0 +1,199 @@
+// commitment.rs
+// Sub-fractal commitments: recursive hash propagation from leaf quorums to the root
+
+use serde::{Deserialize, Serialize};
+use super::aggregation::AggregationPolicy;
+use super::committee::{group_into_tree, CommitteeAssignment};
+use super::pbft::QuorumCertificate;
+use crate::core::security::keys::Keyring;
+use crate::core::triad_matrix::encoding::{TriadHeader, TriadRecord};
+use crate::core::triad_matrix::triad_structure::Triad;
+
+/// How a sub-fractal backs the Triad it commits to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommitmentProof {
+    /// A leaf's Triad was committed by 2f+1 of its nodes.
+    Quorum {
+        nodes: Vec<String>,
+        fault_tolerance: usize,
+        certificate: QuorumCertificate,
+    },
+    /// A parent's Triad holds its children's Triads; each child slot carries that child's
+    /// commitment, in the same order as `Triad::child_references`, or None if the child
+    /// was absent. `child_weights` are the children's node counts and `policy` the rule
+    /// under which the present children were enough.
+    Aggregate {
+        children: Vec<Option<SubFractalCommitment>>,
+        child_weights: Vec<usize>,
+        policy: AggregationPolicy,
+    },
+}
+
+/// The tree a commitment must follow: the nodes of each leaf and the faults they tolerate,
+/// and the policy each parent aggregates its children under. A verifier builds it from what
+/// it knows independently, such as the epoch's committee assignment, never from the
+/// commitment being checked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommitmentShape {
+    Leaf { nodes: Vec<String>, fault_tolerance: usize },
+    Parent { children: Vec<CommitmentShape>, policy: AggregationPolicy },
+}
+
+impl CommitmentShape {
+    /// The shape of a tree whose leaves are the committees of `assignment`, grouped as
+    /// `HierarchicalRecursiveConsensus::with_committees` groups them, with every parent
+    /// aggregating under `policy`.
+    pub fn from_committees(assignment: &CommitteeAssignment, policy: AggregationPolicy) -> Self {
+        let leaves = assignment
+            .leaves
+            .iter()
+            .map(|nodes| CommitmentShape::Leaf { nodes: nodes.clone(), fault_tolerance: assignment.fault_tolerance })
+            .collect();
+        group_into_tree(leaves, |children| CommitmentShape::Parent { children, policy }).unwrap_or(
+            CommitmentShape::Leaf { nodes: Vec::new(), fault_tolerance: assignment.fault_tolerance },
+        )
+    }
+
+    /// Number of nodes in the leaves under this shape; its weight in its parent's policy.
+    pub fn node_count(&self) -> usize {
+        match self {
+            CommitmentShape::Leaf { nodes, .. } => nodes.len(),
+            CommitmentShape::Parent { children, .. } => children.iter().map(CommitmentShape::node_count).sum(),
+        }
+    }
+
+    /// Number of layers from this shape down to its deepest leaf, both included: the most
+    /// confirmations a round over this shape can give a Triad.
+    pub fn layers(&self) -> usize {
+        match self {
+            CommitmentShape::Leaf { .. } => 1,
+            CommitmentShape::Parent { children, .. } => 1 + children.iter().map(CommitmentShape::layers).max().unwrap_or(0),
+        }
+    }
+}
+
+/// What a sub-fractal hands its parent after a successful round: the header and content
+/// hash of its committed Triad, and the proof behind them. The root's commitment covers
+/// the whole tree, since every parent's hash commits to its children's hashes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubFractalCommitment {
+    pub header: TriadHeader,
+    pub triad_hash: [u8; 32],
+    pub proof: CommitmentProof,
+}
+
+impl SubFractalCommitment {
+    /// Commitment of a leaf whose Triad committed under `certificate`.
+    pub fn leaf(triad: &Triad, nodes: Vec<String>, fault_tolerance: usize, certificate: QuorumCertificate) -> Self {
+        SubFractalCommitment {
+            header: triad.header(),
+            triad_hash: triad.hash(),
+            proof: CommitmentProof::Quorum { nodes, fault_tolerance, certificate },
+        }
+    }
+
+    /// Commitment of a parent whose Triad has the present children's Triads attached in
+    /// the slots given by `children`.
+    pub fn aggregate(
+        triad: &Triad,
+        children: Vec<Option<SubFractalCommitment>>,
+        child_weights: Vec<usize>,
+        policy: AggregationPolicy,
+    ) -> Self {
+        SubFractalCommitment {
+            header: triad.header(),
+            triad_hash: triad.hash(),
+            proof: CommitmentProof::Aggregate { children, child_weights, policy },
+        }
+    }
+
+    /// Child slots that were absent from this commitment; empty for a leaf.
+    pub fn absent_children(&self) -> Vec<usize> {
+        match &self.proof {
+            CommitmentProof::Quorum { .. } => Vec::new(),
+            CommitmentProof::Aggregate { children, .. } => {
+                children.iter().enumerate().filter(|(_, c)| c.is_none()).map(|(slot, _)| slot).collect()
+            }
+        }
+    }
+
+    /// Checks the commitment down to every leaf against the `expected` shape: each leaf must
+    /// be its assigned committee, tolerating the assigned f of at least 1, with a certificate
+    /// carrying 2f+1 signatures for its Triad that verify against `keyring`; each parent must
+    /// have the expected children, weights and policy, and its present children must satisfy
+    /// the policy; and each Triad hash must follow from its header and the hashes its
+    /// children committed to. Nothing the commitment says about its own shape is trusted.
+    pub fn verify(&self, expected: &CommitmentShape, keyring: &Keyring) -> Result<(), String> {
+        let mut child_hashes = [None; 3];
+        match (&self.proof, expected) {
+            (
+                CommitmentProof::Quorum { nodes, fault_tolerance, certificate },
+                CommitmentShape::Leaf { nodes: committee, fault_tolerance: assigned },
+            ) => {
+                if *fault_tolerance == 0 {
+                    return Err("A leaf must tolerate at least one fault".to_string());
+                }
+                if fault_tolerance != assigned {
+                    return Err(format!("Leaf tolerates {} faults, expected {}", fault_tolerance, assigned));
+                }
+                if nodes != committee {
+                    return Err("Leaf nodes are not its assigned committee".to_string());
+                }
+                if nodes.len() < 3 * fault_tolerance + 1 {
+                    return Err(format!("{} nodes cannot tolerate {} faults", nodes.len(), fault_tolerance));
+                }
+                if certificate.digest != self.triad_hash {
+                    return Err("Quorum certificate is for a different triad".to_string());
+                }
+                if !certificate.is_valid(nodes, keyring, 2 * fault_tolerance + 1) {
+                    return Err("Quorum certificate lacks 2f+1 valid commit signatures".to_string());
+                }
+            }
+            (
+                CommitmentProof::Aggregate { children, child_weights, policy },
+                CommitmentShape::Parent { children: shapes, policy: expected_policy },
+            ) => {
+                if children.len() > 3 {
+                    return Err(format!("A triad has at most 3 children, got {}", children.len()));
+                }
+                if children.len() != shapes.len() {
+                    return Err(format!("Expected {} children, got {}", shapes.len(), children.len()));
+                }
+                let weights: Vec<usize> = shapes.iter().map(CommitmentShape::node_count).collect();
+                if *child_weights != weights {
+                    return Err("Child weights do not match the children's committees".to_string());
+                }
+                if policy != expected_policy {
+                    return Err(format!("Aggregated under {:?}, expected {:?}", policy, expected_policy));
+                }
+                let present: Vec<bool> = children.iter().map(Option::is_some).collect();
+                if !policy.is_satisfied(&present, child_weights) {
+                    return Err(format!("Present children do not satisfy {:?}", policy));
+                }
+                for (slot, (child, shape)) in children.iter().zip(shapes).enumerate() {
+                    if let Some(child) = child {
+                        child.verify(shape, keyring).map_err(|e| format!("Child {}: {}", slot, e))?;
+                        child_hashes[slot] = Some(child.triad_hash);
+                    }
+                }
+            }
+            (CommitmentProof::Quorum { .. }, CommitmentShape::Parent { .. }) => {
+                return Err("Expected an aggregate of children, got a leaf quorum".to_string());
+            }
+            (CommitmentProof::Aggregate { .. }, CommitmentShape::Leaf { .. }) => {
+                return Err("Expected a leaf quorum, got an aggregate".to_string());
+            }
+        }
+
+        let record = TriadRecord {
+            header: self.header.clone(),
+            transactions: Vec::new(),
+            child_hashes,
+            evidence: Vec::new(),
+        };
+        if record.hash() != self.triad_hash {
+            return Err("Triad hash does not match its header and children".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+// committee.rs
+// Committee formation: assigning staked, promoted nodes to leaf sub-fractals each epoch
+
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::core::security::redundant_paths::RedundantPathSecurity;
+use crate::interface::economics::waclanium_token::WaclaniumToken;
+
+/// Parameters of committee formation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitteeConfig {
+    /// Faults each leaf committee must tolerate; every leaf gets at least 3f+1 nodes.
+    pub fault_tolerance: usize,
+    /// Smallest stake a promoted node needs to be eligible.
+    pub min_stake: u64,
+    /// Most leaf committees to form. Eligible nodes beyond what these need are spread
+    /// across them rather than left out.
+    pub max_leaves: usize,
+    /// Number of rounds per epoch. Committees are reassigned once per epoch.
+    pub epoch_length: u64,
+}
+
+impl Default for CommitteeConfig {
+    fn default() -> Self {
+        CommitteeConfig {
+            fault_tolerance: 1,
+            min_stake: 1,
+            max_leaves: 9,
+            epoch_length: 100,
+        }
+    }
+}
+
+impl CommitteeConfig {
+    /// Smallest leaf that tolerates `fault_tolerance` faults.
+    pub fn leaf_size(&self) -> usize {
+        3 * self.fault_tolerance + 1
+    }
+}
+
+/// The leaf committees of one epoch and the seed they were drawn with.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitteeAssignment {
+    pub epoch: u64,
+    pub seed: [u8; 32],
+    pub fault_tolerance: usize,
+    pub leaves: Vec<Vec<String>>,
+}
+
+impl CommitteeAssignment {
+    /// Every node in the assignment, leaf by leaf.
+    pub fn members(&self) -> Vec<String> {
+        self.leaves.iter().flatten().cloned().collect()
+    }
+
+    /// The leaf a node sits in, if any.
+    pub fn leaf_of(&self, node: &str) -> Option<usize> {
+        self.leaves.iter().position(|leaf| leaf.iter().any(|n| n == node))
+    }
+
+    /// Recomputes the assignment from the previous root hash and the eligible nodes, and
+    /// checks it matches. Anyone holding the previous root can check who sits where.
+    pub fn verify(&self, previous_root: &[u8; 32], eligible: &[String], config: &CommitteeConfig) -> Result<(), String> {
+        if config.fault_tolerance != self.fault_tolerance {
+            return Err("Assignment was formed for a different fault tolerance".to_string());
+        }
+        let expected = assign_committees(eligible, previous_root, self.epoch, config)?;
+        if expected.seed != self.seed {
+            return Err("Seed does not follow from the previous root".to_string());
+        }
+        if expected.leaves != self.leaves {
+            return Err("Leaves do not follow from the seed".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Seed for an epoch's shuffle: SHA-256 over the previous root Triad's hash and the epoch
+/// number. Nobody can pick their committee ahead of time without choosing the root, and
+/// anyone can recompute it once the root is known.
+pub fn epoch_seed(previous_root: &[u8; 32], epoch: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(previous_root);
+    hasher.update(epoch.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Epoch a round falls in.
+pub fn epoch_of(round: u64, config: &CommitteeConfig) -> u64 {
+    round / config.epoch_length.max(1)
+}
+
+/// Candidates that are admitted and promoted in `security` and hold at least `min_stake` in
+/// `token`, sorted so the result does not depend on the order candidates were listed in.
+pub fn eligible_nodes(
+    candidates: &[String],
+    security: &RedundantPathSecurity,
+    token: &WaclaniumToken,
+    config: &CommitteeConfig,
+) -> Vec<String> {
+    let mut eligible: Vec<String> = candidates
+        .iter()
+        .filter(|node| {
+            security.is_admitted(node) && security.is_node_promoted(node) && token.get_stake(node) >= config.min_stake.max(1)
+        })
+        .cloned()
+        .collect();
+    eligible.sort();
+    eligible.dedup();
+    eligible
+}
+
+/// Splits `nodes` into `parts` contiguous groups whose sizes differ by at most one.
+pub fn split_balanced<T: Clone>(nodes: &[T], parts: usize) -> Vec<Vec<T>> {
+    let parts = parts.clamp(1, nodes.len().max(1));
+    let (base, extra) = (nodes.len() / parts, nodes.len() % parts);
+    let mut groups = Vec::with_capacity(parts);
+    let mut start = 0;
+    for part in 0..parts {
+        let size = base + usize::from(part < extra);
+        groups.push(nodes[start..start + size].to_vec());
+        start += size;
+    }
+    groups
+}
+
+/// Groups `leaves` three to a parent, level by level, until a single root remains, and
+/// returns it. `parent` builds a node from its children; parents on one level differ in
+/// size by at most one child. None if there are no leaves.
+pub fn group_into_tree<T>(leaves: Vec<T>, mut parent: impl FnMut(Vec<T>) -> T) -> Option<T> {
+    let mut level = leaves;
+    while level.len() > 1 {
+        let parents = level.len().div_ceil(3);
+        let (base, extra) = (level.len() / parents, level.len() % parents);
+        let mut remaining = level.into_iter();
+        level = (0..parents)
+            .map(|index| parent(remaining.by_ref().take(base + usize::from(index < extra)).collect()))
+            .collect();
+    }
+    level.pop()
+}
+
+/// Assigns `eligible` nodes to leaf committees for `epoch`. The nodes are shuffled with
+/// a ChaCha8 stream seeded by `epoch_seed`, then dealt into as many leaves as the
+/// eligible set can fill with 3f+1 nodes each, up to `max_leaves`. Fails if there are
+/// not enough eligible nodes for even one leaf.
+pub fn assign_committees(
+    eligible: &[String],
+    previous_root: &[u8; 32],
+    epoch: u64,
+    config: &CommitteeConfig,
+) -> Result<CommitteeAssignment, String> {
+    let mut nodes = eligible.to_vec();
+    nodes.sort();
+    nodes.dedup();
+    let leaf_size = config.leaf_size();
+    if nodes.len() < leaf_size {
+        return Err(format!(
+            "{} eligible nodes cannot form a committee tolerating {} faults",
+            nodes.len(),
+            config.fault_tolerance
+        ));
+    }
+
+    let seed = epoch_seed(previous_root, epoch);
+    nodes.shuffle(&mut ChaCha8Rng::from_seed(seed));
+
+    let leaves = (nodes.len() / leaf_size).min(config.max_leaves.max(1));
+    Ok(CommitteeAssignment {
+        epoch,
+        seed,
+        fault_tolerance: config.fault_tolerance,
+        leaves: split_balanced(&nodes, leaves),
+    })
+}
+
+/// Keeps the current epoch's committees and reassigns them when a round enters a new
+/// epoch.
+#[derive(Clone, Debug)]
+pub struct CommitteeSchedule {
+    pub config: CommitteeConfig,
+    current: Option<CommitteeAssignment>,
+}
+
+impl CommitteeSchedule {
+    pub fn new(config: CommitteeConfig) -> Self {
+        CommitteeSchedule { config, current: None }
+    }
+
+    /// The assignment in force, if one has been formed.
+    pub fn current(&self) -> Option<&CommitteeAssignment> {
+        self.current.as_ref()
+    }
+
+    /// Returns true if `round` falls outside the epoch of the current assignment.
+    pub fn needs_rebalance(&self, round: u64) -> bool {
+        self.current
+            .as_ref()
+            .is_none_or(|assignment| assignment.epoch != epoch_of(round, &self.config))
+    }
+
+    /// Committees for `round`. At the first round of an epoch they are drawn afresh from
+    /// the candidates eligible at that point, seeded by `previous_root`; within an epoch
+    /// the existing assignment is kept. On failure the previous assignment stays in force.
+    pub fn committees_for(
+        &mut self,
+        round: u64,
+        previous_root: &[u8; 32],
+        candidates: &[String],
+        security: &RedundantPathSecurity,
+        token: &WaclaniumToken,
+    ) -> Result<&CommitteeAssignment, String> {
+        if self.needs_rebalance(round) {
+            let eligible = eligible_nodes(candidates, security, token, &self.config);
+            let assignment = assign_committees(&eligible, previous_root, epoch_of(round, &self.config), &self.config)?;
+            self.current = Some(assignment);
+        }
+        self.current.as_ref().ok_or_else(|| "No committee assignment".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::security::keys::node_ids;
+
+    fn staked(n: usize) -> (RedundantPathSecurity, WaclaniumToken) {
+        let mut security = RedundantPathSecurity::new();
+        let mut token = WaclaniumToken::new(0, 1_000_000, 1);
+        for node in node_ids(n) {
+            security.promote_node(&node);
+            token.mint(&node, 100).unwrap();
+            token.stake(&node, 50).unwrap();
+        }
+        (security, token)
+    }
+
+    #[test]
+    fn test_eligibility_needs_promotion_and_stake() {
+        let (mut security, mut token) = staked(6);
+        security.remove_promoted_node("node1");
+        token.unstake("node2", 50).unwrap();
+        let config = CommitteeConfig { min_stake: 50, ..CommitteeConfig::default() };
+
+        let mut candidates = node_ids(7);
+        candidates.reverse();
+        let eligible = eligible_nodes(&candidates, &security, &token, &config);
+        // node7 was never promoted, node1 was demoted and node2 holds no stake.
+        assert_eq!(eligible, vec!["node3", "node4", "node5", "node6"]);
+    }
+
+    #[test]
+    fn test_every_leaf_meets_bound() {
+        let config = CommitteeConfig::default();
+        for n in 4..40 {
+            let assignment = assign_committees(&node_ids(n), &[7u8; 32], 0, &config).unwrap();
+            assert!(assignment.leaves.len() <= config.max_leaves);
+            assert!(assignment.leaves.iter().all(|leaf| leaf.len() >= config.leaf_size()), "{} nodes", n);
+            assert_eq!(assignment.members().len(), n);
+        }
+        assert!(assign_committees(&node_ids(3), &[7u8; 32], 0, &config).is_err());
+
+        let config = CommitteeConfig { fault_tolerance: 2, max_leaves: 2, ..CommitteeConfig::default() };
+        let assignment = assign_committees(&node_ids(30), &[7u8; 32], 0, &config).unwrap();
+        assert_eq!(assignment.leaves.len(), 2);
+        assert!(assignment.leaves.iter().all(|leaf| leaf.len() == 15));
+    }
+
+    #[test]
+    fn test_shuffle_is_verifiable() {
+        let config = CommitteeConfig::default();
+        let root = [3u8; 32];
+        let assignment = assign_committees(&node_ids(12), &root, 5, &config).unwrap();
+
+        // Listing order does not matter, and anyone can recompute the result.
+        let mut listed = node_ids(12);
+        listed.reverse();
+        assert_eq!(assign_committees(&listed, &root, 5, &config).unwrap(), assignment);
+        assert_eq!(assignment.seed, epoch_seed(&root, 5));
+        assert!(assignment.verify(&root, &node_ids(12), &config).is_ok());
+        assert!(assignment.verify(&[4u8; 32], &node_ids(12), &config).is_err());
+
+        let mut tampered = assignment.clone();
+        let (a, b) = (tampered.leaves[0][0].clone(), tampered.leaves[1][0].clone());
+        tampered.leaves[0][0] = b;
+        tampered.leaves[1][0] = a;
+        assert!(tampered.verify(&root, &node_ids(12), &config).is_err());
+
+        // A different root reshuffles the committees.
+        assert_ne!(assign_committees(&node_ids(12), &[4u8; 32], 5, &config).unwrap().leaves, assignment.leaves);
+    }
+
+    #[test]
+    fn test_schedule_rebalances_each_epoch() {
+        let (security, mut token) = staked(12);
+        let config = CommitteeConfig { epoch_length: 10, ..CommitteeConfig::default() };
+        let mut schedule = CommitteeSchedule::new(config);
+        let candidates = node_ids(12);
+
+        let first = schedule.committees_for(0, &[1u8; 32], &candidates, &security, &token).unwrap().clone();
+        assert_eq!(first.epoch, 0);
+        assert_eq!(first.leaves.len(), 3);
+
+        // Within the epoch the committees stay put, whatever the root.
+        assert!(!schedule.needs_rebalance(9));
+        assert_eq!(schedule.committees_for(9, &[2u8; 32], &candidates, &security, &token).unwrap(), &first);
+
+        // A node that unstakes drops out at the next epoch.
+        token.unstake("node5", 50).unwrap();
+        assert!(schedule.needs_rebalance(10));
+        let second = schedule.committees_for(10, &[2u8; 32], &candidates, &security, &token).unwrap().clone();
+        assert_eq!(second.epoch, 1);
+        assert_eq!(second.leaf_of("node5"), None);
+        assert_eq!(second.leaves.len(), 2);
+        assert!(second.leaves.iter().all(|leaf| leaf.len() >= 4));
+    }
+}
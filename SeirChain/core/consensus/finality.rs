@@ -0,0 +1,216 @@
+// finality.rs
+// Finality tracking: how many HRC layers have committed over each triad
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use super::aggregation::AggregationPolicy;
+use super::commitment::{CommitmentProof, CommitmentShape, SubFractalCommitment};
+use super::committee::CommitteeAssignment;
+use crate::core::security::keys::Keyring;
+use crate::interface::explorer::triad_explorer::{TriadActivity, TriadExplorer};
+
+/// Layers that must commit over a triad before it is final.
+pub const DEFAULT_FINALITY_DEPTH: usize = 3;
+
+/// Tracks confirmation depth per triad hash. A triad gains one confirmation from the
+/// quorum that committed it and one from each parent layer whose commitment covers it,
+/// so a leaf triad under a root two levels up has three. Once a triad reaches the
+/// finality depth it is final and stays final.
+pub struct FinalityTracker {
+    depth: usize,
+    confirmations: HashMap<[u8; 32], usize>,
+    explorer: Option<Arc<TriadExplorer>>,
+}
+
+impl FinalityTracker {
+    /// Creates a tracker that treats triads as final after `depth` layers.
+    pub fn new(depth: usize) -> Self {
+        FinalityTracker {
+            depth: depth.max(1),
+            confirmations: HashMap::new(),
+            explorer: None,
+        }
+    }
+
+    /// Reports each triad that becomes final to `explorer`.
+    pub fn with_explorer(mut self, explorer: Arc<TriadExplorer>) -> Self {
+        self.explorer = Some(explorer);
+        self
+    }
+
+    /// Layers required for finality.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Number of layers that have committed over `hash`; 0 if it was never committed.
+    pub fn confirmations(&self, hash: &[u8; 32]) -> usize {
+        self.confirmations.get(hash).copied().unwrap_or(0)
+    }
+
+    /// Returns true if at least `depth` layers have committed over `hash`.
+    pub fn is_final(&self, hash: &[u8; 32]) -> bool {
+        self.confirmations(hash) >= self.depth
+    }
+
+    /// Records a commitment returned by a successful round, normally the root's, and
+    /// returns the hashes of the triads it made final. Only a commitment whose leaves are the
+    /// `committees` of the round's assignment, aggregated under `policy`, and that verifies
+    /// against `keyring` is counted. Recording a commitment again, or one that covers a triad
+    /// less deeply than an earlier one, never lowers a triad's confirmations.
+    pub fn record(
+        &mut self,
+        commitment: &SubFractalCommitment,
+        committees: &CommitteeAssignment,
+        policy: AggregationPolicy,
+        keyring: &Keyring,
+    ) -> Result<Vec<[u8; 32]>, String> {
+        self.record_with_shape(commitment, &CommitmentShape::from_committees(committees, policy), keyring)
+    }
+
+    /// `record` for a tree not formed from a committee assignment, checked against the
+    /// `expected` shape its operator configured.
+    pub fn record_with_shape(
+        &mut self,
+        commitment: &SubFractalCommitment,
+        expected: &CommitmentShape,
+        keyring: &Keyring,
+    ) -> Result<Vec<[u8; 32]>, String> {
+        commitment.verify(expected, keyring)?;
+        let mut covered = Vec::new();
+        collect_layers(commitment, &mut covered);
+
+        let mut newly_final = Vec::new();
+        for (hash, layers) in covered {
+            let entry = self.confirmations.entry(hash).or_insert(0);
+            let was_final = *entry >= self.depth;
+            *entry = (*entry).max(layers);
+            if !was_final && *entry >= self.depth {
+                newly_final.push(hash);
+                if let Some(explorer) = &self.explorer {
+                    explorer.add_activity(TriadActivity::TriadFinalized(hex::encode(hash)));
+                }
+            }
+        }
+        Ok(newly_final)
+    }
+}
+
+impl Default for FinalityTracker {
+    fn default() -> Self {
+        FinalityTracker::new(DEFAULT_FINALITY_DEPTH)
+    }
+}
+
+/// Collects every triad in the commitment with the number of layers, its own included,
+/// from it up to `commitment`. Children are listed before their parents.
+fn collect_layers(commitment: &SubFractalCommitment, out: &mut Vec<([u8; 32], usize)>) {
+    let start = out.len();
+    if let CommitmentProof::Aggregate { children, .. } = &commitment.proof {
+        for child in children.iter().flatten() {
+            collect_layers(child, out);
+        }
+    }
+    // Everything collected below sits one layer further from the top than it thought.
+    for (_, layers) in &mut out[start..] {
+        *layers += 1;
+    }
+    out.push((commitment.triad_hash, 1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::committee::{assign_committees, CommitteeConfig};
+    use crate::core::consensus::hierarchical_recursive::HierarchicalRecursiveConsensus;
+    use crate::core::security::keys::Keystore;
+    use crate::core::security::keys::node_ids;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// Commits one round over committees drawn from `n` nodes: up to nine leaves of four,
+    /// grouped three to a parent.
+    fn commit(n: usize, seed: u64) -> (CommitteeAssignment, HierarchicalRecursiveConsensus) {
+        let nodes = node_ids(n);
+        let assignment = assign_committees(&nodes, &[0u8; 32], 0, &CommitteeConfig::default()).unwrap();
+        let keys = Keystore::derive(&nodes, &[0u8; 32]);
+        let mut hrc = HierarchicalRecursiveConsensus::from_committees(&assignment, 1, keys);
+        assert!(hrc.run_consensus(&mut ChaCha8Rng::seed_from_u64(seed)));
+        (assignment, hrc)
+    }
+
+    fn record(
+        tracker: &mut FinalityTracker,
+        assignment: &CommitteeAssignment,
+        hrc: &HierarchicalRecursiveConsensus,
+    ) -> Result<Vec<[u8; 32]>, String> {
+        tracker.record(hrc.final_commitment().unwrap(), assignment, hrc.aggregation, &hrc.keyring())
+    }
+
+    #[test]
+    fn test_confirmations_follow_layers() {
+        let (assignment, hrc) = commit(36, 1);
+        assert_eq!(hrc.shape().layers(), DEFAULT_FINALITY_DEPTH);
+        let explorer = Arc::new(TriadExplorer::new(16));
+        let mut tracker = FinalityTracker::default().with_explorer(explorer.clone());
+        let newly_final = record(&mut tracker, &assignment, &hrc).unwrap();
+
+        let root = hrc.triad.hash();
+        let middle = hrc.children[0].triad.hash();
+        let leaf = hrc.children[0].children[0].triad.hash();
+        assert_eq!(tracker.confirmations(&root), 1);
+        assert_eq!(tracker.confirmations(&middle), 2);
+        assert_eq!(tracker.confirmations(&leaf), 3);
+        assert!(tracker.is_final(&leaf));
+        assert!(!tracker.is_final(&middle));
+        assert_eq!(tracker.confirmations(&[0u8; 32]), 0);
+
+        // Nine leaves became final, and each was reported once.
+        assert_eq!(newly_final.len(), 9);
+        assert!(newly_final.contains(&leaf));
+        let reported = explorer.get_recent_activities();
+        assert_eq!(reported.len(), 9);
+        assert!(reported.contains(&TriadActivity::TriadFinalized(hex::encode(leaf))));
+
+        assert!(record(&mut tracker, &assignment, &hrc).unwrap().is_empty());
+        assert_eq!(explorer.get_recent_activities().len(), 9);
+        assert_eq!(tracker.confirmations(&leaf), 3);
+    }
+
+    #[test]
+    fn test_shallow_trees_do_not_finalize() {
+        let (assignment, hrc) = commit(12, 2);
+        let mut tracker = FinalityTracker::default();
+        assert_eq!(hrc.shape().layers(), 2);
+        assert!(record(&mut tracker, &assignment, &hrc).unwrap().is_empty());
+        assert_eq!(tracker.confirmations(&hrc.children[1].triad.hash()), 2);
+
+        let mut tracker = FinalityTracker::new(2);
+        assert_eq!(record(&mut tracker, &assignment, &hrc).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_rejects_unverifiable_commitment() {
+        let (assignment, hrc) = commit(12, 3);
+        let mut forged = hrc.final_commitment().unwrap().clone();
+        forged.triad_hash = [1u8; 32];
+        let mut tracker = FinalityTracker::new(1);
+        assert!(tracker.record(&forged, &assignment, hrc.aggregation, &hrc.keyring()).is_err());
+        assert_eq!(tracker.confirmations(&[1u8; 32]), 0);
+    }
+
+    #[test]
+    fn test_rejects_commitment_for_other_committees() {
+        let (assignment, hrc) = commit(12, 4);
+        let commitment = hrc.final_commitment().unwrap();
+        let mut tracker = FinalityTracker::new(1);
+
+        // A commitment is only as good as the committees and policy it was formed under.
+        let mut reshuffled = assignment.clone();
+        reshuffled.leaves.swap(0, 1);
+        assert!(tracker.record(commitment, &reshuffled, hrc.aggregation, &hrc.keyring()).is_err());
+        assert!(tracker.record(commitment, &assignment, AggregationPolicy::Unanimous, &hrc.keyring()).is_err());
+        assert_eq!(tracker.confirmations(&hrc.triad.hash()), 0);
+        assert!(tracker.record(commitment, &assignment, hrc.aggregation, &hrc.keyring()).is_ok());
+    }
+}
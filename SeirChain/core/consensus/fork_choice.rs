@@ -0,0 +1,169 @@
+// fork_choice.rs
+// Expected-work accounting for Proof-of-Fractal and heaviest-branch fork choice
+
+use std::cmp::Ordering;
+use super::proof_of_fractal::verify_triad_pof_with;
+use super::puzzle::{FractalPuzzle, SelfSimilarPuzzle};
+use crate::core::triad_matrix::matrix::{TernaryCoordinate, TriadMatrix};
+use crate::core::triad_matrix::triad_structure::Triad;
+
+/// Expected hash attempts to solve `puzzle` at `difficulty`, rounded up to a whole attempt.
+pub fn puzzle_work<P: FractalPuzzle>(puzzle: &P, difficulty: u32) -> u128 {
+    puzzle.expected_work(difficulty).ceil() as u128
+}
+
+/// `puzzle_work` for the self-similar pattern puzzle. See `SelfSimilarPuzzle::expected_work`
+/// for the derivation.
+pub fn pattern_work(difficulty: u32) -> u128 {
+    puzzle_work(&SelfSimilarPuzzle, difficulty)
+}
+
+/// Work a Triad contributes to its branch: the expected attempts for its PoF difficulty
+/// under `puzzle`, or 0 if its PoF does not verify with `puzzle`, so a Triad cannot claim
+/// work by raising its difficulty.
+pub fn triad_work<P: FractalPuzzle>(puzzle: &P, triad: &Triad) -> u128 {
+    if verify_triad_pof_with(puzzle, triad) {
+        puzzle_work(puzzle, triad.proof_of_fractal_data.difficulty)
+    } else {
+        0
+    }
+}
+
+/// Weight of a branch of Triads ending at `tip_hash`. Ordering follows preference:
+/// the greater weight has more cumulative work, and on equal work the lower tip hash,
+/// so every node picks the same branch without further communication.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChainWeight {
+    pub work: u128,
+    pub tip_hash: [u8; 32],
+}
+
+impl ChainWeight {
+    /// Weighs a branch given from its first Triad to its tip, each Triad's work judged with
+    /// `puzzle`. Returns None for an empty branch.
+    pub fn of_branch<P: FractalPuzzle>(puzzle: &P, branch: &[Triad]) -> Option<Self> {
+        let tip = branch.last()?;
+        Some(ChainWeight {
+            work: branch.iter().map(|triad| triad_work(puzzle, triad)).sum(),
+            tip_hash: tip.hash(),
+        })
+    }
+
+    /// Weighs the branch of `matrix` from genesis down to the Triad at `coordinate`, each
+    /// Triad's work judged with `puzzle`. Returns None if the coordinate is empty.
+    pub fn in_matrix<P: FractalPuzzle>(puzzle: &P, matrix: &TriadMatrix, coordinate: &TernaryCoordinate) -> Option<Self> {
+        Some(ChainWeight {
+            work: matrix.cumulative_work(coordinate, |triad| triad_work(puzzle, triad))?,
+            tip_hash: matrix.get(coordinate)?.hash(),
+        })
+    }
+}
+
+impl Ord for ChainWeight {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.work
+            .cmp(&other.work)
+            .then_with(|| other.tip_hash.cmp(&self.tip_hash))
+    }
+}
+
+impl PartialOrd for ChainWeight {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Picks between two competing branches: `Ordering::Greater` means `a` should be followed,
+/// `Ordering::Less` means `b`. `Equal` only when both have the same work and tip.
+pub fn compare_branches(a: &ChainWeight, b: &ChainWeight) -> Ordering {
+    a.cmp(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::proof_of_fractal::{solved_triad, ProofOfFractal};
+    use crate::core::consensus::puzzle::LeadingZeroPuzzle;
+    use crate::core::consensus::solver::{NonceSource, SolverConfig};
+
+    fn solved_at(difficulty: u32, timestamp: u64) -> Triad {
+        solved_triad(Triad { timestamp, ..Triad::new() }, difficulty)
+    }
+
+    #[test]
+    fn test_pattern_work() {
+        assert_eq!(pattern_work(0), 1);
+        assert_eq!(pattern_work(1), 9);
+        assert_eq!(pattern_work(2), 4370);
+        assert!(pattern_work(8) > pattern_work(7));
+        assert_eq!(pattern_work(8), pattern_work(30));
+    }
+
+    #[test]
+    fn test_triad_work_requires_valid_pof() {
+        let triad = solved_at(2, 1);
+        assert_eq!(triad_work(&SelfSimilarPuzzle, &triad), pattern_work(2));
+
+        let mut inflated = triad.to_record().into_triad();
+        inflated.proof_of_fractal_data.difficulty = 8;
+        assert_eq!(triad_work(&SelfSimilarPuzzle, &inflated), 0);
+        assert_eq!(triad_work(&SelfSimilarPuzzle, &Triad::new()), 0);
+    }
+
+    #[test]
+    fn test_triad_work_follows_the_puzzle() {
+        // A Triad solved for the leading-zero puzzle weighs that puzzle's expected work, and
+        // nothing under the self-similar one it was not solved for.
+        let mut triad = Triad::new();
+        let config = SolverConfig::new(NonceSource::Sequential { start: 0, end: u64::MAX });
+        assert!(ProofOfFractal::with_puzzle(LeadingZeroPuzzle, 6).solve_triad(&mut triad, &config).is_solved());
+        assert_eq!(triad_work(&LeadingZeroPuzzle, &triad), 64);
+        assert_eq!(puzzle_work(&LeadingZeroPuzzle, 6), 64);
+        assert_eq!(ChainWeight::of_branch(&LeadingZeroPuzzle, &[triad.clone()]).map(|w| w.work), Some(64));
+        assert_eq!(triad_work(&SelfSimilarPuzzle, &triad), 0);
+    }
+
+    #[test]
+    fn test_heaviest_branch_wins() {
+        let light = vec![solved_at(1, 1), solved_at(1, 2), solved_at(1, 3)];
+        let heavy = vec![solved_at(2, 4)];
+        let light_weight = ChainWeight::of_branch(&SelfSimilarPuzzle, &light).unwrap();
+        let heavy_weight = ChainWeight::of_branch(&SelfSimilarPuzzle, &heavy).unwrap();
+        // The longer branch loses: work counts, not length.
+        assert_eq!(compare_branches(&heavy_weight, &light_weight), Ordering::Greater);
+        assert_eq!(compare_branches(&light_weight, &heavy_weight), Ordering::Less);
+        assert_eq!(ChainWeight::of_branch(&SelfSimilarPuzzle, &[]), None);
+    }
+
+    #[test]
+    fn test_matrix_branches_weigh_their_work() {
+        let mut matrix = TriadMatrix::new(solved_triad(Triad::genesis(None), 1)).unwrap();
+        for (i, difficulty) in [2, 1, 1, 2].into_iter().enumerate() {
+            let triad = Triad { parent_hash: matrix.next_parent_hash(), timestamp: 1_000 + i as u64, ..Triad::new() };
+            matrix.insert(solved_triad(triad, difficulty)).unwrap();
+        }
+        // One unsolved Triad adds no work.
+        let mut unsolved = Triad::new();
+        unsolved.parent_hash = matrix.next_parent_hash();
+        matrix.insert(unsolved).unwrap();
+
+        let weigh = |c: &str| ChainWeight::in_matrix(&SelfSimilarPuzzle, &matrix, &c.parse().unwrap()).unwrap();
+        assert_eq!(weigh("0").work, pattern_work(1) + pattern_work(2));
+        assert_eq!(weigh("2").work, pattern_work(1) + pattern_work(1));
+        assert_eq!(weigh("0.0").work, pattern_work(1) + pattern_work(2) + pattern_work(2));
+        assert_eq!(weigh("0.1").work, weigh("0").work);
+        assert_eq!(matrix.total_work(|t| triad_work(&SelfSimilarPuzzle, t)), 3 * pattern_work(1) + 2 * pattern_work(2));
+        assert!(ChainWeight::in_matrix(&SelfSimilarPuzzle, &matrix, &"0.2".parse().unwrap()).is_none());
+        assert!(weigh("0.0") > weigh("2"));
+    }
+
+    #[test]
+    fn test_tie_breaks_on_lower_hash() {
+        let a = ChainWeight { work: 10, tip_hash: [1u8; 32] };
+        let b = ChainWeight { work: 10, tip_hash: [2u8; 32] };
+        assert_eq!(compare_branches(&a, &b), Ordering::Greater);
+        assert_eq!(compare_branches(&b, &a), Ordering::Less);
+        assert_eq!(compare_branches(&a, &a), Ordering::Equal);
+        assert_eq!([b, a].into_iter().max(), Some(a));
+    }
+}
@@ -1,35 +1,113 @@
 use std::collections::HashMap;
-use crate::core::consensus::proof_of_fractal::ProofOfFractal;
-use crate::core::security::redundant_paths::RedundantPathSecurity;
+use std::time::Instant;
+use rand::Rng;
+use crate::core::consensus::aggregation::AggregationPolicy;
+use crate::core::consensus::commitment::{CommitmentShape, SubFractalCommitment};
+use crate::core::consensus::metrics::{ConsensusReport, CountingTransport, FailureReason, SubFractalMetrics};
+use crate::core::consensus::committee::{group_into_tree, split_balanced, CommitteeAssignment};
+use crate::core::consensus::parallel_miner::ParallelMiner;
+use crate::core::consensus::pbft::{
+    drive, round_context, ConsensusTransport, LocalTransport, PbftReplica, Phase, RecordingTransport,
+};
+use crate::core::consensus::proof_of_fractal::{verify_triad_pof_at, ProofOfFractal};
+use crate::core::consensus::puzzle::{FractalPuzzle, SelfSimilarPuzzle};
+use crate::core::consensus::solver::{NonceSource, SolverConfig};
+use crate::core::triad_matrix::evidence::Evidence;
+use crate::core::security::keys::{Keyring, Keystore};
+use crate::core::security::node_scoring::NodeEvent;
+use crate::core::security::redundant_paths::{PathDelivery, RedundantPathSecurity};
 use crate::core::triad_matrix::triad_structure::Triad;
+use crate::network::routing::multi_path_fractal::MultiPathFractalRouting;
+
+/// Logical ticks a leaf round may take, view changes included, before it is abandoned.
+/// Enough for several view changes at the default view timeout.
+const LEAF_MAX_TICKS: u64 = 400;
+
+/// Seed `HierarchicalRecursiveConsensus::simulated` derives its nodes' keys from.
+const SIMULATION_SEED: [u8; 32] = [0u8; 32];
 
 /// HierarchicalRecursiveConsensus implements recursive PBFT-like consensus for SeirChain.
-pub struct HierarchicalRecursiveConsensus {
+/// It is generic over the PoF puzzle variant so variants can be compared side by side.
+pub struct HierarchicalRecursiveConsensus<P: FractalPuzzle = SelfSimilarPuzzle> {
     pub nodes: Vec<String>, // List of node IDs in the sub-fractal
-    pub state: HashMap<String, String>, // State per node (e.g., votes, messages)
+    pub state: HashMap<String, Phase>, // PBFT phase each node reached in the last leaf round
     pub fault_tolerance: usize, // Number of tolerated faulty nodes
-    pub proof: ProofOfFractal, // Proof-of-Fractal puzzle instance
+    pub proof: ProofOfFractal<P>, // Proof-of-Fractal puzzle instance
     pub security: RedundantPathSecurity, // Security module instance
-    pub children: Vec<HierarchicalRecursiveConsensus>, // Child sub-fractals
+    pub children: Vec<HierarchicalRecursiveConsensus<P>>, // Child sub-fractals
     pub triad: Triad, // TRIAD matrix for routing
+    pub mining_threads: usize, // Worker threads used to solve the PoF puzzle
+    pub commitment: Option<SubFractalCommitment>, // What the last successful round committed to
+    pub aggregation: AggregationPolicy, // How many children must commit for this sub-fractal to commit
+    pub absent_children: Vec<usize>, // Child slots that failed to commit in the last round
+    pub keys: Keystore, // Signing keys of the nodes this instance runs
+    pub admitted: Option<Keyring>, // Keys of the admitted nodes, if admission control is in use
+    pub metrics: SubFractalMetrics, // What happened in the last round
+    pub evidence: Vec<Evidence>, // Misbehaviour seen in leaf rounds, put on record in the next triad
+    pub round: u64, // Rounds started so far; with the nodes it names each leaf round's PBFT instance
 }
 
 impl HierarchicalRecursiveConsensus {
-    /// Creates a new HRC instance with given nodes and fault tolerance.
-    pub fn new(nodes: Vec<String>, fault_tolerance: usize, difficulty: u32, depth: u32) -> Self {
-        let proof = ProofOfFractal::new(difficulty);
-        proof.adjust_difficulty(1000);
-
-        let children = if depth > 0 {
-            let chunk_size = std::cmp::max(1, nodes.len() / 3);
-            nodes.chunks(chunk_size).map(|chunk| {
-                HierarchicalRecursiveConsensus::new(chunk.to_vec(), fault_tolerance, difficulty, depth - 1)
+    /// Creates a new HRC instance with given nodes and fault tolerance, whose nodes sign
+    /// with `keys`.
+    pub fn new(nodes: Vec<String>, fault_tolerance: usize, difficulty: u32, depth: u32, keys: Keystore) -> Self {
+        HierarchicalRecursiveConsensus::with_puzzle(nodes, fault_tolerance, difficulty, depth, SelfSimilarPuzzle, keys)
+    }
+
+    /// Creates an HRC instance whose leaves are the committees of `assignment`.
+    pub fn from_committees(assignment: &CommitteeAssignment, difficulty: u32, keys: Keystore) -> Self {
+        HierarchicalRecursiveConsensus::with_committees(assignment, difficulty, SelfSimilarPuzzle, keys)
+    }
+
+    /// Creates an HRC instance for simulations, whose nodes sign with keys derived from a
+    /// fixed seed. Anyone can recompute those keys, so nothing it commits proves anything
+    /// outside the simulation.
+    pub fn simulated(nodes: Vec<String>, fault_tolerance: usize, difficulty: u32, depth: u32) -> Self {
+        let keys = Keystore::derive(&nodes, &SIMULATION_SEED);
+        HierarchicalRecursiveConsensus::new(nodes, fault_tolerance, difficulty, depth, keys)
+    }
+}
+
+impl<P: FractalPuzzle + Clone + 'static> HierarchicalRecursiveConsensus<P> {
+    /// Creates a new HRC instance whose sub-fractals all solve the given puzzle variant.
+    pub fn with_puzzle(nodes: Vec<String>, fault_tolerance: usize, difficulty: u32, depth: u32, puzzle: P, keys: Keystore) -> Self {
+        // At most three children, one per child slot of the Triad, and only as many as can
+        // each hold 3f+1 nodes; a sub-fractal too small to split stays a leaf.
+        let parts = (nodes.len() / (3 * fault_tolerance + 1)).min(3);
+        let children = if depth > 0 && parts > 1 {
+            split_balanced(&nodes, parts).into_iter().map(|chunk| {
+                HierarchicalRecursiveConsensus::with_puzzle(chunk, fault_tolerance, difficulty, depth - 1, puzzle.clone(), keys.clone())
             }).collect()
         } else {
             Vec::new()
         };
+        HierarchicalRecursiveConsensus::assemble(nodes, children, fault_tolerance, difficulty, puzzle, keys)
+    }
+
+    /// Creates an HRC instance whose leaves are the committees of `assignment`. Leaves are
+    /// grouped three to a parent, level by level, until a single root remains.
+    pub fn with_committees(assignment: &CommitteeAssignment, difficulty: u32, puzzle: P, keys: Keystore) -> Self {
+        let f = assignment.fault_tolerance;
+        let leaves = assignment
+            .leaves
+            .iter()
+            .map(|leaf| HierarchicalRecursiveConsensus::assemble(leaf.clone(), Vec::new(), f, difficulty, puzzle.clone(), keys.clone()))
+            .collect();
+        let root = group_into_tree(leaves, |children: Vec<Self>| {
+            let nodes = children.iter().flat_map(|child| child.nodes.clone()).collect();
+            HierarchicalRecursiveConsensus::assemble(nodes, children, f, difficulty, puzzle.clone(), keys.clone())
+        });
+        root.unwrap_or_else(|| HierarchicalRecursiveConsensus::assemble(Vec::new(), Vec::new(), f, difficulty, puzzle, keys))
+    }
+
+    fn assemble(nodes: Vec<String>, children: Vec<Self>, fault_tolerance: usize, difficulty: u32, puzzle: P, keys: Keystore) -> Self {
+        let proof = ProofOfFractal::with_puzzle(puzzle, difficulty);
 
-        let triad = Triad::new();
+        let mut triad = Triad::new();
+        triad.timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
 
         HierarchicalRecursiveConsensus {
             nodes,
@@ -39,85 +117,821 @@ impl HierarchicalRecursiveConsensus {
             security: RedundantPathSecurity::new(),
             children,
             triad,
+            mining_threads: 1,
+            commitment: None,
+            aggregation: AggregationPolicy::default(),
+            absent_children: Vec::new(),
+            keys,
+            admitted: None,
+            metrics: SubFractalMetrics::default(),
+            evidence: Vec::new(),
+            round: 0,
         }
     }
 
+    /// Sets the number of PoF mining threads for this sub-fractal and all of its children.
+    pub fn set_mining_threads(&mut self, threads: usize) {
+        self.mining_threads = threads.max(1);
+        for child in &mut self.children {
+            child.set_mining_threads(threads);
+        }
+    }
 
-    /// Runs the recursive consensus algorithm with simulated network communication and fault handling.
-    pub fn run_consensus<R: rand::Rng>(&mut self, rng: &mut R) -> bool {
-        // If this is not a leaf node, run consensus on children first.
-        if !self.children.is_empty() {
-            let mut child_results = Vec::new();
-            for child in &mut self.children {
-                child_results.push(child.run_consensus(rng));
-            }
-            // Aggregate results from children. For simplicity, we require all children to agree.
-            if child_results.iter().all(|&r| r) {
-                // self.triad.update_from_children(&self.children.iter().map(|c| &c.triad).collect::<Vec<_>>());
-                return true;
+    /// Sets the PoF difficulty for this sub-fractal and all of its children, e.g. after
+    /// a retarget.
+    pub fn set_difficulty(&mut self, difficulty: u32) {
+        *self.proof.difficulty.lock().unwrap() = difficulty;
+        for child in &mut self.children {
+            child.set_difficulty(difficulty);
+        }
+    }
+
+
+    /// Sets the aggregation policy for this sub-fractal and all of its children.
+    pub fn set_aggregation_policy(&mut self, policy: AggregationPolicy) {
+        self.aggregation = policy;
+        for child in &mut self.children {
+            child.set_aggregation_policy(policy);
+        }
+    }
+
+    /// Installs the nodes' signing keys for this sub-fractal and all of its children.
+    pub fn set_keystore(&mut self, keys: Keystore) {
+        for child in &mut self.children {
+            child.set_keystore(keys.clone());
+        }
+        self.keys = keys;
+    }
+
+    /// Checks this sub-fractal's and its children's votes and commitments against
+    /// `keyring`, typically `AdmissionControl::keyring`, so only admitted nodes count.
+    pub fn set_admitted(&mut self, keyring: Keyring) {
+        for child in &mut self.children {
+            child.set_admitted(keyring.clone());
+        }
+        self.admitted = Some(keyring);
+    }
+
+    /// Public keys that this sub-fractal's votes and commitments are checked against: the
+    /// admitted keys once set, otherwise those of the keystore.
+    pub fn keyring(&self) -> Keyring {
+        self.admitted.clone().unwrap_or_else(|| self.keys.keyring())
+    }
+
+    /// The shape this sub-fractal's commitments must have: its own tree of nodes, fault
+    /// tolerances and aggregation policies.
+    pub fn shape(&self) -> CommitmentShape {
+        if self.children.is_empty() {
+            CommitmentShape::Leaf { nodes: self.nodes.clone(), fault_tolerance: self.fault_tolerance }
+        } else {
+            let children = self.children.iter().map(|child| child.shape()).collect();
+            CommitmentShape::Parent { children, policy: self.aggregation }
+        }
+    }
+
+    /// Runs the recursive consensus algorithm, with each leaf's replicas exchanging
+    /// messages in-process.
+    pub fn run_consensus<R: Rng>(&mut self, rng: &mut R) -> bool {
+        self.run_consensus_with(rng, &mut |nodes: &[String]| LocalTransport::new(nodes))
+    }
+
+    /// Runs the recursive consensus algorithm. `make_transport` is called once per leaf
+    /// with that leaf's nodes and carries its PBFT messages. What happened is left in
+    /// `metrics`.
+    pub fn run_consensus_with<R, T, F>(&mut self, rng: &mut R, make_transport: &mut F) -> bool
+    where
+        R: Rng,
+        T: ConsensusTransport,
+        F: FnMut(&[String]) -> T,
+    {
+        let start = Instant::now();
+        // Evidence carried by a Triad that committed is on record; only a failed round's
+        // evidence is carried into the next attempt.
+        if self.metrics.committed {
+            self.triad.evidence.clear();
+        }
+        self.commitment = None;
+        self.absent_children.clear();
+        // Every attempt is a fresh PBFT instance whose views and sequences start over.
+        self.round += 1;
+        self.metrics = SubFractalMetrics { nodes: self.nodes.len(), ..SubFractalMetrics::default() };
+
+        let result = if self.children.is_empty() {
+            self.run_leaf(rng, make_transport)
+        } else {
+            self.run_parent(rng, make_transport)
+        };
+        self.metrics.latency_micros = start.elapsed().as_micros() as u64;
+        self.metrics.committed = result.is_ok();
+        self.metrics.failure = result.err();
+        self.metrics.committed
+    }
+
+    fn run_parent<R, T, F>(&mut self, rng: &mut R, make_transport: &mut F) -> Result<(), FailureReason>
+    where
+        R: Rng,
+        T: ConsensusTransport,
+        F: FnMut(&[String]) -> T,
+    {
+        // Run consensus on children first.
+        let mut child_results = Vec::new();
+        for child in &mut self.children {
+            child_results.push(child.run_consensus_with(rng, make_transport));
+        }
+        self.metrics.children = self.children.iter().map(|child| child.metrics.clone()).collect();
+
+        // Aggregate results from children: the policy decides whether the children that
+        // committed are enough to stand in for those that did not.
+        let weights: Vec<usize> = self.children.iter().map(|c| c.nodes.len()).collect();
+        self.absent_children = (0..child_results.len()).filter(|&slot| !child_results[slot]).collect();
+        if !self.aggregation.is_satisfied(&child_results, &weights) {
+            return Err(FailureReason::ChildrenMissing {
+                committed: child_results.len() - self.absent_children.len(),
+                children: child_results.len(),
+            });
+        }
+        // Propagate the committed children's Triads and certificates up into this Triad,
+        // so its hash commits to the whole subtree. Absent children leave their slot empty.
+        let mut child_commitments = Vec::with_capacity(self.children.len());
+        for (slot, child) in self.children.iter().enumerate() {
+            self.triad.child_references[slot] = if child_results[slot] {
+                Some(Box::new(child.triad.clone()))
             } else {
-                return false;
+                None
+            };
+            child_commitments.push(child.commitment.clone());
+        }
+        self.commitment = Some(SubFractalCommitment::aggregate(
+            &self.triad,
+            child_commitments,
+            weights,
+            self.aggregation,
+        ));
+        Ok(())
+    }
+
+    fn run_leaf<R, T, F>(&mut self, rng: &mut R, make_transport: &mut F) -> Result<(), FailureReason>
+    where
+        R: Rng,
+        T: ConsensusTransport,
+        F: FnMut(&[String]) -> T,
+    {
+        // The proposal is this sub-fractal's triad, and the PoF is solved over its header
+        // so the solution commits to what is agreed.
+        // The nonce search is seeded from `rng` so a seeded run is reproducible.
+        // Evidence from earlier rounds goes into the triad first, so the PoF covers it too.
+        for evidence in self.evidence.drain(..) {
+            if !self.triad.evidence.contains(&evidence) {
+                self.triad.evidence.push(evidence);
             }
         }
+        let config = SolverConfig {
+            source: NonceSource::Seeded(rng.gen()),
+            ..self.proof.default_solver_config()
+        };
+        let report = ParallelMiner::new(self.mining_threads).mine_triad(&self.proof, &mut self.triad, &config);
+        self.metrics.pof_solve_micros = report.elapsed.as_micros() as u64;
+        self.metrics.pof_attempts = report.total_attempts;
+        if !report.outcome.is_solved() {
+            return Err(FailureReason::PofUnsolved);
+        }
 
-        // Leaf node consensus (PBFT-like simulation)
-        let proposal = "block_data";
+        let keyring = self.keyring();
+        let difficulty = *self.proof.difficulty.lock().unwrap();
+        let context = round_context(&self.nodes, self.round);
+        let mut replicas = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let puzzle = self.proof.puzzle.clone();
+            let key = self.keys.key(node).cloned().ok_or(FailureReason::InvalidGroup)?;
+            let replica = PbftReplica::new(node, self.nodes.clone(), self.fault_tolerance, key, keyring.clone())
+                .map_err(|_| FailureReason::InvalidGroup)?;
+            replicas.push(replica.with_context(context).with_validator(Box::new(move |record| {
+                verify_triad_pof_at(&puzzle, difficulty, &record.clone().into_triad())
+            })));
+        }
 
-        if !self.proof.solve_puzzle(proposal.as_bytes()) {
-            return false;
+        // Every replica is handed the proposal so that whoever leads after a view change
+        // can propose it if the current leader fails to.
+        let mut transport = RecordingTransport::new(CountingTransport::new(make_transport(&self.nodes)));
+        let record = self.triad.to_record();
+        let digest = record.hash();
+        for replica in &mut replicas {
+            for message in replica.submit(record.clone()) {
+                transport.broadcast(replica.id(), message);
+            }
         }
+        self.metrics.ticks = drive(&mut replicas, &mut transport, LEAF_MAX_TICKS);
+        self.metrics.messages = transport.inner().broadcasts();
 
-        for node in &self.nodes {
-            let vote = if rng.gen::<f32>() < 0.9 { "vote" } else { "fault" };
-            self.state.insert(node.clone(), vote.to_string());
-            if vote == "vote" {
-                self.security.add_path(node);
+        // Only evidence that stands up on its own goes on record: a proposal the validator
+        // rejected for anything but its PoF is no offence.
+        for evidence in replicas.iter().flat_map(|replica| replica.evidence()) {
+            let offence = evidence.offence();
+            let known = self.triad.evidence.iter().chain(&self.evidence).any(|e| e.offence() == offence);
+            if !known && evidence.validate_with(&self.proof.puzzle, &keyring).is_ok() {
+                self.evidence.push(evidence.clone());
+            }
+        }
+
+        self.state.clear();
+        for replica in &replicas {
+            let phase = replica.proposal_phase(&digest);
+            self.state.insert(replica.id().to_string(), phase);
+            if phase == Phase::Committed {
+                self.security.add_path(replica.id());
             } else {
-                self.security.remove_path(node);
+                self.security.remove_path(replica.id());
             }
         }
 
-        let votes = self.state.values().filter(|&v| v == "vote").count();
-        let faults = self.state.values().filter(|&v| v == "fault").count();
+        let committed = self.state.values().filter(|&&p| p == Phase::Committed).count();
+        let faults = self.nodes.len() - committed;
+        self.metrics.votes = committed;
+        self.metrics.faults = faults;
 
-        if faults > self.fault_tolerance || !self.security.validate_paths() {
-            return false;
+        if faults > self.fault_tolerance {
+            return Err(FailureReason::TooManyFaults { faults, tolerated: self.fault_tolerance });
+        }
+        // With at most f faults among at least 3f+1 nodes, 2f+1 committed.
+        if !self.validate_subfractal() {
+            return Err(FailureReason::InvalidSubfractal);
         }
 
-        let quorum = 2 * self.fault_tolerance + 1;
-        votes >= quorum && self.validate_subfractal()
+        // A certificate held by a replica other than the proposer, so the Triad's hash had to
+        // travel from one to the other.
+        let (holder, certificate) = replicas
+            .iter()
+            .flat_map(|replica| replica.committed().iter().map(move |c| (replica, c)))
+            .find(|(replica, c)| c.certificate.digest == digest && replica.leader_of(c.certificate.view) != replica.id())
+            .map(|(replica, c)| (replica.id().to_string(), c.certificate.clone()))
+            .ok_or(FailureReason::MissingCertificate)?;
+        let proposer = replicas[0].leader_of(certificate.view).to_string();
+
+        // The proposal reached the holder directly in the proposer's own messages, and through
+        // every other replica that heard it from the proposer and told the holder what it
+        // heard. Each path carries whatever digests the holder actually received over it.
+        let (view, sequence) = (certificate.view, certificate.sequence);
+        let mut deliveries = Vec::new();
+        for relay in &self.nodes {
+            let path = if *relay == proposer {
+                vec![proposer.clone(), holder.clone()]
+            } else if *relay != holder && !transport.received_from(relay, &proposer, view, sequence).is_empty() {
+                vec![proposer.clone(), relay.clone(), holder.clone()]
+            } else {
+                continue;
+            };
+            let mut heard = transport.received_from(&holder, relay, view, sequence);
+            heard.sort();
+            heard.dedup();
+            deliveries.extend(heard.into_iter().map(|triad_hash| PathDelivery { path: path.clone(), triad_hash }));
+        }
+        let links = self.links(&transport);
+        let validation = self.security.validate_triad(&links, &proposer, &holder, &digest, &deliveries);
+        if !validation.is_accepted() {
+            return Err(FailureReason::TooFewPaths { agreeing: validation.agreeing.len(), required: validation.required });
+        }
+
+        // Credit the nodes whose commits are in the certificate, and the leader whose
+        // proposal, PoF solution included, committed.
+        let now = self.triad.timestamp;
+        for node in certificate.signed_by(&self.nodes) {
+            self.security.record_event(node, NodeEvent::ValidVote, now);
+        }
+        self.security.record_event(&proposer, NodeEvent::PofSolved, now);
+        self.commitment = Some(SubFractalCommitment::leaf(
+            &self.triad,
+            self.nodes.clone(),
+            self.fault_tolerance,
+            certificate,
+        ));
+        Ok(())
+    }
+
+    /// The links between this sub-fractal's nodes: every pair `transport` currently connects.
+    pub fn links<T: ConsensusTransport>(&self, transport: &T) -> MultiPathFractalRouting {
+        let mut routing = MultiPathFractalRouting::new();
+        for (i, a) in self.nodes.iter().enumerate() {
+            for b in &self.nodes[i + 1..] {
+                if transport.connected(a, b) {
+                    routing.add_link(a, b);
+                }
+            }
+        }
+        routing
     }
 
-    /// Validates the sub-fractal consensus state.
+    /// Metrics of the last round across the whole tree, ready to serialize.
+    pub fn report(&self) -> ConsensusReport {
+        ConsensusReport::from_metrics(&self.metrics)
+    }
+
+    /// The single commitment the last successful round produced at this level; at the
+    /// root it covers every sub-fractal. None if the round failed or has not run.
+    pub fn final_commitment(&self) -> Option<&SubFractalCommitment> {
+        self.commitment.as_ref()
+    }
+
+    /// Validates the sub-fractal consensus state. A leaf's triad must carry a valid PoF at
+    /// this sub-fractal's difficulty; a non-leaf is valid when enough of its children are to
+    /// satisfy its aggregation policy.
     pub fn validate_subfractal(&self) -> bool {
-        self.nodes.len() >= 3 * self.fault_tolerance + 1 && self.proof.verify_solution("block_data".as_bytes())
+        if self.nodes.len() < 3 * self.fault_tolerance + 1 {
+            return false;
+        }
+        if self.children.is_empty() {
+            verify_triad_pof_at(&self.proof.puzzle, *self.proof.difficulty.lock().unwrap(), &self.triad)
+        } else {
+            let valid: Vec<bool> = self.children.iter().map(|child| child.validate_subfractal()).collect();
+            let weights: Vec<usize> = self.children.iter().map(|child| child.nodes.len()).collect();
+            self.aggregation.is_satisfied(&valid, &weights)
+                && self.commitment.as_ref().is_none_or(|c| c.verify(&self.shape(), &self.keyring()).is_ok())
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::consensus::commitment::CommitmentProof;
+    use crate::core::consensus::pbft::{ConsensusMessage, QuorumCertificate};
+    use crate::core::triad_matrix::evidence::{Vote, VoteKind};
+    use crate::core::security::keys::node_ids;
+    use crate::core::consensus::proof_of_fractal::verify_triad_pof_with;
+    use crate::core::consensus::puzzle::{pof_hash, LeadingZeroPuzzle};
+    use ed25519_dalek::SigningKey;
+    use std::collections::{BTreeMap, HashSet};
     use rand::prelude::*;
     use rand_chacha::ChaCha8Rng;
 
     #[test]
     fn test_hierarchical_recursive_consensus() {
-        let nodes = vec![
-            "node1".to_string(), "node2".to_string(), "node3".to_string(), "node4".to_string(),
-            "node5".to_string(), "node6".to_string(), "node7".to_string(), "node8".to_string(),
-            "node9".to_string(), "node10".to_string(), "node11".to_string(), "node12".to_string(),
-        ];
-        let mut hrc = HierarchicalRecursiveConsensus::new(nodes, 1, 4, 1);
+        let nodes = node_ids(12);
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(nodes, 1, 2, 1);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert!(hrc.run_consensus(&mut rng));
+    }
+
+    #[test]
+    fn test_commitment_propagates_to_root() {
+        let nodes = node_ids(12);
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(nodes, 1, 1, 1);
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        assert!(hrc.run_consensus(&mut rng));
+
+        let root = hrc.final_commitment().expect("root commitment").clone();
+        assert_eq!(root.verify(&hrc.shape(), &hrc.keyring()), Ok(()));
+        // The leaf certificates only check out against the keys that signed them.
+        let strangers = Keystore::derive(&hrc.nodes, &[1u8; 32]).keyring();
+        assert!(root.verify(&hrc.shape(), &strangers).is_err());
+        assert_eq!(root.triad_hash, hrc.triad.hash());
+        let children = match &root.proof {
+            CommitmentProof::Aggregate { children, .. } => children,
+            other => panic!("expected an aggregate, got {:?}", other),
+        };
+        assert_eq!(children.len(), 3);
+        for (slot, child) in hrc.children.iter().enumerate() {
+            let committed = children[slot].as_ref().unwrap();
+            assert_eq!(committed.triad_hash, child.triad.hash());
+            assert_eq!(hrc.triad.child_hashes()[slot], Some(child.triad.hash()));
+            assert!(matches!(committed.proof, CommitmentProof::Quorum { .. }));
+        }
+
+        // Tampering with any leaf breaks the root commitment.
+        let mut forged = root.clone();
+        if let CommitmentProof::Aggregate { children, .. } = &mut forged.proof {
+            children[1].as_mut().unwrap().triad_hash[0] ^= 1;
+        }
+        assert!(forged.verify(&hrc.shape(), &hrc.keyring()).is_err());
+    }
+
+    #[test]
+    fn test_commitment_is_checked_against_expected_shape() {
+        let nodes = node_ids(12);
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(nodes, 1, 1, 1);
+        assert!(hrc.run_consensus(&mut ChaCha8Rng::seed_from_u64(2)));
+        let root = hrc.final_commitment().unwrap().clone();
+        let shape = hrc.shape();
+        let keyring = hrc.keyring();
+        let tamper = |change: &dyn Fn(&mut CommitmentProof)| {
+            let mut forged = root.clone();
+            change(&mut forged.proof);
+            forged.verify(&shape, &keyring)
+        };
+
+        // The proof cannot pick a looser policy or reweigh its children.
+        assert!(tamper(&|proof| {
+            if let CommitmentProof::Aggregate { policy, .. } = proof {
+                *policy = AggregationPolicy::Threshold { required: 1 };
+            }
+        })
+        .is_err());
+        assert!(tamper(&|proof| {
+            if let CommitmentProof::Aggregate { child_weights, .. } = proof {
+                child_weights[0] = 100;
+            }
+        })
+        .is_err());
+        // Nor can a leaf name its own committee or fault tolerance.
+        assert!(tamper(&|proof| {
+            if let CommitmentProof::Aggregate { children, .. } = proof {
+                if let Some(CommitmentProof::Quorum { nodes, .. }) = children[0].as_mut().map(|c| &mut c.proof) {
+                    nodes.swap(0, 1);
+                }
+            }
+        })
+        .is_err());
+        let leaf = hrc.children[0].final_commitment().unwrap();
+        let CommitmentProof::Quorum { certificate, .. } = &leaf.proof else { panic!("expected a quorum") };
+        let key = hrc.keys.key("node1").unwrap();
+        let (context, view, sequence) = (certificate.context, certificate.view, certificate.sequence);
+        let commit = Vote::sign(VoteKind::Commit, context, view, sequence, leaf.triad_hash, "node1", key);
+        let alone = vec!["node1".to_string()];
+        let certificate = QuorumCertificate::from_commits(context, view, sequence, leaf.triad_hash, &alone, [&commit]);
+        let mut solo = leaf.clone();
+        solo.proof = CommitmentProof::Quorum { nodes: alone.clone(), fault_tolerance: 0, certificate };
+        let solo_shape = CommitmentShape::Leaf { nodes: alone, fault_tolerance: 0 };
+        assert!(solo.verify(&solo_shape, &keyring).is_err_and(|e| e.contains("at least one fault")));
+        assert!(tamper(&|proof| {
+            if let CommitmentProof::Aggregate { children, .. } = proof {
+                children[0] = Some(solo.clone());
+            }
+        })
+        .is_err());
+
+        // A leaf where a parent is expected, or the other way round, is rejected.
+        assert!(leaf.verify(&shape, &keyring).is_err());
+        assert!(root.verify(&hrc.children[0].shape(), &keyring).is_err());
+        assert_eq!(root.verify(&shape, &keyring), Ok(()));
+    }
+
+    #[test]
+    fn test_only_admitted_nodes_take_part() {
+        use crate::core::security::admission::{AdmissionConfig, AdmissionControl};
+        use crate::interface::economics::waclanium_token::WaclaniumToken;
+
+        let nodes = node_ids(4);
+        let keys = Keystore::generate(&nodes, &mut rand::rngs::OsRng);
+        let token = WaclaniumToken::new(0, 1_000_000, 0);
+        let mut admission = AdmissionControl::new(AdmissionConfig { work_difficulty: 4, ..AdmissionConfig::default() });
+        admission.admit_keystore(&keys, "10.0.0.1".parse().unwrap(), &token, 0).unwrap();
+
+        let mut hrc = HierarchicalRecursiveConsensus::new(nodes, 1, 1, 0, keys);
+        hrc.set_admitted(admission.keyring());
         let mut rng = ChaCha8Rng::seed_from_u64(1);
         assert!(hrc.run_consensus(&mut rng));
+        assert_eq!(hrc.final_commitment().map(|c| c.verify(&hrc.shape(), &admission.keyring())), Some(Ok(())));
+
+        // A group holding a node whose admission was withdrawn does not run.
+        admission.revoke("node4");
+        hrc.set_admitted(admission.keyring());
+        assert!(!hrc.run_consensus(&mut rng));
+        assert_eq!(hrc.metrics.failure, Some(FailureReason::InvalidGroup));
+    }
+
+    #[test]
+    fn test_zero_difficulty_triad_is_invalid() {
+        let nodes = node_ids(4);
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(nodes, 1, 1, 0);
+        assert!(hrc.run_consensus(&mut ChaCha8Rng::seed_from_u64(1)));
+        assert!(hrc.validate_subfractal());
+
+        // Re-solved at difficulty 0 the PoF is self-consistent, but not at the required difficulty.
+        hrc.triad.proof_of_fractal_data.difficulty = 0;
+        let nonce = hrc.triad.proof_of_fractal_data.nonce;
+        hrc.triad.proof_of_fractal_data.hash = pof_hash(&hrc.triad.pof_preimage(), nonce);
+        assert!(verify_triad_pof_with(&hrc.proof.puzzle, &hrc.triad));
+        assert!(!hrc.validate_subfractal());
     }
 
     #[test]
     fn test_leaf_consensus() {
-        let nodes = vec!["node1".to_string(), "node2".to_string(), "node3".to_string(), "node4".to_string()];
-        let mut hrc = HierarchicalRecursiveConsensus::new(nodes, 1, 4, 0);
+        let nodes = node_ids(4);
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(nodes, 1, 2, 0);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert!(hrc.run_consensus(&mut rng));
+        assert!(hrc.validate_subfractal());
+        assert!(hrc.state.values().all(|&phase| phase == Phase::Committed));
+        // The leader of view 0 proposed, and at least a quorum's commits were certified.
+        let stats = hrc.security.scoring.stats("node1").unwrap();
+        assert_eq!(stats.pof_solves, 1);
+        let voters = hrc.nodes.iter().filter(|n| hrc.security.scoring.stats(n).is_some_and(|s| s.valid_votes == 1)).count();
+        assert!(voters >= 3);
+        assert_eq!(hrc.final_commitment().map(|c| c.verify(&hrc.shape(), &hrc.keyring())), Some(Ok(())));
+    }
+
+    #[test]
+    fn test_honest_rounds_give_no_double_vote_evidence() {
+        use crate::core::security::evidence::Slasher;
+        use crate::interface::economics::waclanium_token::WaclaniumToken;
+
+        // Each round's replicas start over at view 0 and sequence 1 and commit a different
+        // Triad, so without the round context their commits would look like double votes.
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(node_ids(4), 1, 1, 0);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut commits: Vec<BTreeMap<String, Vote>> = Vec::new();
+        for _ in 0..2 {
+            assert!(hrc.run_consensus(&mut rng));
+            let leaf = hrc.final_commitment().unwrap();
+            let CommitmentProof::Quorum { nodes, certificate, .. } = &leaf.proof else { panic!("expected a quorum") };
+            assert_eq!((certificate.view, certificate.sequence), (0, 1));
+            let signed = certificate.signed_by(nodes).into_iter().zip(&certificate.signatures);
+            commits.push(signed.map(|(node, signature)| {
+                let vote = Vote {
+                    context: certificate.context,
+                    view: certificate.view,
+                    sequence: certificate.sequence,
+                    digest: certificate.digest,
+                    sender: node.to_string(),
+                    signature: *signature,
+                };
+                (node.to_string(), vote)
+            }).collect());
+        }
+        assert!(hrc.evidence.is_empty());
+        // Two quorums of three out of four overlap in at least two nodes.
+        let node = commits[0].keys().find(|node| commits[1].contains_key(*node)).unwrap().clone();
+        let commits = [commits[0][&node].clone(), commits[1][&node].clone()];
+        assert_ne!(commits[0].digest, commits[1].digest);
+        let keyring = hrc.keyring();
+        assert!(commits.iter().all(|vote| vote.verify(VoteKind::Commit, &keyring)));
+
+        let evidence = Evidence::DoubleVote { kind: VoteKind::Commit, first: commits[0].clone(), second: commits[1].clone() };
+        assert!(evidence.validate(&keyring).is_err());
+        let mut token = WaclaniumToken::new(0, 1_000_000, 1);
+        token.mint(&node, 1_000).unwrap();
+        token.stake(&node, 1_000).unwrap();
+        assert!(Slasher::default().slash(&evidence, &keyring, &mut token, &mut hrc.security, 1).is_err());
+        assert_eq!(token.get_stake(&node), 1_000);
+    }
+
+    /// Drops everything sent by the `silent` nodes, and lets `equivocator`, signing with its
+    /// key, send node3 and node4 a tampered copy of each proposal.
+    struct FaultyTransport {
+        inner: LocalTransport,
+        silent: HashSet<String>,
+        equivocator: Option<(String, SigningKey)>,
+    }
+
+    impl ConsensusTransport for FaultyTransport {
+        fn broadcast(&mut self, from: &str, message: ConsensusMessage) {
+            if self.silent.contains(from) {
+                return;
+            }
+            match message {
+                ConsensusMessage::PrePrepare { context, view, sequence, proposal, sender, .. }
+                    if self.equivocator.as_ref().is_some_and(|(id, _)| id == from) =>
+                {
+                    let key = &self.equivocator.as_ref().unwrap().1;
+                    let mut tampered = (*proposal).clone();
+                    tampered.header.proof_of_fractal_data.nonce ^= 1;
+                    for (node, proposal) in [("node1", &*proposal), ("node2", &*proposal), ("node3", &tampered), ("node4", &tampered)] {
+                        self.inner.send(node, ConsensusMessage::pre_prepare(context, view, sequence, proposal.clone(), &sender, key));
+                    }
+                }
+                message => self.inner.broadcast(from, message),
+            }
+        }
+
+        fn receive(&mut self, node: &str) -> Option<ConsensusMessage> {
+            self.inner.receive(node)
+        }
+    }
+
+    fn run_leaf(silent: &[&str], equivocator: Option<&str>) -> (bool, HierarchicalRecursiveConsensus) {
+        let nodes = node_ids(4);
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(nodes, 1, 1, 0);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let silent: HashSet<String> = silent.iter().map(|s| s.to_string()).collect();
+        let equivocator = equivocator.map(|id| (id.to_string(), hrc.keys.key(id).unwrap().clone()));
+        let committed = hrc.run_consensus_with(&mut rng, &mut |nodes: &[String]| FaultyTransport {
+            inner: LocalTransport::new(nodes),
+            silent: silent.clone(),
+            equivocator: equivocator.clone(),
+        });
+        (committed, hrc)
+    }
+
+    #[test]
+    fn test_leaf_consensus_tolerates_f_silent_nodes() {
+        let (committed, hrc) = run_leaf(&["node4"], None);
+        assert!(committed);
+        assert_eq!(hrc.state.values().filter(|&&phase| phase == Phase::Committed).count(), 4);
+    }
+
+    #[test]
+    fn test_leaf_consensus_replaces_silent_leader() {
+        let (committed, hrc) = run_leaf(&["node1"], None);
+        assert!(committed);
+        assert!(hrc.state.values().all(|&phase| phase == Phase::Committed));
+    }
+
+    #[test]
+    fn test_leaf_consensus_replaces_equivocating_leader() {
+        let (committed, hrc) = run_leaf(&[], Some("node1"));
+        assert!(committed);
+        assert!(hrc.state.values().all(|&phase| phase == Phase::Committed));
+    }
+
+    #[test]
+    fn test_leaf_consensus_fails_without_quorum() {
+        let (committed, hrc) = run_leaf(&["node3", "node4"], None);
+        assert!(!committed);
+        assert!(hrc.state.values().all(|&phase| phase == Phase::PrePrepared));
+        assert!(!hrc.security.validate_paths());
+        assert_eq!(hrc.metrics.failure, Some(FailureReason::TooManyFaults { faults: 4, tolerated: 1 }));
+        assert_eq!((hrc.metrics.votes, hrc.metrics.faults), (0, 4));
+    }
+
+    #[test]
+    fn test_leaf_needs_enough_disjoint_paths_to_the_certificate() {
+        // With node4 silent the certificate carries three commits, so the proposal reached
+        // its holder directly and through one other signer only.
+        let nodes = node_ids(4);
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(nodes, 1, 1, 0);
+        hrc.security.min_disjoint_paths = 3;
         let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let silent: HashSet<String> = ["node4".to_string()].into_iter().collect();
+        let committed = hrc.run_consensus_with(&mut rng, &mut |nodes: &[String]| FaultyTransport {
+            inner: LocalTransport::new(nodes),
+            silent: silent.clone(),
+            equivocator: None,
+        });
+        assert!(!committed);
+        assert_eq!(hrc.metrics.failure, Some(FailureReason::TooFewPaths { agreeing: 2, required: 3 }));
+
+        hrc.security.min_disjoint_paths = 2;
+        assert!(hrc.run_consensus_with(&mut rng, &mut |nodes: &[String]| FaultyTransport {
+            inner: LocalTransport::new(nodes),
+            silent: silent.clone(),
+            equivocator: None,
+        }));
+    }
+
+    /// Delivers everything, except that node3 tells node2 it voted for another proposal.
+    struct LyingRelay {
+        inner: LocalTransport,
+        key: SigningKey,
+    }
+
+    impl ConsensusTransport for LyingRelay {
+        fn broadcast(&mut self, from: &str, message: ConsensusMessage) {
+            if from != "node3" {
+                return self.inner.broadcast(from, message);
+            }
+            let lie = |kind, vote: &Vote| Vote::sign(kind, vote.context, vote.view, vote.sequence, [9u8; 32], "node3", &self.key);
+            for node in node_ids(4) {
+                let message = match message.clone() {
+                    ConsensusMessage::Prepare(vote) if node == "node2" => ConsensusMessage::Prepare(lie(VoteKind::Prepare, &vote)),
+                    ConsensusMessage::Commit(vote) if node == "node2" => ConsensusMessage::Commit(lie(VoteKind::Commit, &vote)),
+                    message => message,
+                };
+                self.inner.send(&node, message);
+            }
+        }
+
+        fn receive(&mut self, node: &str) -> Option<ConsensusMessage> {
+            self.inner.receive(node)
+        }
+    }
+
+    #[test]
+    fn test_leaf_paths_are_the_deliveries_the_holder_received() {
+        // node2 holds the certificate, but what reached it through node3 disagrees, so only
+        // the direct path and the one through node4 count.
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(node_ids(4), 1, 1, 0);
+        hrc.security.min_disjoint_paths = 3;
+        let key = hrc.keys.key("node3").unwrap().clone();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let committed = hrc.run_consensus_with(&mut rng, &mut |nodes: &[String]| LyingRelay {
+            inner: LocalTransport::new(nodes),
+            key: key.clone(),
+        });
+        assert!(!committed);
+        assert_eq!(hrc.state["node2"], Phase::Committed);
+        assert_eq!(hrc.metrics.failure, Some(FailureReason::TooFewPaths { agreeing: 2, required: 3 }));
+
+        // Told the truth, node2 hears the proposal over three disjoint paths.
         assert!(hrc.run_consensus(&mut rng));
+    }
+
+    /// Runs a 12-node tree of three 4-node leaves in which node9 and node10 are silent,
+    /// so the third leaf cannot reach quorum.
+    fn run_tree_with_failed_leaf(policy: AggregationPolicy) -> (bool, HierarchicalRecursiveConsensus) {
+        let nodes = node_ids(12);
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(nodes, 1, 1, 1);
+        hrc.set_aggregation_policy(policy);
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let silent: HashSet<String> = ["node9", "node10"].iter().map(|s| s.to_string()).collect();
+        let committed = hrc.run_consensus_with(&mut rng, &mut |nodes: &[String]| FaultyTransport {
+            inner: LocalTransport::new(nodes),
+            silent: silent.clone(),
+            equivocator: None,
+        });
+        (committed, hrc)
+    }
+
+    #[test]
+    fn test_minority_child_failure_is_tolerated() {
+        let (committed, hrc) = run_tree_with_failed_leaf(AggregationPolicy::Majority);
+        assert!(committed);
+        assert_eq!(hrc.absent_children, vec![2]);
         assert!(hrc.validate_subfractal());
+
+        let root = hrc.final_commitment().unwrap();
+        assert_eq!(root.verify(&hrc.shape(), &hrc.keyring()), Ok(()));
+        assert_eq!(root.absent_children(), vec![2]);
+        assert!(hrc.triad.get_child(2).is_none());
+        assert_eq!(hrc.triad.child_hashes()[0], Some(hrc.children[0].triad.hash()));
+
+        // The same commitment does not hold up under a stricter policy.
+        let mut strict = root.clone();
+        if let CommitmentProof::Aggregate { policy, .. } = &mut strict.proof {
+            *policy = AggregationPolicy::Unanimous;
+        }
+        assert!(strict.verify(&hrc.shape(), &hrc.keyring()).is_err());
+    }
+
+    #[test]
+    fn test_unanimous_policy_fails_on_any_child() {
+        let (committed, hrc) = run_tree_with_failed_leaf(AggregationPolicy::Unanimous);
+        assert!(!committed);
+        assert_eq!(hrc.absent_children, vec![2]);
+        assert!(hrc.final_commitment().is_none());
+        let report = hrc.report();
+        assert_eq!(report.failure, Some(FailureReason::ChildrenMissing { committed: 2, children: 3 }));
+        assert_eq!(report.layers[1].committed, 2);
+        assert_eq!(report.root.children[2].failure, Some(FailureReason::TooManyFaults { faults: 4, tolerated: 1 }));
+
+        let (committed, _) = run_tree_with_failed_leaf(AggregationPolicy::NodeWeighted { numerator: 2, denominator: 3 });
+        assert!(committed);
+    }
+
+    #[test]
+    fn test_round_report() {
+        let nodes = node_ids(12);
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(nodes, 1, 1, 1);
+        assert!(hrc.run_consensus(&mut ChaCha8Rng::seed_from_u64(5)));
+
+        let report = hrc.report();
+        assert!(report.committed);
+        assert_eq!(report.failure, None);
+        assert_eq!(report.layers.len(), 2);
+        assert_eq!((report.layers[1].subfractals, report.layers[1].committed), (3, 3));
+        assert_eq!((report.layers[1].votes, report.layers[1].faults), (12, 0));
+        // Parents aggregate in-process; only the leaves exchange messages.
+        assert_eq!(report.layers[0].messages, 0);
+        assert!(report.layers[1].messages > 0);
+        assert_eq!(report.messages, report.layers[1].messages);
+        for leaf in &report.root.children {
+            assert!(leaf.pof_attempts > 0);
+            assert!(leaf.latency_micros <= report.latency_micros);
+        }
+        assert!(report.to_json().unwrap().contains("\"layers\""));
+    }
+
+    #[test]
+    fn test_leaf_consensus_with_leading_zero_puzzle() {
+        let nodes = node_ids(4);
+        let keys = Keystore::derive(&nodes, &[0u8; 32]);
+        let mut hrc = HierarchicalRecursiveConsensus::with_puzzle(nodes, 1, 8, 0, LeadingZeroPuzzle, keys);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert!(hrc.run_consensus(&mut rng));
+        assert!(hrc.validate_subfractal());
+        assert_eq!(hrc.triad.proof_of_fractal_data.hash[0], 0);
+    }
+
+    #[test]
+    fn test_splits_never_go_below_3f_plus_1() {
+        // 10 nodes used to split 4/4/2; now they split 5/5, and 6 nodes stay one leaf.
+        let hrc = HierarchicalRecursiveConsensus::simulated(node_ids(10), 1, 1, 2);
+        let sizes: Vec<usize> = hrc.children.iter().map(|c| c.nodes.len()).collect();
+        assert_eq!(sizes, vec![5, 5]);
+        assert!(hrc.children.iter().all(|c| c.children.is_empty()));
+        let hrc = HierarchicalRecursiveConsensus::simulated(node_ids(6), 1, 1, 1);
+        assert!(hrc.children.is_empty());
+    }
+
+    #[test]
+    fn test_consensus_over_committees() {
+        use crate::core::consensus::committee::{assign_committees, CommitteeConfig};
+
+        let eligible = node_ids(16);
+        let config = CommitteeConfig { max_leaves: 4, ..CommitteeConfig::default() };
+        let assignment = assign_committees(&eligible, &[9u8; 32], 0, &config).unwrap();
+        let keys = Keystore::derive(&eligible, &[0u8; 32]);
+        let mut hrc = HierarchicalRecursiveConsensus::from_committees(&assignment, 1, keys);
+
+        // Four leaves group into two parents under the root.
+        assert_eq!(hrc.children.len(), 2);
+        let leaves: Vec<Vec<String>> = hrc.children.iter().flat_map(|c| c.children.iter().map(|l| l.nodes.clone())).collect();
+        assert_eq!(leaves, assignment.leaves);
+        assert_eq!(hrc.nodes.len(), 16);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        assert!(hrc.run_consensus(&mut rng));
+        // Anyone holding the assignment can rebuild the shape the commitment must have.
+        let shape = CommitmentShape::from_committees(&assignment, AggregationPolicy::default());
+        assert_eq!(shape, hrc.shape());
+        assert!(hrc.final_commitment().unwrap().verify(&shape, &hrc.keyring()).is_ok());
     }
 }
@@ -0,0 +1,216 @@
+// metrics.rs
+// Per-round consensus metrics, the reasons a round can fail, and the report built from them
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use super::pbft::{ConsensusMessage, ConsensusTransport};
+
+/// Why a sub-fractal did not commit in a round.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailureReason {
+    /// The leaf's Proof-of-Fractal puzzle went unsolved within the solver's limits.
+    PofUnsolved,
+    /// The leaf's replicas could not be set up: fewer than 3f+1 nodes, or a node without
+    /// a signing key.
+    InvalidGroup,
+    /// More nodes failed to commit than the leaf tolerates.
+    TooManyFaults { faults: usize, tolerated: usize },
+    /// The Triad's hash reached the replica holding its certificate over fewer node-disjoint
+    /// paths than `RedundantPathSecurity::min_disjoint_paths` requires.
+    TooFewPaths { agreeing: usize, required: usize },
+    /// The committed Triad failed validation after the vote.
+    InvalidSubfractal,
+    /// Replicas committed, but none holds a certificate for this sub-fractal's Triad.
+    MissingCertificate,
+    /// Too few children committed to satisfy the aggregation policy.
+    ChildrenMissing { committed: usize, children: usize },
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureReason::PofUnsolved => write!(f, "proof-of-fractal unsolved"),
+            FailureReason::InvalidGroup => write!(f, "invalid replica group"),
+            FailureReason::TooManyFaults { faults, tolerated } => {
+                write!(f, "{} faulty nodes, {} tolerated", faults, tolerated)
+            }
+            FailureReason::TooFewPaths { agreeing, required } => {
+                write!(f, "{} node-disjoint paths agreed, {} required", agreeing, required)
+            }
+            FailureReason::InvalidSubfractal => write!(f, "sub-fractal failed validation"),
+            FailureReason::MissingCertificate => write!(f, "no quorum certificate"),
+            FailureReason::ChildrenMissing { committed, children } => {
+                write!(f, "{} of {} children committed", committed, children)
+            }
+        }
+    }
+}
+
+/// What one sub-fractal did in its last round. A parent holds its children's metrics,
+/// so the root's cover the whole tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubFractalMetrics {
+    pub nodes: usize,
+    pub committed: bool,
+    pub failure: Option<FailureReason>,
+    /// Wall-clock time of the round, children included.
+    pub latency_micros: u64,
+    /// Time a leaf spent solving its puzzle, and the hashes it tried.
+    pub pof_solve_micros: u64,
+    pub pof_attempts: u64,
+    /// Messages a leaf's replicas broadcast, and the logical ticks their vote took.
+    pub messages: u64,
+    pub ticks: u64,
+    /// Replicas of a leaf that committed, and those that did not.
+    pub votes: usize,
+    pub faults: usize,
+    pub children: Vec<SubFractalMetrics>,
+}
+
+/// Totals for one layer of the tree, the root's being layer 0.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerMetrics {
+    pub layer: usize,
+    pub subfractals: usize,
+    pub committed: usize,
+    pub messages: u64,
+    pub votes: usize,
+    pub faults: usize,
+    pub pof_solve_micros: u64,
+}
+
+/// Summary of a round across the whole tree, ready to serialize.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsensusReport {
+    pub committed: bool,
+    pub failure: Option<FailureReason>,
+    pub latency_micros: u64,
+    pub messages: u64,
+    pub pof_solve_micros: u64,
+    pub layers: Vec<LayerMetrics>,
+    pub root: SubFractalMetrics,
+}
+
+impl ConsensusReport {
+    /// Builds the report for the round whose root metrics are `root`.
+    pub fn from_metrics(root: &SubFractalMetrics) -> Self {
+        let mut layers = Vec::new();
+        collect_layers(root, 0, &mut layers);
+        ConsensusReport {
+            committed: root.committed,
+            failure: root.failure,
+            latency_micros: root.latency_micros,
+            messages: layers.iter().map(|l| l.messages).sum(),
+            pof_solve_micros: layers.iter().map(|l| l.pof_solve_micros).sum(),
+            layers,
+            root: root.clone(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+}
+
+fn collect_layers(metrics: &SubFractalMetrics, layer: usize, layers: &mut Vec<LayerMetrics>) {
+    if layers.len() <= layer {
+        layers.push(LayerMetrics { layer, ..LayerMetrics::default() });
+    }
+    let totals = &mut layers[layer];
+    totals.subfractals += 1;
+    totals.committed += usize::from(metrics.committed);
+    totals.messages += metrics.messages;
+    totals.votes += metrics.votes;
+    totals.faults += metrics.faults;
+    totals.pof_solve_micros += metrics.pof_solve_micros;
+    for child in &metrics.children {
+        collect_layers(child, layer + 1, layers);
+    }
+}
+
+/// Wraps a transport and counts the messages broadcast through it.
+pub struct CountingTransport<T> {
+    inner: T,
+    broadcasts: u64,
+}
+
+impl<T: ConsensusTransport> CountingTransport<T> {
+    pub fn new(inner: T) -> Self {
+        CountingTransport { inner, broadcasts: 0 }
+    }
+
+    /// Messages broadcast so far.
+    pub fn broadcasts(&self) -> u64 {
+        self.broadcasts
+    }
+}
+
+impl<T: ConsensusTransport> ConsensusTransport for CountingTransport<T> {
+    fn broadcast(&mut self, from: &str, message: ConsensusMessage) {
+        self.broadcasts += 1;
+        self.inner.broadcast(from, message);
+    }
+
+    fn receive(&mut self, node: &str) -> Option<ConsensusMessage> {
+        self.inner.receive(node)
+    }
+
+    fn tick(&mut self) {
+        self.inner.tick();
+    }
+
+    fn connected(&self, a: &str, b: &str) -> bool {
+        self.inner.connected(a, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(committed: bool, messages: u64, faults: usize) -> SubFractalMetrics {
+        SubFractalMetrics {
+            nodes: 4,
+            committed,
+            failure: (!committed).then_some(FailureReason::TooManyFaults { faults, tolerated: 1 }),
+            messages,
+            votes: 4 - faults,
+            faults,
+            pof_solve_micros: 10,
+            ..SubFractalMetrics::default()
+        }
+    }
+
+    #[test]
+    fn test_report_totals_by_layer() {
+        let root = SubFractalMetrics {
+            nodes: 12,
+            committed: true,
+            latency_micros: 500,
+            children: vec![leaf(true, 30, 0), leaf(true, 28, 1), leaf(false, 9, 2)],
+            ..SubFractalMetrics::default()
+        };
+        let report = ConsensusReport::from_metrics(&root);
+
+        assert!(report.committed);
+        assert_eq!(report.messages, 67);
+        assert_eq!(report.pof_solve_micros, 30);
+        assert_eq!(report.layers.len(), 2);
+        assert_eq!(report.layers[0], LayerMetrics { layer: 0, subfractals: 1, committed: 1, ..LayerMetrics::default() });
+        let leaves = &report.layers[1];
+        assert_eq!((leaves.subfractals, leaves.committed, leaves.votes, leaves.faults), (3, 2, 9, 3));
+
+        let json = report.to_json().unwrap();
+        assert!(json.contains("\"TooManyFaults\""), "{}", json);
+        assert_eq!(serde_json::from_str::<ConsensusReport>(&json).unwrap(), report);
+    }
+
+    #[test]
+    fn test_failure_reason_display() {
+        assert_eq!(
+            FailureReason::ChildrenMissing { committed: 1, children: 3 }.to_string(),
+            "1 of 3 children committed"
+        );
+        assert_eq!(FailureReason::PofUnsolved.to_string(), "proof-of-fractal unsolved");
+    }
+}
@@ -1,2 +1,13 @@
+pub mod aggregation;
+pub mod commitment;
+pub mod committee;
+pub mod finality;
+pub mod fork_choice;
 pub mod hierarchical_recursive;
+pub mod metrics;
+pub mod parallel_miner;
+pub mod pbft;
 pub mod proof_of_fractal;
+pub mod puzzle;
+pub mod retarget;
+pub mod solver;
@@ -0,0 +1,254 @@
+// parallel_miner.rs
+// Multi-threaded Proof-of-Fractal mining over a partitioned nonce space
+
+use std::thread;
+use std::time::{Duration, Instant};
+use super::proof_of_fractal::ProofOfFractal;
+use super::puzzle::FractalPuzzle;
+use super::solver::{CancellationToken, NonceSource, SolveOutcome, SolverConfig};
+use crate::core::triad_matrix::triad_structure::{ProofOfFractalData, Triad};
+
+/// Result of a parallel mining run.
+#[derive(Clone, Debug)]
+pub struct MiningReport {
+    /// `Solved` carries the winning worker's nonce, hash and attempt count; any other
+    /// outcome carries the attempts of all workers combined.
+    pub outcome: SolveOutcome,
+    /// Hashes computed across all workers, including those stopped after the win.
+    pub total_attempts: u64,
+    pub elapsed: Duration,
+    pub threads: usize,
+}
+
+impl MiningReport {
+    /// Combined hash rate of all workers.
+    pub fn hashes_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.total_attempts as f64 / secs
+    }
+}
+
+/// Splits a Proof-of-Fractal search across worker threads. Each worker searches its own
+/// share of the nonce space and the first solution found stops the others.
+pub struct ParallelMiner {
+    threads: usize,
+}
+
+impl ParallelMiner {
+    /// Creates a miner with the given number of worker threads (at least one).
+    pub fn new(threads: usize) -> Self {
+        ParallelMiner { threads: threads.max(1) }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Gives each worker its share of the search described by `config`:
+    /// - `Sequential` ranges are cut into contiguous, non-overlapping slices.
+    /// - `Seeded` sources get the seed offset by the worker index, keeping runs reproducible.
+    /// - `Os` sources are shared as is, since independent OS draws do not need partitioning.
+    ///
+    /// The iteration budget is divided evenly and every worker stops on `cancel`.
+    pub fn partition(&self, config: &SolverConfig, cancel: &CancellationToken) -> Vec<SolverConfig> {
+        let n = self.threads as u64;
+        (0..n)
+            .map(|i| {
+                let source = match config.source {
+                    NonceSource::Sequential { start, end } => {
+                        let chunk = (end.saturating_sub(start)).div_ceil(n);
+                        let lo = start.saturating_add(chunk.saturating_mul(i)).min(end);
+                        let hi = lo.saturating_add(chunk).min(end);
+                        NonceSource::Sequential { start: lo, end: hi }
+                    }
+                    NonceSource::Seeded(seed) => NonceSource::Seeded(seed.wrapping_add(i)),
+                    NonceSource::Os => NonceSource::Os,
+                };
+                SolverConfig {
+                    source,
+                    max_iterations: config.max_iterations.map(|max| max.div_ceil(n)),
+                    timeout: config.timeout,
+                    cancel: Some(cancel.clone()),
+                }
+            })
+            .collect()
+    }
+
+    /// Mines `data` at the puzzle's current difficulty. Difficulty is read once up front,
+    /// and the winning nonce and hash are published into `pof` so `verify_solution` accepts them.
+    pub fn mine<P: FractalPuzzle>(&self, pof: &ProofOfFractal<P>, data: &[u8], config: &SolverConfig) -> MiningReport {
+        let difficulty = *pof.difficulty.lock().unwrap();
+        let report = self.run(&pof.puzzle, data, difficulty, config);
+        if let SolveOutcome::Solved { nonce, hash, .. } = report.outcome {
+            pof.publish(nonce, hash);
+        }
+        report
+    }
+
+    /// Parallel counterpart of `ProofOfFractal::solve_triad`: mines over the Triad header
+    /// and stores the solution in `triad.proof_of_fractal_data` as well as in `pof`.
+    pub fn mine_triad<P: FractalPuzzle>(
+        &self,
+        pof: &ProofOfFractal<P>,
+        triad: &mut Triad,
+        config: &SolverConfig,
+    ) -> MiningReport {
+        let difficulty = *pof.difficulty.lock().unwrap();
+        triad.proof_of_fractal_data.difficulty = difficulty;
+        let report = self.run(&pof.puzzle, &triad.pof_preimage(), difficulty, config);
+        if let SolveOutcome::Solved { nonce, hash, .. } = report.outcome {
+            triad.proof_of_fractal_data = ProofOfFractalData { nonce, difficulty, hash };
+            pof.publish(nonce, hash);
+        }
+        report
+    }
+
+    fn run<P: FractalPuzzle>(&self, puzzle: &P, data: &[u8], difficulty: u32, config: &SolverConfig) -> MiningReport {
+        let stop = CancellationToken::new();
+        let workers = self.partition(config, &stop);
+        let start_time = Instant::now();
+
+        let outcomes: Vec<SolveOutcome> = thread::scope(|scope| {
+            let handles: Vec<_> = workers
+                .iter()
+                .map(|worker| {
+                    let stop = stop.clone();
+                    scope.spawn(move || {
+                        let outcome = puzzle.solve(data, difficulty, worker);
+                        if outcome.is_solved() {
+                            stop.cancel();
+                        }
+                        outcome
+                    })
+                })
+                .collect();
+
+            // Forward the caller's cancellation to the workers without tripping it ourselves.
+            if let Some(external) = &config.cancel {
+                while !handles.iter().all(|h| h.is_finished()) {
+                    if external.is_cancelled() {
+                        stop.cancel();
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+
+            handles.into_iter().map(|h| h.join().expect("mining worker panicked")).collect()
+        });
+
+        let elapsed = start_time.elapsed();
+        let total_attempts = outcomes.iter().map(|o| o.attempts()).sum();
+        let winner = outcomes.iter().find(|o| o.is_solved()).cloned();
+
+        let outcome = match winner {
+            Some(solved) => solved,
+            _ if config.cancel.as_ref().is_some_and(|c| c.is_cancelled()) => {
+                SolveOutcome::Cancelled { attempts: total_attempts }
+            }
+            _ if outcomes.iter().any(|o| matches!(o, SolveOutcome::TimedOut { .. })) => {
+                SolveOutcome::TimedOut { attempts: total_attempts }
+            }
+            _ => SolveOutcome::Exhausted { attempts: total_attempts },
+        };
+
+        MiningReport { outcome, total_attempts, elapsed, threads: self.threads }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::proof_of_fractal::verify_triad_pof;
+    use std::sync::atomic::Ordering;
+
+    fn sequential(start: u64, end: u64) -> SolverConfig {
+        SolverConfig::new(NonceSource::Sequential { start, end })
+    }
+
+    #[test]
+    fn test_partition_covers_range_without_overlap() {
+        let miner = ParallelMiner::new(3);
+        let parts = miner.partition(&sequential(10, 20), &CancellationToken::new());
+        let nonces: Vec<u64> = parts.iter().flat_map(|p| p.source.nonces()).collect();
+        assert_eq!(nonces, (10..20).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_partition_splits_budget_and_seeds() {
+        let miner = ParallelMiner::new(4);
+        let config = SolverConfig {
+            max_iterations: Some(10),
+            ..SolverConfig::new(NonceSource::Seeded(7))
+        };
+        let parts = miner.partition(&config, &CancellationToken::new());
+        assert_eq!(parts.len(), 4);
+        assert!(parts.iter().all(|p| p.max_iterations == Some(3)));
+        assert_eq!(parts[2].source, NonceSource::Seeded(9));
+    }
+
+    #[test]
+    fn test_mine_publishes_solution() {
+        let pof = ProofOfFractal::new(2);
+        let data = b"test data";
+        let report = ParallelMiner::new(4).mine(&pof, data, &sequential(0, 1_000_000));
+        match report.outcome {
+            SolveOutcome::Solved { nonce, hash, .. } => {
+                assert_eq!(pof.nonce.load(Ordering::SeqCst), nonce);
+                assert_eq!(*pof.hash.lock().unwrap(), hash);
+            }
+            other => panic!("expected a solution, got {:?}", other),
+        }
+        assert!(pof.verify_solution(data));
+        assert!(report.total_attempts >= report.outcome.attempts());
+        assert_eq!(report.threads, 4);
+    }
+
+    #[test]
+    fn test_mine_triad() {
+        let pof = ProofOfFractal::new(2);
+        let mut triad = Triad::new();
+        triad.timestamp = 1_700_000_000;
+        let report = ParallelMiner::new(3).mine_triad(&pof, &mut triad, &sequential(0, 1_000_000));
+        assert!(report.outcome.is_solved());
+        assert!(verify_triad_pof(&triad));
+    }
+
+    #[test]
+    fn test_single_thread_matches_solver() {
+        let data = b"test data";
+        let config = sequential(0, 1_000_000);
+        let expected = ProofOfFractal::new(2).solve_with(data, &config);
+        let report = ParallelMiner::new(1).mine(&ProofOfFractal::new(2), data, &config);
+        assert_eq!(report.outcome, expected);
+    }
+
+    #[test]
+    fn test_mine_exhausts_budget() {
+        let pof = ProofOfFractal::new(8);
+        let config = SolverConfig {
+            max_iterations: Some(400),
+            ..SolverConfig::new(NonceSource::Seeded(1))
+        };
+        let report = ParallelMiner::new(4).mine(&pof, b"test", &config);
+        assert_eq!(report.outcome, SolveOutcome::Exhausted { attempts: 400 });
+        assert_eq!(report.total_attempts, 400);
+        assert!(report.hashes_per_sec() > 0.0);
+    }
+
+    #[test]
+    fn test_mine_cancelled() {
+        let pof = ProofOfFractal::new(8);
+        let token = CancellationToken::new();
+        token.cancel();
+        let config = SolverConfig {
+            cancel: Some(token),
+            ..SolverConfig::new(NonceSource::Os)
+        };
+        let report = ParallelMiner::new(2).mine(&pof, b"test", &config);
+        assert!(matches!(report.outcome, SolveOutcome::Cancelled { .. }));
+    }
+}
@@ -0,0 +1,1704 @@
+// pbft.rs
+// Message-driven PBFT replica: pre-prepare, prepare and commit phases, view changes and
+// leader rotation over an injectable transport
+
+use ed25519_dalek::{Signature, Signer, SigningKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use crate::core::security::keys::Keyring;
+use crate::core::triad_matrix::encoding::TriadRecord;
+use crate::core::triad_matrix::evidence::{Evidence, SignedHeader, Vote, VoteKind};
+
+/// Proof that a proposal prepared in some view: the proposal and 2f+1 matching prepares.
+/// A new leader must re-propose the most recent one it is shown, so nothing that may have
+/// committed in an earlier view is lost.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreparedCertificate {
+    pub context: [u8; 32],
+    pub view: u64,
+    pub sequence: u64,
+    pub proposal: Box<TriadRecord>,
+    pub prepares: Vec<Vote>,
+}
+
+impl PreparedCertificate {
+    /// Returns true if the certificate holds `quorum` signed prepares from distinct members
+    /// of `nodes`, all for its context, round and proposal's digest.
+    pub fn is_valid(&self, nodes: &[String], keyring: &Keyring, quorum: usize) -> bool {
+        let digest = self.proposal.hash();
+        let mut senders = BTreeSet::new();
+        for vote in &self.prepares {
+            if vote.context != self.context
+                || vote.view != self.view
+                || vote.sequence != self.sequence
+                || vote.digest != digest
+            {
+                return false;
+            }
+            // Votes from non-members do not count.
+            if nodes.contains(&vote.sender) && vote.verify(VoteKind::Prepare, keyring) {
+                senders.insert(vote.sender.as_str());
+            }
+        }
+        senders.len() >= quorum
+    }
+
+    /// Identifies the certificate by its context, round and proposal, for view changes to sign.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.context);
+        hasher.update(self.view.to_le_bytes());
+        hasher.update(self.sequence.to_le_bytes());
+        hasher.update(self.proposal.hash());
+        hasher.finalize().into()
+    }
+}
+
+/// Proof that a proposal committed: the signatures of 2f+1 members on their commits for
+/// (`context`, `view`, `sequence`, `digest`). It is checked against the members' public keys alone,
+/// without the commit messages, and stays small enough for a parent Triad to embed: a
+/// bitmap of which members signed, in group order, and one signature per signer.
+/// Parents accept a sub-fractal's Triad on the strength of this certificate.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuorumCertificate {
+    pub context: [u8; 32],
+    pub view: u64,
+    pub sequence: u64,
+    pub digest: [u8; 32],
+    /// Bit `i` (of byte `i / 8`, least significant first) is set if `nodes[i]` signed.
+    pub signers: Vec<u8>,
+    /// One signature per set bit, in the same order.
+    pub signatures: Vec<Signature>,
+}
+
+impl QuorumCertificate {
+    /// Bundles the signatures of `commits` for (`context`, `view`, `sequence`, `digest`)
+    /// from members of `nodes`. Commits for anything else or from non-members are left out,
+    /// and a member's second commit is ignored.
+    pub fn from_commits<'a, I>(
+        context: [u8; 32],
+        view: u64,
+        sequence: u64,
+        digest: [u8; 32],
+        nodes: &[String],
+        commits: I,
+    ) -> Self
+    where
+        I: IntoIterator<Item = &'a Vote>,
+    {
+        let mut by_member: BTreeMap<usize, Signature> = BTreeMap::new();
+        for vote in commits {
+            if vote.context != context || vote.view != view || vote.sequence != sequence || vote.digest != digest {
+                continue;
+            }
+            if let Some(index) = nodes.iter().position(|n| *n == vote.sender) {
+                by_member.entry(index).or_insert(vote.signature);
+            }
+        }
+        let mut signers = vec![0u8; nodes.len().div_ceil(8)];
+        for &index in by_member.keys() {
+            signers[index / 8] |= 1 << (index % 8);
+        }
+        QuorumCertificate {
+            context,
+            view,
+            sequence,
+            digest,
+            signers,
+            signatures: by_member.into_values().collect(),
+        }
+    }
+
+    /// The members of `nodes` whose bits are set, in group order.
+    pub fn signed_by<'a>(&self, nodes: &'a [String]) -> Vec<&'a str> {
+        nodes
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.signers.get(index / 8).is_some_and(|byte| byte & (1 << (index % 8)) != 0))
+            .map(|(_, node)| node.as_str())
+            .collect()
+    }
+
+    /// Returns true if at least `quorum` members of `nodes` signed a commit for the
+    /// certificate's context, round and digest, each checked against its key in `keyring`.
+    pub fn is_valid(&self, nodes: &[String], keyring: &Keyring, quorum: usize) -> bool {
+        if self.signers.len() != nodes.len().div_ceil(8) {
+            return false;
+        }
+        // No bits may be set past the last member.
+        let used: u32 = self.signers.iter().map(|byte| byte.count_ones()).sum();
+        let signed = self.signed_by(nodes);
+        if used as usize != signed.len() || signed.len() != self.signatures.len() || signed.len() < quorum {
+            return false;
+        }
+        let bytes = Vote::signing_bytes(VoteKind::Commit, &self.context, self.view, self.sequence, &self.digest);
+        signed
+            .iter()
+            .zip(&self.signatures)
+            .all(|(node, signature)| keyring.verify(node, &bytes, signature))
+    }
+}
+
+/// A replica's request to move to `new_view`, carrying what it needs the next leader to know.
+/// It is signed by its sender, so a leader cannot make up the view changes its new-view
+/// message rests on, e.g. to drop a prepared proposal it would rather not carry over.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewChange {
+    pub context: [u8; 32],
+    pub new_view: u64,
+    /// Highest sequence number the sender has committed, 0 if none.
+    pub committed_sequence: u64,
+    /// The sender's most recent prepared but uncommitted proposal, if any.
+    pub prepared: Option<PreparedCertificate>,
+    pub sender: String,
+    pub signature: Signature,
+}
+
+impl ViewChange {
+    /// What a view change signs: its context, view, committed sequence and the digest of
+    /// its prepared certificate, all zero if it has none.
+    pub fn signing_bytes(
+        context: &[u8; 32],
+        new_view: u64,
+        committed_sequence: u64,
+        prepared: Option<&PreparedCertificate>,
+    ) -> Vec<u8> {
+        let mut bytes = b"seirchain-view-change".to_vec();
+        bytes.extend_from_slice(context);
+        bytes.extend_from_slice(&new_view.to_le_bytes());
+        bytes.extend_from_slice(&committed_sequence.to_le_bytes());
+        bytes.extend_from_slice(&prepared.map_or([0u8; 32], PreparedCertificate::digest));
+        bytes
+    }
+
+    /// Asks for `new_view` of `context` as `sender`, signed with its `key`.
+    pub fn sign(
+        context: [u8; 32],
+        new_view: u64,
+        committed_sequence: u64,
+        prepared: Option<PreparedCertificate>,
+        sender: &str,
+        key: &SigningKey,
+    ) -> Self {
+        let bytes = ViewChange::signing_bytes(&context, new_view, committed_sequence, prepared.as_ref());
+        let signature = key.sign(&bytes);
+        ViewChange { context, new_view, committed_sequence, prepared, sender: sender.to_string(), signature }
+    }
+
+    /// Returns true if the view change was signed by its sender's key in `keyring`.
+    pub fn verify(&self, keyring: &Keyring) -> bool {
+        let bytes =
+            ViewChange::signing_bytes(&self.context, self.new_view, self.committed_sequence, self.prepared.as_ref());
+        keyring.verify(&self.sender, &bytes, &self.signature)
+    }
+}
+
+/// Messages exchanged by replicas. Every message is authenticated by a signature checked
+/// against the group's keyring (see `verify`), so replicas trust no transport to vouch for
+/// senders: a decision by its quorum certificate, everything else by its sender's signature.
+/// Every message belongs to the consensus instance named by its `context` (see
+/// `round_context`) and is signed with it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConsensusMessage {
+    /// The leader assigns `sequence` to a proposed Triad. `signature` signs the proposal's
+    /// header for the round, as in `SignedHeader`.
+    PrePrepare {
+        context: [u8; 32],
+        view: u64,
+        sequence: u64,
+        proposal: Box<TriadRecord>,
+        sender: String,
+        signature: Signature,
+    },
+    Prepare(Vote),
+    Commit(Vote),
+    ViewChange(ViewChange),
+    /// The leader of `view` proves 2f+1 replicas asked for it and re-proposes the most recent
+    /// prepared proposal they reported, or a fresh one if none was, under `sequence`.
+    /// `signature` covers `new_view_signing_bytes`.
+    NewView {
+        context: [u8; 32],
+        view: u64,
+        view_changes: Vec<ViewChange>,
+        proposal: Option<(u64, Box<TriadRecord>)>,
+        sender: String,
+        signature: Signature,
+    },
+    /// A committed proposal with its certificate, sent to a replica whose view change shows
+    /// it fell behind, e.g. because it was shown a different proposal than the quorum.
+    /// The certificate stands on its own, so it does not matter who relays it.
+    Decision {
+        proposal: Box<TriadRecord>,
+        certificate: QuorumCertificate,
+        sender: String,
+    },
+}
+
+impl ConsensusMessage {
+    /// A pre-prepare of `proposal` for round (`view`, `sequence`) of `context`, signed by
+    /// `sender`'s `key`.
+    pub fn pre_prepare(
+        context: [u8; 32],
+        view: u64,
+        sequence: u64,
+        proposal: TriadRecord,
+        sender: &str,
+        key: &SigningKey,
+    ) -> Self {
+        let signed = SignedHeader::sign(context, view, sequence, proposal.header.clone(), sender, key);
+        ConsensusMessage::PrePrepare {
+            context,
+            view,
+            sequence,
+            proposal: Box::new(proposal),
+            sender: sender.to_string(),
+            signature: signed.signature,
+        }
+    }
+
+    /// A new-view message for `view` of `context` from `sender`, signed with its `key`.
+    pub fn new_view(
+        context: [u8; 32],
+        view: u64,
+        view_changes: Vec<ViewChange>,
+        proposal: Option<(u64, Box<TriadRecord>)>,
+        sender: &str,
+        key: &SigningKey,
+    ) -> Self {
+        let signature = key.sign(&ConsensusMessage::new_view_signing_bytes(&context, view, proposal.as_ref()));
+        ConsensusMessage::NewView { context, view, view_changes, proposal, sender: sender.to_string(), signature }
+    }
+
+    /// What a new-view message signs. With a proposal it is the `SignedHeader` bytes of the
+    /// proposal's header for (`view`, `sequence`), so the re-proposal is signed just like a
+    /// pre-prepare; without one, a domain tag, the context and the view. The view changes
+    /// sign themselves.
+    pub fn new_view_signing_bytes(
+        context: &[u8; 32],
+        view: u64,
+        proposal: Option<&(u64, Box<TriadRecord>)>,
+    ) -> Vec<u8> {
+        match proposal {
+            Some((sequence, record)) => SignedHeader::signing_bytes(context, view, *sequence, &record.header),
+            None => {
+                let mut bytes = b"seirchain-new-view".to_vec();
+                bytes.extend_from_slice(context);
+                bytes.extend_from_slice(&view.to_le_bytes());
+                bytes
+            }
+        }
+    }
+
+    /// The signed header of the proposal a pre-prepare or new-view message carries.
+    pub fn proposal_header(&self) -> Option<SignedHeader> {
+        let (context, view, sequence, record, sender, signature) = match self {
+            ConsensusMessage::PrePrepare { context, view, sequence, proposal, sender, signature } => {
+                (*context, *view, *sequence, proposal, sender, signature)
+            }
+            ConsensusMessage::NewView {
+                context, view, proposal: Some((sequence, proposal)), sender, signature, ..
+            } => (*context, *view, *sequence, proposal, sender, signature),
+            _ => return None,
+        };
+        Some(SignedHeader {
+            context,
+            view,
+            sequence,
+            header: record.header.clone(),
+            signer: sender.clone(),
+            signature: *signature,
+        })
+    }
+
+    /// Returns true if the message is signed by its sender's key in `keyring` and, when it
+    /// carries a proposal, the proposal's transactions and evidence match its signed header.
+    /// A decision is always accepted here; its certificate is checked when it is handled.
+    pub fn verify(&self, keyring: &Keyring) -> bool {
+        match self {
+            ConsensusMessage::Prepare(vote) => vote.verify(VoteKind::Prepare, keyring),
+            ConsensusMessage::Commit(vote) => vote.verify(VoteKind::Commit, keyring),
+            ConsensusMessage::ViewChange(change) => change.verify(keyring),
+            ConsensusMessage::PrePrepare { proposal, .. } => {
+                proposal.roots_match() && self.proposal_header().is_some_and(|header| header.verify(keyring))
+            }
+            ConsensusMessage::NewView { context, view, proposal: None, sender, signature, .. } => {
+                keyring.verify(sender, &ConsensusMessage::new_view_signing_bytes(context, *view, None), signature)
+            }
+            ConsensusMessage::NewView { proposal: Some((_, record)), .. } => {
+                record.roots_match() && self.proposal_header().is_some_and(|header| header.verify(keyring))
+            }
+            ConsensusMessage::Decision { .. } => true,
+        }
+    }
+
+    /// The (view, sequence) round this message belongs to. View-change messages concern a
+    /// whole view and report sequence 0.
+    pub fn round(&self) -> (u64, u64) {
+        match self {
+            ConsensusMessage::PrePrepare { view, sequence, .. } => (*view, *sequence),
+            ConsensusMessage::Prepare(vote) | ConsensusMessage::Commit(vote) => (vote.view, vote.sequence),
+            ConsensusMessage::ViewChange(change) => (change.new_view, 0),
+            ConsensusMessage::NewView { view, .. } => (*view, 0),
+            ConsensusMessage::Decision { certificate, .. } => (certificate.view, certificate.sequence),
+        }
+    }
+
+    /// The consensus instance this message belongs to.
+    pub fn context(&self) -> [u8; 32] {
+        match self {
+            ConsensusMessage::PrePrepare { context, .. } | ConsensusMessage::NewView { context, .. } => *context,
+            ConsensusMessage::Prepare(vote) | ConsensusMessage::Commit(vote) => vote.context,
+            ConsensusMessage::ViewChange(change) => change.context,
+            ConsensusMessage::Decision { certificate, .. } => certificate.context,
+        }
+    }
+
+    pub fn sender(&self) -> &str {
+        match self {
+            ConsensusMessage::PrePrepare { sender, .. }
+            | ConsensusMessage::NewView { sender, .. }
+            | ConsensusMessage::Decision { sender, .. } => sender,
+            ConsensusMessage::Prepare(vote) | ConsensusMessage::Commit(vote) => &vote.sender,
+            ConsensusMessage::ViewChange(change) => &change.sender,
+        }
+    }
+}
+
+/// How far a replica has got with one round.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Phase {
+    /// Nothing accepted for this round yet.
+    #[default]
+    Idle,
+    /// The leader's proposal was accepted and a prepare sent.
+    PrePrepared,
+    /// 2f+1 matching prepares seen; a commit was sent.
+    Prepared,
+    /// 2f+1 matching commits seen; the proposal is decided.
+    Committed,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::Idle => "idle",
+            Phase::PrePrepared => "pre-prepared",
+            Phase::Prepared => "prepared",
+            Phase::Committed => "committed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A proposal this replica has seen committed, with the commits that decided it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommittedProposal {
+    pub view: u64,
+    pub sequence: u64,
+    pub proposal: TriadRecord,
+    pub certificate: QuorumCertificate,
+}
+
+/// Delivers consensus messages between replicas.
+pub trait ConsensusTransport {
+    /// Sends `message` from `from` to every replica, the sender included.
+    fn broadcast(&mut self, from: &str, message: ConsensusMessage);
+
+    /// Takes the next message waiting for `node`, if any.
+    fn receive(&mut self, node: &str) -> Option<ConsensusMessage>;
+
+    /// Called by `drive` once per logical tick, after the replicas have ticked. Transports
+    /// that model time advance their clock here; delivery is instant by default.
+    fn tick(&mut self) {}
+
+    /// Returns true if messages can currently travel between `a` and `b`. Every pair is
+    /// linked by default; transports that model a topology or partitions say otherwise.
+    fn connected(&self, _a: &str, _b: &str) -> bool {
+        true
+    }
+}
+
+/// In-process transport: one FIFO inbox per replica, delivered in order with no loss.
+#[derive(Clone, Debug, Default)]
+pub struct LocalTransport {
+    inboxes: BTreeMap<String, VecDeque<ConsensusMessage>>,
+}
+
+impl LocalTransport {
+    pub fn new(nodes: &[String]) -> Self {
+        LocalTransport {
+            inboxes: nodes.iter().map(|n| (n.clone(), VecDeque::new())).collect(),
+        }
+    }
+
+    /// Queues `message` for `node` alone, e.g. to model a sender that tells replicas
+    /// different things.
+    pub fn send(&mut self, node: &str, message: ConsensusMessage) {
+        if let Some(inbox) = self.inboxes.get_mut(node) {
+            inbox.push_back(message);
+        }
+    }
+
+    /// Number of messages not yet received by any replica.
+    pub fn pending(&self) -> usize {
+        self.inboxes.values().map(|inbox| inbox.len()).sum()
+    }
+}
+
+impl ConsensusTransport for LocalTransport {
+    fn broadcast(&mut self, _from: &str, message: ConsensusMessage) {
+        for inbox in self.inboxes.values_mut() {
+            inbox.push_back(message.clone());
+        }
+    }
+
+    fn receive(&mut self, node: &str) -> Option<ConsensusMessage> {
+        self.inboxes.get_mut(node)?.pop_front()
+    }
+}
+
+/// A digest a replica received for round (`view`, `sequence`) in a message from `from`: the
+/// proposal a pre-prepare or new-view carried, or the proposal a vote was for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Received {
+    pub from: String,
+    pub view: u64,
+    pub sequence: u64,
+    pub digest: [u8; 32],
+}
+
+/// Wraps a transport and records, per replica, the digest every proposal and vote it
+/// received carried and whom it came from. Messages are authenticated by their sender and
+/// sent to replicas directly, so this is the route each digest actually took.
+pub struct RecordingTransport<T> {
+    inner: T,
+    received: BTreeMap<String, Vec<Received>>,
+}
+
+impl<T: ConsensusTransport> RecordingTransport<T> {
+    pub fn new(inner: T) -> Self {
+        RecordingTransport { inner, received: BTreeMap::new() }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// The digests `node` received for round (`view`, `sequence`) from `from`, in order.
+    pub fn received_from(&self, node: &str, from: &str, view: u64, sequence: u64) -> Vec<[u8; 32]> {
+        self.received
+            .get(node)
+            .into_iter()
+            .flatten()
+            .filter(|r| r.from == from && (r.view, r.sequence) == (view, sequence))
+            .map(|r| r.digest)
+            .collect()
+    }
+}
+
+impl<T: ConsensusTransport> ConsensusTransport for RecordingTransport<T> {
+    fn broadcast(&mut self, from: &str, message: ConsensusMessage) {
+        self.inner.broadcast(from, message);
+    }
+
+    fn receive(&mut self, node: &str) -> Option<ConsensusMessage> {
+        let message = self.inner.receive(node)?;
+        let round = match &message {
+            ConsensusMessage::PrePrepare { view, sequence, proposal, .. } => Some((*view, *sequence, proposal.hash())),
+            ConsensusMessage::NewView { view, proposal: Some((sequence, proposal)), .. } => {
+                Some((*view, *sequence, proposal.hash()))
+            }
+            ConsensusMessage::Prepare(vote) | ConsensusMessage::Commit(vote) => Some((vote.view, vote.sequence, vote.digest)),
+            _ => None,
+        };
+        if let Some((view, sequence, digest)) = round {
+            let from = message.sender().to_string();
+            self.received.entry(node.to_string()).or_default().push(Received { from, view, sequence, digest });
+        }
+        Some(message)
+    }
+
+    fn tick(&mut self) {
+        self.inner.tick();
+    }
+
+    fn connected(&self, a: &str, b: &str) -> bool {
+        self.inner.connected(a, b)
+    }
+}
+
+/// Ticks a replica waits for its pending proposal to commit before asking for a new view.
+/// The wait doubles with every view change that fails to make progress.
+pub const DEFAULT_VIEW_TIMEOUT: u64 = 10;
+
+/// Checks a proposal before a replica accepts it, e.g. that its Proof-of-Fractal verifies.
+pub type ProposalValidator = Box<dyn Fn(&TriadRecord) -> bool + Send>;
+
+#[derive(Default)]
+struct Round {
+    digest: Option<[u8; 32]>,
+    proposal: Option<TriadRecord>,
+    // The leader's signature on the accepted proposal, kept to catch it signing another.
+    header: Option<SignedHeader>,
+    phase: Phase,
+    // Votes are kept per digest, then per sender, so that votes for a conflicting proposal
+    // never add up toward the quorum of the accepted one. They may arrive before the
+    // pre-prepare.
+    prepares: HashMap<[u8; 32], BTreeMap<String, Vote>>,
+    commits: HashMap<[u8; 32], BTreeMap<String, Vote>>,
+}
+
+/// One PBFT replica. It is a pure state machine: `handle` consumes a message and `tick`
+/// advances its logical clock, both returning the messages to broadcast in response, so any
+/// `ConsensusTransport` can drive it.
+///
+/// Leaders rotate round-robin through `nodes` by view. A replica with a submitted proposal
+/// that does not commit within its timeout asks for the next view; the leader of that view
+/// takes over once 2f+1 replicas agree.
+pub struct PbftReplica {
+    id: String,
+    key: SigningKey,
+    keyring: Keyring,
+    nodes: Vec<String>,
+    fault_tolerance: usize,
+    context: [u8; 32],
+    view: u64,
+    next_sequence: u64,
+    rounds: BTreeMap<(u64, u64), Round>,
+    committed: Vec<CommittedProposal>,
+    validator: Option<ProposalValidator>,
+    pending: Option<TriadRecord>,
+    ticks: u64,
+    base_timeout: u64,
+    timeout: u64,
+    // Set while this replica has left `view` and waits for a new-view message.
+    view_change_target: Option<u64>,
+    view_changes: BTreeMap<u64, BTreeMap<String, ViewChange>>,
+    new_view_sent: Option<u64>,
+    // Misbehaviour seen in the votes this replica received.
+    evidence: Vec<Evidence>,
+}
+
+impl PbftReplica {
+    /// Creates replica `id` in a group of `nodes` tolerating `fault_tolerance` faults. It
+    /// signs its votes with `key` and checks other members' votes against `keyring`.
+    /// The group needs at least 3f+1 members and must include `id`, and `keyring` must
+    /// hold every member's key, `key`'s public half as `id`'s.
+    pub fn new(
+        id: &str,
+        nodes: Vec<String>,
+        fault_tolerance: usize,
+        key: SigningKey,
+        keyring: Keyring,
+    ) -> Result<Self, String> {
+        if nodes.len() < 3 * fault_tolerance + 1 {
+            return Err(format!(
+                "PBFT needs at least {} nodes to tolerate {} faults, got {}",
+                3 * fault_tolerance + 1,
+                fault_tolerance,
+                nodes.len()
+            ));
+        }
+        if !nodes.iter().any(|n| n == id) {
+            return Err(format!("Replica {} is not a member of the group", id));
+        }
+        if let Some(missing) = nodes.iter().find(|n| !keyring.contains(n)) {
+            return Err(format!("No public key for {}", missing));
+        }
+        if keyring.get(id) != Some(&key.verifying_key()) {
+            return Err(format!("Signing key does not match {}'s public key", id));
+        }
+        Ok(PbftReplica {
+            id: id.to_string(),
+            key,
+            keyring,
+            nodes,
+            fault_tolerance,
+            context: [0u8; 32],
+            view: 0,
+            next_sequence: 1,
+            rounds: BTreeMap::new(),
+            committed: Vec::new(),
+            validator: None,
+            pending: None,
+            ticks: 0,
+            base_timeout: DEFAULT_VIEW_TIMEOUT,
+            timeout: DEFAULT_VIEW_TIMEOUT,
+            view_change_target: None,
+            view_changes: BTreeMap::new(),
+            new_view_sent: None,
+            evidence: Vec::new(),
+        })
+    }
+
+    /// Sets how many ticks a pending proposal may wait before a view change.
+    pub fn with_view_timeout(mut self, ticks: u64) -> Self {
+        self.base_timeout = ticks.max(1);
+        self.timeout = self.base_timeout;
+        self
+    }
+
+    /// Names the consensus instance this replica takes part in, e.g. with `round_context`.
+    /// Views and sequences start over in every instance; the context sets their votes and
+    /// headers apart, and messages for any other instance are ignored.
+    pub fn with_context(mut self, context: [u8; 32]) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Installs a check every proposal must pass before this replica prepares it.
+    pub fn with_validator(mut self, validator: ProposalValidator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn view(&self) -> u64 {
+        self.view
+    }
+
+    pub fn context(&self) -> [u8; 32] {
+        self.context
+    }
+
+    /// The replica that proposes in the current view.
+    pub fn leader(&self) -> &str {
+        self.leader_of(self.view)
+    }
+
+    /// The replica that proposes in `view`: members take turns in order.
+    pub fn leader_of(&self, view: u64) -> &str {
+        &self.nodes[(view % self.nodes.len() as u64) as usize]
+    }
+
+    /// True while this replica has abandoned its view and waits for the next one.
+    pub fn is_changing_view(&self) -> bool {
+        self.view_change_target.is_some()
+    }
+
+    /// True while a submitted proposal has not yet committed here.
+    pub fn has_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leader() == self.id
+    }
+
+    /// Matching votes needed to move a round forward: 2f+1.
+    pub fn quorum(&self) -> usize {
+        2 * self.fault_tolerance + 1
+    }
+
+    /// Phase this replica has reached for round (`view`, `sequence`).
+    pub fn phase(&self, view: u64, sequence: u64) -> Phase {
+        self.rounds.get(&(view, sequence)).map(|r| r.phase).unwrap_or_default()
+    }
+
+    /// Furthest phase this replica reached for the proposal with `digest` in any view.
+    pub fn proposal_phase(&self, digest: &[u8; 32]) -> Phase {
+        self.rounds
+            .values()
+            .filter(|round| round.digest.as_ref() == Some(digest))
+            .map(|round| round.phase)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Proposals decided so far, in the order they committed.
+    pub fn committed(&self) -> &[CommittedProposal] {
+        &self.committed
+    }
+
+    /// Evidence of double votes and of leaders signing two proposals for one round that this
+    /// replica has received, and the signed headers of proposals its validator rejected, one
+    /// per offence. A validator may reject a proposal
+    /// for more than its Proof-of-Fractal, so check `Evidence::validate` before acting on one.
+    pub fn evidence(&self) -> &[Evidence] {
+        &self.evidence
+    }
+
+    /// Starts a round for `proposal` under the next sequence number.
+    /// Only the leader may propose; the returned pre-prepare must be broadcast to all replicas,
+    /// this one included.
+    pub fn propose(&mut self, proposal: TriadRecord) -> Result<ConsensusMessage, String> {
+        if !self.is_leader() {
+            return Err(format!("{} is not the leader of view {}", self.id, self.view));
+        }
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        Ok(ConsensusMessage::pre_prepare(self.context, self.view, sequence, proposal, &self.id, &self.key))
+    }
+
+    /// Hands this replica a proposal to get committed and starts its view timer. Every
+    /// replica should be given the proposal, so that whichever of them leads can propose it;
+    /// the current leader returns its pre-prepare right away.
+    pub fn submit(&mut self, proposal: TriadRecord) -> Vec<ConsensusMessage> {
+        self.pending = Some(proposal.clone());
+        self.ticks = 0;
+        if self.is_leader() && !self.is_changing_view() {
+            self.propose(proposal).into_iter().collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Advances the view timer by one tick. When a pending proposal has waited out the
+    /// timeout, the replica moves on to the next view and broadcasts a view change; each
+    /// further expiry before a new view is installed skips another view with twice the wait.
+    pub fn tick(&mut self) -> Vec<ConsensusMessage> {
+        if self.pending.is_none() && self.view_change_target.is_none() {
+            return Vec::new();
+        }
+        self.ticks += 1;
+        if self.ticks < self.timeout {
+            return Vec::new();
+        }
+        self.timeout = self.timeout.saturating_mul(2).min(self.base_timeout.saturating_mul(64));
+        let next = self.view_change_target.unwrap_or(self.view) + 1;
+        self.start_view_change(next)
+    }
+
+    /// Processes one message and returns the messages this replica broadcasts in response.
+    /// Messages from non-members, for another context or view or that conflict with what
+    /// was already accepted are ignored.
+    pub fn handle(&mut self, message: ConsensusMessage) -> Vec<ConsensusMessage> {
+        // A message from a non-member, one its sender did not sign or one for another
+        // instance is dropped as if never sent.
+        if !self.nodes.iter().any(|n| n == message.sender())
+            || message.context() != self.context
+            || !message.verify(&self.keyring)
+        {
+            return Vec::new();
+        }
+        match message {
+            ConsensusMessage::ViewChange(change) => self.on_view_change(change),
+            ConsensusMessage::NewView { view, view_changes, proposal, sender, signature, .. } => {
+                self.on_new_view(view, view_changes, proposal, &sender, signature)
+            }
+            ConsensusMessage::Decision { proposal, certificate, .. } => self.on_decision(*proposal, certificate),
+            // A replica that has left its view accepts no new proposals until the next one
+            // is installed.
+            ConsensusMessage::PrePrepare { .. } if self.is_changing_view() => Vec::new(),
+            ConsensusMessage::PrePrepare { view, sequence, proposal, sender, signature, .. } => {
+                self.on_pre_prepare(view, sequence, *proposal, &sender, signature)
+            }
+            ConsensusMessage::Prepare(vote) => {
+                let (view, sequence) = (vote.view, vote.sequence);
+                self.check_double_vote(VoteKind::Prepare, &vote);
+                let round = match self.vote_round(&vote) {
+                    Some(round) => round,
+                    None => return Vec::new(),
+                };
+                round.prepares.entry(vote.digest).or_default().entry(vote.sender.clone()).or_insert(vote);
+                self.advance_current(view, sequence)
+            }
+            ConsensusMessage::Commit(vote) => {
+                let (view, sequence) = (vote.view, vote.sequence);
+                self.check_double_vote(VoteKind::Commit, &vote);
+                let round = match self.vote_round(&vote) {
+                    Some(round) => round,
+                    None => return Vec::new(),
+                };
+                round.commits.entry(vote.digest).or_default().entry(vote.sender.clone()).or_insert(vote);
+                self.advance_current(view, sequence)
+            }
+        }
+    }
+
+    /// Records evidence if the sender of `vote` already cast a `kind` vote for another
+    /// proposal in the same round.
+    fn check_double_vote(&mut self, kind: VoteKind, vote: &Vote) {
+        let Some(round) = self.rounds.get(&(vote.view, vote.sequence)) else {
+            return;
+        };
+        let votes = match kind {
+            VoteKind::Prepare => &round.prepares,
+            VoteKind::Commit => &round.commits,
+        };
+        let earlier = votes
+            .iter()
+            .filter(|(digest, _)| **digest != vote.digest)
+            .find_map(|(_, senders)| senders.get(&vote.sender));
+        if let Some(first) = earlier {
+            let evidence = Evidence::DoubleVote { kind, first: first.clone(), second: vote.clone() };
+            self.record_evidence(evidence);
+        }
+    }
+
+    /// Keeps `evidence` unless this replica already holds evidence of the same offence.
+    fn record_evidence(&mut self, evidence: Evidence) {
+        if self.evidence.iter().all(|e| e.offence() != evidence.offence()) {
+            self.evidence.push(evidence);
+        }
+    }
+
+    /// The round a vote counts toward, or None for a vote from an earlier view. Votes for
+    /// later views are kept: on a network that reorders messages they can arrive before
+    /// the new-view message that installs their view.
+    fn vote_round(&mut self, vote: &Vote) -> Option<&mut Round> {
+        if vote.view < self.view {
+            return None;
+        }
+        Some(self.rounds.entry((vote.view, vote.sequence)).or_default())
+    }
+
+    /// Advances a round only if it belongs to the view this replica is working in.
+    fn advance_current(&mut self, view: u64, sequence: u64) -> Vec<ConsensusMessage> {
+        if view != self.view || self.is_changing_view() {
+            return Vec::new();
+        }
+        self.advance(view, sequence)
+    }
+
+    /// Accepts the leader's proposal for a round, whose header the leader signed with
+    /// `signature`. A proposal the validator rejects is dropped, and the signed header kept
+    /// as evidence against the leader; so is a second, different proposal for the round.
+    fn on_pre_prepare(
+        &mut self,
+        view: u64,
+        sequence: u64,
+        proposal: TriadRecord,
+        sender: &str,
+        signature: Signature,
+    ) -> Vec<ConsensusMessage> {
+        if view != self.view || sender != self.leader() {
+            return Vec::new();
+        }
+        let header = SignedHeader {
+            context: self.context,
+            view,
+            sequence,
+            header: proposal.header.clone(),
+            signer: sender.to_string(),
+            signature,
+        };
+        if self.validator.as_ref().is_some_and(|validate| !validate(&proposal)) {
+            self.record_evidence(Evidence::InvalidPof { header });
+            return Vec::new();
+        }
+        let digest = proposal.hash();
+        let round = self.rounds.entry((view, sequence)).or_default();
+        if round.digest.is_some() {
+            // A second pre-prepare for the same round is either a duplicate or an
+            // equivocating leader; either way the first one stands, but an equivocating
+            // leader's two signed headers are kept as evidence.
+            let first = round.header.clone().filter(|first| first.header.hash() != header.header.hash());
+            if let Some(first) = first {
+                self.record_evidence(Evidence::ConflictingHeaders { first: Box::new(first), second: Box::new(header) });
+            }
+            return Vec::new();
+        }
+        round.digest = Some(digest);
+        round.proposal = Some(proposal);
+        round.header = Some(header);
+        round.phase = Phase::PrePrepared;
+        self.next_sequence = self.next_sequence.max(sequence + 1);
+
+        let mut out = vec![ConsensusMessage::Prepare(self.vote(VoteKind::Prepare, view, sequence, digest))];
+        out.extend(self.advance(view, sequence));
+        out
+    }
+
+    /// Moves a round through prepared and committed once the votes for its digest reach quorum.
+    fn advance(&mut self, view: u64, sequence: u64) -> Vec<ConsensusMessage> {
+        let quorum = self.quorum();
+        let mut out = Vec::new();
+        let round = match self.rounds.get_mut(&(view, sequence)) {
+            Some(round) => round,
+            None => return out,
+        };
+        let digest = match round.digest {
+            Some(digest) => digest,
+            None => return out,
+        };
+        let votes = |set: &HashMap<[u8; 32], BTreeMap<String, Vote>>| set.get(&digest).map_or(0, |s| s.len());
+
+        if round.phase == Phase::PrePrepared && votes(&round.prepares) >= quorum {
+            round.phase = Phase::Prepared;
+            out.push(ConsensusMessage::Commit(Vote::sign(
+                VoteKind::Commit,
+                self.context,
+                view,
+                sequence,
+                digest,
+                &self.id,
+                &self.key,
+            )));
+        }
+        if round.phase == Phase::Prepared && votes(&round.commits) >= quorum {
+            round.phase = Phase::Committed;
+            let proposal = round.proposal.clone().expect("a round with a digest has its proposal");
+            let commits = round.commits[&digest].values();
+            let certificate = QuorumCertificate::from_commits(self.context, view, sequence, digest, &self.nodes, commits);
+            self.record_commit(proposal, certificate);
+        }
+        out
+    }
+
+    fn record_commit(&mut self, proposal: TriadRecord, certificate: QuorumCertificate) {
+        let (view, sequence, digest) = (certificate.view, certificate.sequence, certificate.digest);
+        // A proposal carried into a new view commits again under the same sequence.
+        if !self.committed.iter().any(|c| c.sequence == sequence) {
+            self.committed.push(CommittedProposal { view, sequence, proposal, certificate });
+        }
+        if self.pending.as_ref().is_some_and(|p| p.hash() == digest) {
+            self.pending = None;
+            self.ticks = 0;
+            self.timeout = self.base_timeout;
+        }
+    }
+
+    /// Catches up on a proposal the quorum committed without this replica. The certificate
+    /// stands in for the votes it missed; a view change it started only because that
+    /// proposal seemed stuck is called off.
+    fn on_decision(&mut self, proposal: TriadRecord, certificate: QuorumCertificate) -> Vec<ConsensusMessage> {
+        if certificate.digest != proposal.hash()
+            || !certificate.is_valid(&self.nodes, &self.keyring, self.quorum())
+            || self.committed.iter().any(|c| c.sequence == certificate.sequence)
+        {
+            return Vec::new();
+        }
+        let round = self.rounds.entry((certificate.view, certificate.sequence)).or_default();
+        round.digest = Some(certificate.digest);
+        round.proposal = Some(proposal.clone());
+        round.phase = Phase::Committed;
+        self.next_sequence = self.next_sequence.max(certificate.sequence + 1);
+        self.record_commit(proposal, certificate);
+        if self.pending.is_none() && self.view_change_target.is_some() {
+            self.view_change_target = None;
+            self.ticks = 0;
+        }
+        Vec::new()
+    }
+
+    fn committed_sequence(&self) -> u64 {
+        self.committed.iter().map(|c| c.sequence).max().unwrap_or(0)
+    }
+
+    /// The most recent round this replica prepared but has not committed.
+    fn prepared_certificate(&self) -> Option<PreparedCertificate> {
+        let committed = self.committed_sequence();
+        self.rounds
+            .iter()
+            .filter(|(&(_, sequence), round)| round.phase == Phase::Prepared && sequence > committed)
+            .max_by_key(|(&(view, sequence), _)| (sequence, view))
+            .map(|(&(view, sequence), round)| {
+                let digest = round.digest.expect("a prepared round has a digest");
+                PreparedCertificate {
+                    context: self.context,
+                    view,
+                    sequence,
+                    proposal: Box::new(round.proposal.clone().expect("a prepared round has its proposal")),
+                    prepares: round.prepares[&digest].values().cloned().collect(),
+                }
+            })
+    }
+
+    fn start_view_change(&mut self, new_view: u64) -> Vec<ConsensusMessage> {
+        self.view_change_target = Some(new_view);
+        self.ticks = 0;
+        vec![ConsensusMessage::ViewChange(ViewChange::sign(
+            self.context,
+            new_view,
+            self.committed_sequence(),
+            self.prepared_certificate(),
+            &self.id,
+            &self.key,
+        ))]
+    }
+
+    fn on_view_change(&mut self, change: ViewChange) -> Vec<ConsensusMessage> {
+        if !change.verify(&self.keyring) {
+            return Vec::new();
+        }
+        let mut out: Vec<ConsensusMessage> = self
+            .committed
+            .iter()
+            .filter(|c| c.sequence > change.committed_sequence)
+            .map(|c| ConsensusMessage::Decision {
+                proposal: Box::new(c.proposal.clone()),
+                certificate: c.certificate.clone(),
+                sender: self.id.clone(),
+            })
+            .collect();
+
+        if change.new_view <= self.view || change.prepared.as_ref().is_some_and(|cert| !self.accepts_prepared(cert)) {
+            return out;
+        }
+        let new_view = change.new_view;
+        self.view_changes.entry(new_view).or_default().insert(change.sender.clone(), change);
+
+        // f+1 replicas asking for a later view include at least one honest one, so join them
+        // rather than wait out a timer that would only lead to the same place.
+        let current = self.view_change_target.unwrap_or(self.view);
+        let joinable = self
+            .view_changes
+            .range(current + 1..)
+            .find(|(_, senders)| senders.len() > self.fault_tolerance)
+            .map(|(&view, _)| view);
+        if let Some(view) = joinable {
+            out.extend(self.start_view_change(view));
+            // Our own view change reaches us through the transport like everyone else's.
+        }
+
+        let votes = self.view_changes.get(&new_view).map_or(0, |senders| senders.len());
+        if self.leader_of(new_view) == self.id && votes >= self.quorum() && self.new_view_sent < Some(new_view) {
+            self.new_view_sent = Some(new_view);
+            let view_changes: Vec<ViewChange> = self.view_changes[&new_view].values().cloned().collect();
+            let proposal = Self::new_view_proposal(&view_changes).or_else(|| {
+                let sequence = view_changes.iter().map(|c| c.committed_sequence).max().unwrap_or(0) + 1;
+                self.pending.clone().map(|p| (sequence, Box::new(p)))
+            });
+            out.push(ConsensusMessage::new_view(self.context, new_view, view_changes, proposal, &self.id, &self.key));
+        }
+        out
+    }
+
+    /// The proposal a new view must carry forward: the most recent prepared certificate
+    /// among the view changes, if any.
+    fn new_view_proposal(view_changes: &[ViewChange]) -> Option<(u64, Box<TriadRecord>)> {
+        view_changes
+            .iter()
+            .filter_map(|c| c.prepared.as_ref())
+            .max_by_key(|cert| (cert.view, cert.sequence))
+            .map(|cert| (cert.sequence, cert.proposal.clone()))
+    }
+
+    fn on_new_view(
+        &mut self,
+        view: u64,
+        view_changes: Vec<ViewChange>,
+        proposal: Option<(u64, Box<TriadRecord>)>,
+        sender: &str,
+        signature: Signature,
+    ) -> Vec<ConsensusMessage> {
+        if view <= self.view || sender != self.leader_of(view) {
+            return Vec::new();
+        }
+        let quorum = self.quorum();
+        let mut senders = BTreeSet::new();
+        for change in &view_changes {
+            if change.context != self.context
+                || change.new_view != view
+                || !self.nodes.contains(&change.sender)
+                || !change.verify(&self.keyring)
+                || change.prepared.as_ref().is_some_and(|cert| !self.accepts_prepared(cert))
+            {
+                return Vec::new();
+            }
+            senders.insert(change.sender.as_str());
+        }
+        if senders.len() < quorum {
+            return Vec::new();
+        }
+        // The leader may only choose freely when no prepared proposal has to be carried over,
+        // and then only the next sequence after everything the quorum has committed.
+        match (Self::new_view_proposal(&view_changes), &proposal) {
+            (Some(required), Some(given)) if required != *given => return Vec::new(),
+            (Some(_), None) => return Vec::new(),
+            (None, Some((sequence, _))) => {
+                let committed = view_changes.iter().map(|c| c.committed_sequence).max().unwrap_or(0);
+                if *sequence != committed + 1 {
+                    return Vec::new();
+                }
+            }
+            _ => {}
+        }
+
+        self.view = view;
+        self.view_change_target = None;
+        self.ticks = 0;
+        self.view_changes = self.view_changes.split_off(&(view + 1));
+        match proposal {
+            Some((sequence, record)) => self.on_pre_prepare(view, sequence, *record, sender, signature),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns true if `cert` is a valid prepared certificate from this replica's instance.
+    fn accepts_prepared(&self, cert: &PreparedCertificate) -> bool {
+        cert.context == self.context && cert.is_valid(&self.nodes, &self.keyring, self.quorum())
+    }
+
+    fn vote(&self, kind: VoteKind, view: u64, sequence: u64, digest: [u8; 32]) -> Vote {
+        Vote::sign(kind, self.context, view, sequence, digest, &self.id, &self.key)
+    }
+}
+
+/// Names the consensus instance `members` run for the Triad at `height`: a hash of a domain
+/// tag, the members in order and the height. Replicas given it with `PbftReplica::with_context`
+/// sign it into every vote and header, so that an honest replica's votes in two instances
+/// never count as votes for the same round.
+pub fn round_context(members: &[String], height: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"seirchain-round");
+    for member in members {
+        hasher.update((member.len() as u32).to_le_bytes());
+        hasher.update(member.as_bytes());
+    }
+    hasher.update(height.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Delivers messages to `replicas` until no replica has anything left to receive,
+/// broadcasting every response. Replicas missing from the slice never receive, which is
+/// how tests model crashed or silent nodes.
+pub fn deliver_all<T: ConsensusTransport>(replicas: &mut [PbftReplica], transport: &mut T) {
+    loop {
+        let mut delivered = false;
+        for replica in replicas.iter_mut() {
+            while let Some(message) = transport.receive(replica.id()) {
+                delivered = true;
+                for response in replica.handle(message) {
+                    transport.broadcast(replica.id(), response);
+                }
+            }
+        }
+        if !delivered {
+            break;
+        }
+    }
+}
+
+/// Delivers messages until quiet, then ticks every replica and delivers again, until no
+/// replica has a pending proposal or `max_ticks` ticks have passed. Returns the ticks used.
+pub fn drive<T: ConsensusTransport>(replicas: &mut [PbftReplica], transport: &mut T, max_ticks: u64) -> u64 {
+    deliver_all(replicas, transport);
+    for tick in 0..max_ticks {
+        if replicas.iter().all(|replica| !replica.has_pending()) {
+            return tick;
+        }
+        for replica in replicas.iter_mut() {
+            for message in replica.tick() {
+                transport.broadcast(replica.id(), message);
+            }
+        }
+        transport.tick();
+        deliver_all(replicas, transport);
+    }
+    max_ticks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::security::keys::{node_ids, Keystore};
+    use crate::core::triad_matrix::triad_structure::{Transaction, Triad};
+
+    /// Keys for node1 to node9, plus mallory, who is in no group.
+    fn keys() -> Keystore {
+        let mut ids = node_ids(9);
+        ids.push("mallory".to_string());
+        Keystore::derive(&ids, &[0u8; 32])
+    }
+
+    fn replica(id: &str, nodes: Vec<String>, f: usize) -> Result<PbftReplica, String> {
+        let keys = keys();
+        let key = keys.key(id).cloned().unwrap_or_else(|| keys.key("mallory").unwrap().clone());
+        PbftReplica::new(id, nodes, f, key, keys.keyring())
+    }
+
+    fn replicas(nodes: &[String], f: usize) -> Vec<PbftReplica> {
+        nodes.iter().map(|id| replica(id, nodes.to_vec(), f).unwrap()).collect()
+    }
+
+    fn vote(kind: VoteKind, view: u64, digest: [u8; 32], sender: &str) -> Vote {
+        Vote::sign(kind, [0u8; 32], view, 1, digest, sender, keys().key(sender).unwrap())
+    }
+
+    fn pre_prepare(view: u64, sequence: u64, proposal: TriadRecord, sender: &str) -> ConsensusMessage {
+        ConsensusMessage::pre_prepare([0u8; 32], view, sequence, proposal, sender, keys().key(sender).unwrap())
+    }
+
+    fn proposal(amount: u64) -> TriadRecord {
+        let mut triad = Triad::new();
+        triad.insert_transaction(Transaction {
+            sender: "user1".to_string(),
+            receiver: "user2".to_string(),
+            amount,
+            timestamp: amount,
+        });
+        triad.to_record()
+    }
+
+    #[test]
+    fn test_new_requires_3f_plus_1() {
+        assert!(replica("node1", node_ids(3), 1).is_err());
+        assert!(replica("node9", node_ids(4), 1).is_err());
+        assert!(replica("node1", node_ids(4), 1).is_ok());
+    }
+
+    #[test]
+    fn test_new_checks_keys() {
+        let keys = keys();
+        let node2 = keys.key("node2").unwrap().clone();
+        assert!(PbftReplica::new("node1", node_ids(4), 1, node2, keys.keyring()).is_err());
+        let partial = Keystore::derive(&node_ids(3), &[0u8; 32]);
+        let node1 = partial.key("node1").unwrap().clone();
+        assert!(PbftReplica::new("node1", node_ids(4), 1, node1, partial.keyring()).is_err());
+    }
+
+    #[test]
+    fn test_all_honest_replicas_commit() {
+        let nodes = node_ids(4);
+        let mut group = replicas(&nodes, 1);
+        let mut transport = LocalTransport::new(&nodes);
+
+        let pre_prepare = group[0].propose(proposal(1)).unwrap();
+        transport.broadcast("node1", pre_prepare);
+        deliver_all(&mut group, &mut transport);
+
+        for replica in &group {
+            assert_eq!(replica.phase(0, 1), Phase::Committed, "{}", replica.id());
+            assert_eq!(replica.committed().len(), 1);
+            assert_eq!(replica.committed()[0].proposal, proposal(1));
+            let certificate = &replica.committed()[0].certificate;
+            assert_eq!(certificate.digest, proposal(1).hash());
+            assert!(certificate.is_valid(&nodes, &keys().keyring(), replica.quorum()));
+            assert!(!certificate.is_valid(&nodes[..2], &keys().keyring(), replica.quorum()));
+        }
+        assert_eq!(transport.pending(), 0);
+    }
+
+    #[test]
+    fn test_sequence_numbers_advance_per_round() {
+        let nodes = node_ids(4);
+        let mut group = replicas(&nodes, 1);
+        let mut transport = LocalTransport::new(&nodes);
+
+        for amount in 1..=3 {
+            let pre_prepare = group[0].propose(proposal(amount)).unwrap();
+            transport.broadcast("node1", pre_prepare);
+            deliver_all(&mut group, &mut transport);
+        }
+        for replica in &group {
+            let sequences: Vec<u64> = replica.committed().iter().map(|c| c.sequence).collect();
+            assert_eq!(sequences, vec![1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_commits_with_f_silent_replicas() {
+        let nodes = node_ids(4);
+        let mut group = replicas(&nodes, 1);
+        let mut transport = LocalTransport::new(&nodes);
+
+        let pre_prepare = group[0].propose(proposal(1)).unwrap();
+        transport.broadcast("node1", pre_prepare);
+        // node4 never processes anything.
+        deliver_all(&mut group[..3], &mut transport);
+
+        for replica in &group[..3] {
+            assert_eq!(replica.phase(0, 1), Phase::Committed);
+        }
+        assert_eq!(group[3].phase(0, 1), Phase::Idle);
+    }
+
+    #[test]
+    fn test_no_progress_without_quorum() {
+        let nodes = node_ids(4);
+        let mut group = replicas(&nodes, 1);
+        let mut transport = LocalTransport::new(&nodes);
+
+        let pre_prepare = group[0].propose(proposal(1)).unwrap();
+        transport.broadcast("node1", pre_prepare);
+        // Two of four replicas silent: f = 1 is exceeded, so nobody can prepare.
+        deliver_all(&mut group[..2], &mut transport);
+
+        for replica in &group[..2] {
+            assert_eq!(replica.phase(0, 1), Phase::PrePrepared);
+            assert!(replica.committed().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_only_leader_may_propose() {
+        let nodes = node_ids(4);
+        let mut group = replicas(&nodes, 1);
+        assert!(group[1].propose(proposal(1)).is_err());
+
+        // A pre-prepare from a backup is ignored, and so is one a backup signed in the
+        // leader's name or whose transactions were swapped under the leader's signature.
+        assert!(group[2].handle(pre_prepare(0, 1, proposal(1), "node2")).is_empty());
+        let mut forged = pre_prepare(0, 1, proposal(1), "node2");
+        if let ConsensusMessage::PrePrepare { sender, .. } = &mut forged {
+            *sender = "node1".to_string();
+        }
+        assert!(group[2].handle(forged).is_empty());
+        let mut swapped = pre_prepare(0, 1, proposal(1), "node1");
+        if let ConsensusMessage::PrePrepare { proposal: record, .. } = &mut swapped {
+            record.transactions = proposal(2).transactions;
+        }
+        assert!(group[2].handle(swapped).is_empty());
+        assert_eq!(group[2].phase(0, 1), Phase::Idle);
+    }
+
+    #[test]
+    fn test_conflicting_votes_do_not_count() {
+        let nodes = node_ids(4);
+        let mut replica = replica("node2", nodes.clone(), 1).unwrap();
+        let accepted = proposal(1);
+        let digest = accepted.hash();
+        let own_prepare = replica.handle(pre_prepare(0, 1, accepted, "node1"));
+        assert_eq!(own_prepare.len(), 1);
+
+        // A second pre-prepare for the same round is ignored, but a different one is kept
+        // as evidence against the leader, once.
+        assert!(replica.handle(pre_prepare(0, 1, proposal(1), "node1")).is_empty());
+        assert!(replica.evidence().is_empty());
+        let out = replica.handle(pre_prepare(0, 1, proposal(2), "node1"));
+        assert!(out.is_empty());
+        replica.handle(pre_prepare(0, 1, proposal(2), "node1"));
+        let [evidence @ Evidence::ConflictingHeaders { .. }] = replica.evidence() else { panic!("{:?}", replica.evidence()) };
+        assert_eq!(evidence.offender(), "node1");
+        assert_eq!(evidence.validate(&keys().keyring()), Ok(()));
+
+        // Prepares for another digest never reach quorum for the accepted one.
+        for sender in ["node1", "node3", "node4"] {
+            replica.handle(ConsensusMessage::Prepare(vote(VoteKind::Prepare, 0, proposal(2).hash(), sender)));
+        }
+        assert_eq!(replica.phase(0, 1), Phase::PrePrepared);
+
+        // Outsiders are ignored, duplicates count once.
+        replica.handle(own_prepare[0].clone());
+        for sender in ["node1", "node1", "mallory"] {
+            replica.handle(ConsensusMessage::Prepare(vote(VoteKind::Prepare, 0, digest, sender)));
+        }
+        assert_eq!(replica.phase(0, 1), Phase::PrePrepared);
+
+        let out = replica.handle(ConsensusMessage::Prepare(vote(VoteKind::Prepare, 0, digest, "node3")));
+        assert_eq!(replica.phase(0, 1), Phase::Prepared);
+        assert!(matches!(&out[..], [ConsensusMessage::Commit(vote)] if vote.digest == digest));
+    }
+
+    #[test]
+    fn test_votes_need_valid_signatures() {
+        let nodes = node_ids(4);
+        let mut replica = replica("node2", nodes.clone(), 1).unwrap();
+        let digest = proposal(1).hash();
+        replica.handle(pre_prepare(0, 1, proposal(1), "node1"));
+
+        // node3's name on node4's signature, a signature over another digest, and a
+        // commit signature presented as a prepare are all dropped.
+        let mut forged = vote(VoteKind::Prepare, 0, digest, "node4");
+        forged.sender = "node3".to_string();
+        let mut moved = vote(VoteKind::Prepare, 0, proposal(2).hash(), "node1");
+        moved.digest = digest;
+        let relabelled = vote(VoteKind::Commit, 0, digest, "node4");
+        for bad in [forged, moved, relabelled] {
+            assert!(replica.handle(ConsensusMessage::Prepare(bad)).is_empty());
+        }
+        for sender in ["node1", "node2"] {
+            replica.handle(ConsensusMessage::Prepare(vote(VoteKind::Prepare, 0, digest, sender)));
+        }
+        assert_eq!(replica.phase(0, 1), Phase::PrePrepared);
+
+        let out = replica.handle(ConsensusMessage::Prepare(vote(VoteKind::Prepare, 0, digest, "node3")));
+        assert_eq!(replica.phase(0, 1), Phase::Prepared);
+        assert!(matches!(&out[..], [ConsensusMessage::Commit(v)] if v.verify(VoteKind::Commit, &keys().keyring())));
+    }
+
+    #[test]
+    fn test_quorum_certificate_stands_alone() {
+        let nodes = node_ids(4);
+        let keyring = keys().keyring();
+        let digest = proposal(1).hash();
+        let commits: Vec<Vote> = ["node4", "node1", "node3", "node1", "mallory"]
+            .iter()
+            .map(|sender| vote(VoteKind::Commit, 0, digest, sender))
+            .collect();
+        let certificate = QuorumCertificate::from_commits([0u8; 32], 0, 1, digest, &nodes, &commits);
+
+        // Duplicates and outsiders are left out; signers are listed in group order.
+        assert_eq!(certificate.signers, vec![0b1101]);
+        assert_eq!(certificate.signed_by(&nodes), vec!["node1", "node3", "node4"]);
+        assert!(certificate.is_valid(&nodes, &keyring, 3));
+        assert!(!certificate.is_valid(&nodes, &keyring, 4));
+        // Round-trips through serde and stays well under a kilobyte.
+        let json = serde_json::to_vec(&certificate).unwrap();
+        assert_eq!(serde_json::from_slice::<QuorumCertificate>(&json).unwrap(), certificate);
+        assert!(json.len() < 1024, "{}", json.len());
+
+        let mut tampered = certificate.clone();
+        tampered.sequence = 2;
+        assert!(!tampered.is_valid(&nodes, &keyring, 3));
+        let mut tampered = certificate.clone();
+        tampered.signers = vec![0b0111];
+        assert!(!tampered.is_valid(&nodes, &keyring, 3));
+        let mut tampered = certificate.clone();
+        tampered.signers = vec![0b1_1101];
+        assert!(!tampered.is_valid(&nodes, &keyring, 3));
+        let mut tampered = certificate.clone();
+        tampered.signatures.swap(0, 1);
+        assert!(!tampered.is_valid(&nodes, &keyring, 3));
+
+        // Prepare signatures cannot stand in for commits.
+        let prepares: Vec<Vote> = ["node1", "node2", "node3"].iter().map(|s| vote(VoteKind::Prepare, 0, digest, s)).collect();
+        assert!(!QuorumCertificate::from_commits([0u8; 32], 0, 1, digest, &nodes, &prepares).is_valid(&nodes, &keyring, 3));
+    }
+
+    #[test]
+    fn test_validator_rejects_proposal() {
+        let nodes = node_ids(4);
+        let mut group: Vec<PbftReplica> = nodes
+            .iter()
+            .map(|id| {
+                replica(id, nodes.clone(), 1)
+                    .unwrap()
+                    .with_validator(Box::new(|record: &TriadRecord| !record.transactions.is_empty()))
+            })
+            .collect();
+        let mut transport = LocalTransport::new(&nodes);
+
+        let pre_prepare = group[0].propose(Triad::new().to_record()).unwrap();
+        transport.broadcast("node1", pre_prepare);
+        deliver_all(&mut group, &mut transport);
+        assert!(group.iter().all(|r| r.phase(0, 1) == Phase::Idle));
+    }
+
+    type Rule = Box<dyn FnMut(&str, &str, ConsensusMessage) -> Option<ConsensusMessage>>;
+
+    /// Delivers each broadcast to each recipient through `rule`, which may drop or rewrite it.
+    struct RewritingTransport {
+        inboxes: BTreeMap<String, VecDeque<ConsensusMessage>>,
+        rule: Rule,
+    }
+
+    impl RewritingTransport {
+        fn new(nodes: &[String], rule: Rule) -> Self {
+            RewritingTransport {
+                inboxes: nodes.iter().map(|n| (n.clone(), VecDeque::new())).collect(),
+                rule,
+            }
+        }
+    }
+
+    impl ConsensusTransport for RewritingTransport {
+        fn broadcast(&mut self, from: &str, message: ConsensusMessage) {
+            for (to, inbox) in self.inboxes.iter_mut() {
+                if let Some(message) = (self.rule)(from, to, message.clone()) {
+                    inbox.push_back(message);
+                }
+            }
+        }
+
+        fn receive(&mut self, node: &str) -> Option<ConsensusMessage> {
+            self.inboxes.get_mut(node)?.pop_front()
+        }
+    }
+
+    fn submit_all<T: ConsensusTransport>(group: &mut [PbftReplica], transport: &mut T, record: &TriadRecord) {
+        for replica in group.iter_mut() {
+            for message in replica.submit(record.clone()) {
+                transport.broadcast(replica.id(), message);
+            }
+        }
+    }
+
+    fn timed_replicas(nodes: &[String]) -> Vec<PbftReplica> {
+        replicas(nodes, 1).into_iter().map(|r| r.with_view_timeout(3)).collect()
+    }
+
+    #[test]
+    fn test_leaders_rotate_round_robin() {
+        let replica = replica("node1", node_ids(4), 1).unwrap();
+        let leaders: Vec<&str> = (0..6).map(|view| replica.leader_of(view)).collect();
+        assert_eq!(leaders, vec!["node1", "node2", "node3", "node4", "node1", "node2"]);
+    }
+
+    #[test]
+    fn test_view_change_replaces_silent_leader() {
+        let nodes = node_ids(4);
+        let mut group = timed_replicas(&nodes);
+        let mut transport = RewritingTransport::new(&nodes, Box::new(|from, _, m| (from != "node1").then_some(m)));
+
+        submit_all(&mut group, &mut transport, &proposal(1));
+        drive(&mut group, &mut transport, 100);
+
+        for replica in &group {
+            assert_eq!(replica.view(), 1, "{}", replica.id());
+            assert_eq!(replica.leader(), "node2");
+            assert_eq!(replica.committed().len(), 1);
+            assert_eq!(replica.committed()[0].proposal, proposal(1));
+            assert_eq!(replica.committed()[0].view, 1);
+            assert!(!replica.has_pending());
+        }
+    }
+
+    #[test]
+    fn test_equivocating_leader_is_replaced() {
+        let nodes = node_ids(4);
+        let mut group = timed_replicas(&nodes);
+        // node1 shows node3 and node4 a different proposal than everyone else.
+        let mut transport = RewritingTransport::new(
+            &nodes,
+            Box::new(|from, to, message| match message {
+                ConsensusMessage::PrePrepare { view, sequence, sender, .. }
+                    if from == "node1" && (to == "node3" || to == "node4") =>
+                {
+                    Some(pre_prepare(view, sequence, proposal(2), &sender))
+                }
+                other => Some(other),
+            }),
+        );
+
+        submit_all(&mut group, &mut transport, &proposal(1));
+        drive(&mut group, &mut transport, 100);
+
+        for replica in &group {
+            assert_eq!(replica.phase(0, 1), Phase::PrePrepared, "{}", replica.id());
+            let committed: Vec<&TriadRecord> = replica.committed().iter().map(|c| &c.proposal).collect();
+            assert_eq!(committed, vec![&proposal(1)]);
+            assert_eq!(replica.view(), 1);
+        }
+    }
+
+    #[test]
+    fn test_new_view_carries_prepared_proposal() {
+        let nodes = node_ids(4);
+        let mut group = timed_replicas(&nodes);
+        // Commits in view 0 are lost, so the proposal prepares everywhere but commits nowhere.
+        let mut transport = RewritingTransport::new(
+            &nodes,
+            Box::new(|_, _, message| match message {
+                ConsensusMessage::Commit(vote) if vote.view == 0 => None,
+                other => Some(other),
+            }),
+        );
+
+        submit_all(&mut group, &mut transport, &proposal(1));
+        deliver_all(&mut group, &mut transport);
+        assert!(group.iter().all(|r| r.phase(0, 1) == Phase::Prepared));
+
+        // The next leader would rather propose something else, but must carry the prepared
+        // proposal over under the same sequence.
+        group[1].submit(proposal(2));
+        drive(&mut group, &mut transport, 100);
+        for replica in &group {
+            assert_eq!(replica.phase(1, 1), Phase::Committed, "{}", replica.id());
+            assert_eq!(replica.committed()[0].proposal, proposal(1));
+            assert_eq!(replica.committed()[0].sequence, 1);
+        }
+    }
+
+    #[test]
+    fn test_lagging_replica_catches_up_from_decision() {
+        let nodes = node_ids(4);
+        let mut group = timed_replicas(&nodes);
+        // node4 never sees the proposal, so it cannot commit from votes alone.
+        let mut transport = RewritingTransport::new(
+            &nodes,
+            Box::new(|_, to, message| match message {
+                ConsensusMessage::PrePrepare { .. } if to == "node4" => None,
+                other => Some(other),
+            }),
+        );
+
+        submit_all(&mut group, &mut transport, &proposal(1));
+        drive(&mut group, &mut transport, 100);
+
+        for replica in &group {
+            assert_eq!(replica.committed().len(), 1, "{}", replica.id());
+            assert_eq!(replica.committed()[0].proposal, proposal(1));
+            assert_eq!(replica.view(), 0);
+            assert!(!replica.is_changing_view());
+        }
+        assert_eq!(group[3].phase(0, 1), Phase::Committed);
+    }
+
+    #[test]
+    fn test_new_view_needs_quorum_from_leader() {
+        let nodes = node_ids(4);
+        let mut replica = replica("node3", nodes.clone(), 1).unwrap();
+        let change = |sender: &str| ViewChange::sign([0u8; 32], 1, 0, None, sender, keys().key(sender).unwrap());
+        let new_view = |view_changes: Vec<ViewChange>, sender: &str| {
+            let proposal = Some((1, Box::new(proposal(1))));
+            ConsensusMessage::new_view([0u8; 32], 1, view_changes, proposal, sender, keys().key(sender).unwrap())
+        };
+
+        // Too few view changes, duplicates counted once.
+        replica.handle(new_view(vec![change("node1"), change("node2"), change("node2")], "node2"));
+        assert_eq!(replica.view(), 0);
+        // Not from the leader of view 1.
+        replica.handle(new_view(vec![change("node1"), change("node2"), change("node4")], "node4"));
+        assert_eq!(replica.view(), 0);
+
+        // A view change the leader signed in node4's name.
+        let mut forged = change("node2");
+        forged.sender = "node4".to_string();
+        replica.handle(new_view(vec![change("node1"), change("node2"), forged], "node2"));
+        assert_eq!(replica.view(), 0);
+
+        let out = replica.handle(new_view(vec![change("node1"), change("node2"), change("node4")], "node2"));
+        assert_eq!(replica.view(), 1);
+        assert!(matches!(&out[..], [ConsensusMessage::Prepare(vote)] if vote.view == 1 && vote.sequence == 1));
+    }
+
+    #[test]
+    fn test_next_leader_cannot_forge_view_changes() {
+        let nodes = node_ids(4);
+        let mut honest = vec![
+            replica("node1", nodes.clone(), 1).unwrap(),
+            replica("node3", nodes.clone(), 1).unwrap(),
+            replica("node4", nodes.clone(), 1).unwrap(),
+        ];
+        let byzantine = keys().key("node2").unwrap().clone();
+        let p1 = proposal(1);
+        let p2 = proposal(2);
+
+        // node1 commits p1 at sequence 1 with node2's and node3's votes; node3 only prepares
+        // it, and node4 hears nothing.
+        let pre_prepare = honest[0].propose(p1.clone()).unwrap();
+        for replica in &mut honest[..2] {
+            replica.handle(pre_prepare.clone());
+            for sender in ["node1", "node2", "node3"] {
+                replica.handle(ConsensusMessage::Prepare(vote(VoteKind::Prepare, 0, p1.hash(), sender)));
+            }
+        }
+        for sender in ["node1", "node2", "node3"] {
+            honest[0].handle(ConsensusMessage::Commit(vote(VoteKind::Commit, 0, p1.hash(), sender)));
+        }
+        assert_eq!(honest[0].phase(0, 1), Phase::Committed);
+        assert_eq!(honest[1].phase(0, 1), Phase::Prepared);
+
+        // node2, next in line, claims node3 and node4 asked for view 1 with nothing prepared
+        // and re-proposes sequence 1 with p2.
+        let blank = |sender: &str| {
+            let mut change = ViewChange::sign([0u8; 32], 1, 0, None, "node2", &byzantine);
+            change.sender = sender.to_string();
+            change
+        };
+        let new_view = ConsensusMessage::new_view(
+            [0u8; 32],
+            1,
+            vec![ViewChange::sign([0u8; 32], 1, 0, None, "node2", &byzantine), blank("node3"), blank("node4")],
+            Some((1, Box::new(p2.clone()))),
+            "node2",
+            &byzantine,
+        );
+        for replica in &mut honest {
+            replica.handle(new_view.clone());
+            for sender in ["node2", "node3", "node4"] {
+                replica.handle(ConsensusMessage::Prepare(vote(VoteKind::Prepare, 1, p2.hash(), sender)));
+                replica.handle(ConsensusMessage::Commit(vote(VoteKind::Commit, 1, p2.hash(), sender)));
+            }
+        }
+
+        assert!(honest.iter().all(|r| r.view() == 0));
+        assert_eq!(honest[1].proposal_phase(&p2.hash()), Phase::Idle);
+        assert!(honest.iter().flat_map(|r| r.committed()).all(|c| c.certificate.digest == p1.hash()));
+    }
+
+    #[test]
+    fn test_message_serde_round_trip() {
+        let message = pre_prepare(2, 7, proposal(3), "node3");
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(serde_json::from_str::<ConsensusMessage>(&json).unwrap(), message);
+    }
+}
@@ -1,122 +1,115 @@
-
-use sha2::{Digest, Sha256};
 use std::fmt;
-use rand::rngs::OsRng;
-use rand::RngCore;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
+use std::time::Duration;
+use super::puzzle::{pof_hash, FractalPuzzle, SelfSimilarPuzzle};
+use super::retarget::{self, RetargetConfig};
+use super::solver::{NonceSource, SolveOutcome, SolverConfig};
+use crate::core::triad_matrix::triad_structure::{ProofOfFractalData, Triad};
 
 /// ProofOfFractal represents the Proof-of-Fractal puzzle state and logic.
-pub struct ProofOfFractal {
+/// The puzzle variant is a type parameter and defaults to the self-similar pattern puzzle.
+pub struct ProofOfFractal<P: FractalPuzzle = SelfSimilarPuzzle> {
     pub nonce: AtomicU64,
     pub difficulty: Mutex<u32>,
-    pub target: Mutex<[u8; 32]>,
     pub hash: Mutex<[u8; 32]>,
+    pub puzzle: P,
 }
 
 impl ProofOfFractal {
     /// Creates a new ProofOfFractal with the given difficulty.
     /// Difficulty can be adjusted dynamically based on Triad count or other metrics.
     pub fn new(difficulty: u32) -> Self {
-        let target = ProofOfFractal::calculate_target(difficulty);
+        ProofOfFractal::with_puzzle(SelfSimilarPuzzle, difficulty)
+    }
+}
+
+impl<P: FractalPuzzle> ProofOfFractal<P> {
+    /// Creates a new ProofOfFractal for the given puzzle variant and difficulty.
+    pub fn with_puzzle(puzzle: P, difficulty: u32) -> Self {
         ProofOfFractal {
             nonce: AtomicU64::new(0),
             difficulty: Mutex::new(difficulty),
-            target: Mutex::new(target),
             hash: Mutex::new([0u8; 32]),
+            puzzle,
         }
     }
 
     /// Adjusts difficulty based on Triad count.
     /// For example, difficulty increases logarithmically with triad_count.
+    /// This is a fixed schedule that ignores solve times; prefer `retarget`.
     pub fn adjust_difficulty(&self, triad_count: u64) {
         let base_difficulty = 4;
-        let adjusted = base_difficulty + (64 - triad_count.leading_zeros()) as u32;
-        let new_difficulty = adjusted.min(32);
-        let mut difficulty_guard = self.difficulty.lock().unwrap();
-        *difficulty_guard = new_difficulty;
-        let mut target_guard = self.target.lock().unwrap();
-        *target_guard = ProofOfFractal::calculate_target(new_difficulty);
+        let adjusted = base_difficulty + (64 - triad_count.leading_zeros());
+        *self.difficulty.lock().unwrap() = adjusted.min(32);
     }
 
-    /// Calculates the target hash based on difficulty.
-    /// Higher difficulty means more leading zeros in the target.
-    fn calculate_target(difficulty: u32) -> [u8; 32] {
-        let mut target = [0xffu8; 32];
-        let byte_count = (difficulty / 8) as usize;
-        let bit_count = (difficulty % 8) as usize;
+    /// Retargets difficulty from the timestamps of recently produced triads, oldest first,
+    /// toward `config.target_interval_secs`. Returns the new difficulty.
+    pub fn retarget(&self, timestamps: &[u64], config: &RetargetConfig) -> u32 {
+        let mut difficulty_guard = self.difficulty.lock().unwrap();
+        *difficulty_guard = retarget::retarget(&self.puzzle, *difficulty_guard, timestamps, config);
+        *difficulty_guard
+    }
 
-        for i in 0..byte_count {
-            target[i] = 0x00;
-        }
-        if byte_count < 32 {
-            target[byte_count] = 0xff >> bit_count;
-        }
-        target
+    /// Expected number of hashes to solve the puzzle at the current difficulty.
+    pub fn expected_work(&self) -> f64 {
+        self.puzzle.expected_work(*self.difficulty.lock().unwrap())
     }
 
-    /// Attempts to solve the PoF puzzle by finding a nonce that produces a hash with a self-similar pattern.
+    /// Attempts to solve the PoF puzzle by finding a nonce whose hash meets the puzzle.
     /// Returns true if a valid nonce is found.
     pub fn solve_puzzle(&self, data: &[u8]) -> bool {
-        let mut rng = OsRng;
-        let start_time = std::time::Instant::now();
-        let timeout_secs = (*self.difficulty.lock().unwrap() as u64).pow(2).max(30).min(300);
-        let timeout = std::time::Duration::from_secs(timeout_secs);
-
-        loop {
-            if start_time.elapsed() > timeout {
-                return false;
-            }
-
-            let nonce_candidate = rng.next_u64();
-            let mut hasher = Sha256::new();
-            hasher.update(data);
-            hasher.update(&nonce_candidate.to_le_bytes());
-            let result = hasher.finalize();
-            let mut hash_arr = [0u8; 32];
-            hash_arr.copy_from_slice(&result);
-
-            let difficulty = *self.difficulty.lock().unwrap();
-            if ProofOfFractal::hash_meets_target(&hash_arr, difficulty) {
-                self.nonce.store(nonce_candidate, Ordering::SeqCst);
-                let mut hash_guard = self.hash.lock().unwrap();
-                *hash_guard = hash_arr;
-                return true;
-            }
+        self.solve_with(data, &self.default_solver_config()).is_solved()
+    }
+
+    /// The config `solve_puzzle` uses: OS RNG nonces and a timeout of difficulty² seconds,
+    /// clamped to 30..=300.
+    pub fn default_solver_config(&self) -> SolverConfig {
+        let timeout_secs = (*self.difficulty.lock().unwrap() as u64).pow(2).clamp(30, 300);
+        SolverConfig {
+            timeout: Some(Duration::from_secs(timeout_secs)),
+            ..SolverConfig::new(NonceSource::Os)
         }
     }
 
-    /// Checks if the given hash meets the fractal pattern difficulty.
-    /// The pattern is that a part of the hash is repeated.
-    fn hash_meets_target(hash: &[u8; 32], difficulty: u32) -> bool {
-        let pattern_length = (difficulty as usize).min(8); // Max pattern length of 8 bytes
-        if pattern_length == 0 {
-            return true;
+    /// Searches for a nonce using the source, limits and cancellation token in `config`.
+    /// On success the nonce and hash are stored, so `verify_solution` accepts the same data.
+    pub fn solve_with(&self, data: &[u8], config: &SolverConfig) -> SolveOutcome {
+        let difficulty = *self.difficulty.lock().unwrap();
+        let outcome = self.puzzle.solve(data, difficulty, config);
+        if let SolveOutcome::Solved { nonce, hash, .. } = outcome {
+            self.publish(nonce, hash);
         }
-        let pattern = &hash[0..pattern_length];
-        for i in (pattern_length..hash.len()).step_by(pattern_length) {
-            if i + pattern_length > hash.len() {
-                break;
-            }
-            if &hash[i..i + pattern_length] == pattern {
-                return true;
-            }
+        outcome
+    }
+
+    /// Solves the puzzle for `triad` at the current difficulty over its header
+    /// (see `Triad::pof_preimage`). On success the nonce, difficulty and hash are stored in
+    /// `triad.proof_of_fractal_data`, so any peer can check them with `verify_triad_pof`.
+    pub fn solve_triad(&self, triad: &mut Triad, config: &SolverConfig) -> SolveOutcome {
+        let difficulty = *self.difficulty.lock().unwrap();
+        triad.proof_of_fractal_data.difficulty = difficulty;
+        let outcome = self.puzzle.solve(&triad.pof_preimage(), difficulty, config);
+        if let SolveOutcome::Solved { nonce, hash, .. } = outcome {
+            triad.proof_of_fractal_data = ProofOfFractalData { nonce, difficulty, hash };
+            self.publish(nonce, hash);
         }
-        false
+        outcome
     }
 
-    /// Verifies that the current nonce produces a valid hash below the target.
+    /// Stores a found solution so `verify_solution` and `hash_hex` reflect it.
+    pub fn publish(&self, nonce: u64, hash: [u8; 32]) {
+        self.nonce.store(nonce, Ordering::SeqCst);
+        let mut hash_guard = self.hash.lock().unwrap();
+        *hash_guard = hash;
+    }
+
+    /// Verifies that the current nonce solves the puzzle for `data` at the current difficulty.
     pub fn verify_solution(&self, data: &[u8]) -> bool {
-        let mut hasher = Sha256::new();
-        hasher.update(data);
         let nonce_val = self.nonce.load(Ordering::SeqCst);
-        hasher.update(&nonce_val.to_le_bytes());
-        let result = hasher.finalize();
-        let mut hash_arr = [0u8; 32];
-        hash_arr.copy_from_slice(&result);
-
         let difficulty = *self.difficulty.lock().unwrap();
-        ProofOfFractal::hash_meets_target(&hash_arr, difficulty)
+        self.puzzle.verify(data, nonce_val, difficulty)
     }
 
     /// Resets the PoF state.
@@ -133,7 +126,37 @@ impl ProofOfFractal {
     }
 }
 
-impl fmt::Display for ProofOfFractal {
+/// Checks the Proof-of-Fractal stored in a Triad using only the Triad itself: the stored
+/// hash must be the hash of the header preimage and nonce, and must meet the stored difficulty.
+/// Callers still have to check that the stored difficulty is the one they expect.
+/// Uses the self-similar puzzle; see `verify_triad_pof_with` for other variants.
+pub fn verify_triad_pof(triad: &Triad) -> bool {
+    verify_triad_pof_with(&SelfSimilarPuzzle, triad)
+}
+
+/// `verify_triad_pof` for an arbitrary puzzle variant.
+pub fn verify_triad_pof_with<P: FractalPuzzle>(puzzle: &P, triad: &Triad) -> bool {
+    let pof = &triad.proof_of_fractal_data;
+    let hash_arr = pof_hash(&triad.pof_preimage(), pof.nonce);
+    hash_arr == pof.hash && puzzle.meets_target(&hash_arr, pof.difficulty)
+}
+
+/// `verify_triad_pof_with`, also requiring the Triad to have been solved at `difficulty`.
+/// A Triad claiming a lower difficulty, down to 0, which every hash meets, is rejected.
+pub fn verify_triad_pof_at<P: FractalPuzzle>(puzzle: &P, difficulty: u32, triad: &Triad) -> bool {
+    triad.proof_of_fractal_data.difficulty == difficulty && verify_triad_pof_with(puzzle, triad)
+}
+
+/// Solves `triad` at `difficulty` with the self-similar puzzle, trying nonces upwards from
+/// zero, for tests that need Triads carrying valid Proof-of-Fractal.
+#[cfg(test)]
+pub(crate) fn solved_triad(mut triad: Triad, difficulty: u32) -> Triad {
+    let config = SolverConfig::new(NonceSource::Sequential { start: 0, end: u64::MAX });
+    assert!(ProofOfFractal::new(difficulty).solve_triad(&mut triad, &config).is_solved());
+    triad
+}
+
+impl<P: FractalPuzzle> fmt::Display for ProofOfFractal<P> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let nonce_val = self.nonce.load(Ordering::SeqCst);
         let difficulty_guard = self.difficulty.lock().unwrap();
@@ -150,6 +173,9 @@ impl fmt::Display for ProofOfFractal {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::consensus::puzzle::LeadingZeroPuzzle;
+    use crate::core::consensus::solver::CancellationToken;
+    use crate::core::triad_matrix::triad_structure::Transaction;
 
     #[test]
     fn test_new_proof_of_fractal() {
@@ -166,18 +192,169 @@ mod tests {
     }
 
     #[test]
-    #[ignore]
+    fn test_retarget() {
+        let pof = ProofOfFractal::with_puzzle(LeadingZeroPuzzle, 4);
+        let config = RetargetConfig { target_interval_secs: 10, ..RetargetConfig::default() };
+        assert_eq!(pof.retarget(&[100, 102, 104, 106], &config), 5);
+        assert_eq!(*pof.difficulty.lock().unwrap(), 5);
+        assert_eq!(pof.retarget(&[100, 140, 180], &config), 4);
+
+        // The same intervals are nowhere near a self-similar step's worth of work.
+        let pof = ProofOfFractal::new(4);
+        assert_eq!(pof.retarget(&[100, 102, 104, 106], &config), 4);
+    }
+
+    fn sequential(max_iterations: u64) -> SolverConfig {
+        SolverConfig {
+            max_iterations: Some(max_iterations),
+            ..SolverConfig::new(NonceSource::Sequential { start: 0, end: u64::MAX })
+        }
+    }
+
+    #[test]
     fn test_solve_and_verify() {
         let pof = ProofOfFractal::new(2); // Use a low difficulty for testing
         let data = b"test data";
-        assert!(pof.solve_puzzle(data));
+        assert!(pof.solve_with(data, &sequential(1_000_000)).is_solved());
         assert!(pof.verify_solution(data));
     }
 
+    #[test]
+    fn test_solve_is_deterministic() {
+        let data = b"test data";
+        let config = SolverConfig {
+            max_iterations: Some(1_000_000),
+            ..SolverConfig::new(NonceSource::Seeded(7))
+        };
+        let first = ProofOfFractal::new(2).solve_with(data, &config);
+        let second = ProofOfFractal::new(2).solve_with(data, &config);
+        assert!(first.is_solved());
+        assert_eq!(first, second);
+
+        let a = ProofOfFractal::new(2).solve_with(data, &sequential(1_000_000));
+        let b = ProofOfFractal::new(2).solve_with(data, &sequential(1_000_000));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_solve_respects_iteration_budget() {
+        let pof = ProofOfFractal::new(8);
+        let outcome = pof.solve_with(b"test", &sequential(100));
+        assert_eq!(outcome, SolveOutcome::Exhausted { attempts: 100 });
+        assert_eq!(pof.nonce.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_solve_exhausts_sequential_range() {
+        let pof = ProofOfFractal::new(8);
+        let config = SolverConfig::new(NonceSource::Sequential { start: 10, end: 20 });
+        assert_eq!(pof.solve_with(b"test", &config), SolveOutcome::Exhausted { attempts: 10 });
+    }
+
+    #[test]
+    fn test_solve_cancelled() {
+        let pof = ProofOfFractal::new(8);
+        let token = CancellationToken::new();
+        token.cancel();
+        let config = SolverConfig {
+            cancel: Some(token),
+            ..SolverConfig::new(NonceSource::Os)
+        };
+        assert_eq!(pof.solve_with(b"test", &config), SolveOutcome::Cancelled { attempts: 0 });
+    }
+
+    #[test]
+    fn test_solve_times_out() {
+        let pof = ProofOfFractal::new(8);
+        let config = SolverConfig {
+            timeout: Some(Duration::from_millis(10)),
+            ..SolverConfig::new(NonceSource::Seeded(1))
+        };
+        assert!(matches!(pof.solve_with(b"test", &config), SolveOutcome::TimedOut { .. }));
+    }
+
+    fn sample_triad() -> Triad {
+        let mut triad = Triad::new();
+        triad.insert_transaction(Transaction {
+            sender: "user1".to_string(),
+            receiver: "user2".to_string(),
+            amount: 50,
+            timestamp: 1,
+        });
+        triad.parent_hash = [3u8; 32];
+        triad.timestamp = 1_700_000_000;
+        triad
+    }
+
+    #[test]
+    fn test_solve_and_verify_triad() {
+        let pof = ProofOfFractal::new(2);
+        let mut triad = sample_triad();
+        assert!(!verify_triad_pof(&triad));
+        assert!(pof.solve_triad(&mut triad, &sequential(1_000_000)).is_solved());
+        assert_eq!(triad.proof_of_fractal_data.difficulty, 2);
+        assert!(verify_triad_pof(&triad));
+        assert!(pof.verify_solution(&triad.pof_preimage()));
+    }
+
+    #[test]
+    fn test_triad_pof_binds_header() {
+        let pof = ProofOfFractal::new(2);
+        let mut triad = sample_triad();
+        assert!(pof.solve_triad(&mut triad, &sequential(1_000_000)).is_solved());
+
+        let mut moved = triad.to_record().into_triad();
+        moved.parent_hash = [4u8; 32];
+        assert!(!verify_triad_pof(&moved));
+
+        let mut retimed = triad.to_record().into_triad();
+        retimed.timestamp += 1;
+        assert!(!verify_triad_pof(&retimed));
+
+        let mut edited = triad.to_record().into_triad();
+        edited.insert_transaction(Transaction {
+            sender: "user2".to_string(),
+            receiver: "user3".to_string(),
+            amount: 5,
+            timestamp: 2,
+        });
+        assert!(!verify_triad_pof(&edited));
+
+        // Lowering the claimed difficulty changes the preimage, so the old hash no longer matches.
+        let mut easier = triad.to_record().into_triad();
+        easier.proof_of_fractal_data.difficulty = 1;
+        assert!(!verify_triad_pof(&easier));
+    }
+
+    #[test]
+    fn test_expected_difficulty_is_enforced() {
+        let mut triad = sample_triad();
+        assert!(ProofOfFractal::new(2).solve_triad(&mut triad, &sequential(1_000_000)).is_solved());
+        assert!(verify_triad_pof_at(&SelfSimilarPuzzle, 2, &triad));
+        assert!(!verify_triad_pof_at(&SelfSimilarPuzzle, 3, &triad));
+
+        // Difficulty 0 is met by any hash, so a self-consistent solution costs one hash.
+        let mut free = sample_triad();
+        assert!(ProofOfFractal::new(0).solve_triad(&mut free, &sequential(1)).is_solved());
+        assert!(verify_triad_pof(&free));
+        assert!(!verify_triad_pof_at(&SelfSimilarPuzzle, 2, &free));
+    }
+
+    #[test]
+    fn test_leading_zero_variant() {
+        let pof = ProofOfFractal::with_puzzle(LeadingZeroPuzzle, 8);
+        assert_eq!(pof.expected_work(), 256.0);
+        let mut triad = sample_triad();
+        assert!(pof.solve_triad(&mut triad, &sequential(1_000_000)).is_solved());
+        assert_eq!(triad.proof_of_fractal_data.hash[0], 0);
+        assert!(verify_triad_pof_with(&LeadingZeroPuzzle, &triad));
+        assert!(pof.verify_solution(&triad.pof_preimage()));
+    }
+
     #[test]
     fn test_reset() {
-        let pof = ProofOfFractal::new(4);
-        pof.solve_puzzle(b"test");
+        let pof = ProofOfFractal::new(2);
+        assert!(pof.solve_with(b"test", &sequential(1_000_000)).is_solved());
         pof.reset();
         assert_eq!(pof.nonce.load(Ordering::SeqCst), 0);
         assert_eq!(*pof.hash.lock().unwrap(), [0u8; 32]);
@@ -188,7 +365,7 @@ mod tests {
         let mut hash = [0u8; 32];
         hash[0..2].copy_from_slice(&[1, 2]);
         hash[2..4].copy_from_slice(&[1, 2]);
-        assert!(ProofOfFractal::hash_meets_target(&hash, 2));
+        assert!(SelfSimilarPuzzle.meets_target(&hash, 2));
     }
 
     #[test]
@@ -196,6 +373,6 @@ mod tests {
         let mut hash = [0u8; 32];
         hash[0..2].copy_from_slice(&[1, 2]);
         hash[2..4].copy_from_slice(&[3, 4]);
-        assert!(!ProofOfFractal::hash_meets_target(&hash, 2));
+        assert!(!SelfSimilarPuzzle.meets_target(&hash, 2));
     }
 }
@@ -0,0 +1,213 @@
+// puzzle.rs
+// Puzzle definitions for Proof-of-Fractal: what a valid hash looks like at a given difficulty
+
+use sha2::{Digest, Sha256};
+use std::time::Instant;
+use super::solver::{SolveOutcome, SolverConfig};
+
+/// Hashes the puzzle input followed by the little-endian nonce. Every puzzle judges this hash.
+pub fn pof_hash(data: &[u8], nonce: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.update(nonce.to_le_bytes());
+    let result = hasher.finalize();
+    let mut hash_arr = [0u8; 32];
+    hash_arr.copy_from_slice(&result);
+    hash_arr
+}
+
+/// A Proof-of-Fractal puzzle variant. Implementors decide which hashes meet a difficulty
+/// and how much work that takes; solving and verifying are shared.
+pub trait FractalPuzzle: Send + Sync {
+    /// Returns true if `hash` solves the puzzle at `difficulty`.
+    fn meets_target(&self, hash: &[u8; 32], difficulty: u32) -> bool;
+
+    /// Expected number of hashes needed to find a solution at `difficulty`.
+    fn expected_work(&self, difficulty: u32) -> f64;
+
+    /// Highest difficulty that still adds work; `expected_work` stops growing past it.
+    fn max_difficulty(&self) -> u32;
+
+    /// Searches for a nonce whose hash with `data` meets `difficulty`, within the limits of
+    /// `config`. Touches no shared state, so several searches can run side by side.
+    fn solve(&self, data: &[u8], difficulty: u32, config: &SolverConfig) -> SolveOutcome {
+        let start_time = Instant::now();
+        let mut attempts = 0u64;
+
+        for nonce_candidate in config.source.nonces() {
+            if config.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+                return SolveOutcome::Cancelled { attempts };
+            }
+            if config.max_iterations.is_some_and(|max| attempts >= max) {
+                return SolveOutcome::Exhausted { attempts };
+            }
+            if config.timeout.is_some_and(|t| start_time.elapsed() > t) {
+                return SolveOutcome::TimedOut { attempts };
+            }
+
+            attempts += 1;
+            let hash_arr = pof_hash(data, nonce_candidate);
+            if self.meets_target(&hash_arr, difficulty) {
+                return SolveOutcome::Solved { nonce: nonce_candidate, hash: hash_arr, attempts };
+            }
+        }
+        SolveOutcome::Exhausted { attempts }
+    }
+
+    /// Returns true if `nonce` solves the puzzle for `data` at `difficulty`.
+    fn verify(&self, data: &[u8], nonce: u64, difficulty: u32) -> bool {
+        self.meets_target(&pof_hash(data, nonce), difficulty)
+    }
+}
+
+/// Longest prefix, in bytes, the self-similar puzzle asks to see repeated.
+const MAX_PATTERN_LENGTH: u32 = 8;
+
+/// The fractal puzzle: the first `min(difficulty, 8)` bytes of the hash must reappear
+/// at a later aligned position, so the hash repeats its own prefix.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SelfSimilarPuzzle;
+
+impl FractalPuzzle for SelfSimilarPuzzle {
+    fn meets_target(&self, hash: &[u8; 32], difficulty: u32) -> bool {
+        let pattern_length = difficulty.min(MAX_PATTERN_LENGTH) as usize;
+        if pattern_length == 0 {
+            return true;
+        }
+        let pattern = &hash[0..pattern_length];
+        for i in (pattern_length..hash.len()).step_by(pattern_length) {
+            if i + pattern_length > hash.len() {
+                break;
+            }
+            if &hash[i..i + pattern_length] == pattern {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// With pattern length L there are floor(32 / L) - 1 later positions, each matching
+    /// with probability 2^(-8L), so the success chance per hash is 1 - (1 - 2^(-8L))^k.
+    fn expected_work(&self, difficulty: u32) -> f64 {
+        let pattern_length = difficulty.min(MAX_PATTERN_LENGTH) as i32;
+        if pattern_length == 0 {
+            return 1.0;
+        }
+        let positions = (32 / pattern_length - 1) as f64;
+        let miss = (-(2f64.powi(-8 * pattern_length))).ln_1p();
+        1.0 / -(positions * miss).exp_m1()
+    }
+
+    fn max_difficulty(&self) -> u32 {
+        MAX_PATTERN_LENGTH
+    }
+}
+
+/// The classic target puzzle: the hash must start with `difficulty` zero bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LeadingZeroPuzzle;
+
+impl LeadingZeroPuzzle {
+    /// Calculates the target hash based on difficulty.
+    /// Higher difficulty means more leading zeros in the target.
+    pub fn target(difficulty: u32) -> [u8; 32] {
+        let mut target = [0xffu8; 32];
+        let byte_count = (difficulty.min(256) / 8) as usize;
+        let bit_count = (difficulty % 8) as usize;
+
+        for byte in target.iter_mut().take(byte_count) {
+            *byte = 0x00;
+        }
+        if byte_count < 32 {
+            target[byte_count] = 0xff >> bit_count;
+        }
+        target
+    }
+}
+
+impl FractalPuzzle for LeadingZeroPuzzle {
+    fn meets_target(&self, hash: &[u8; 32], difficulty: u32) -> bool {
+        *hash <= LeadingZeroPuzzle::target(difficulty)
+    }
+
+    fn expected_work(&self, difficulty: u32) -> f64 {
+        2f64.powi(difficulty.min(256) as i32)
+    }
+
+    fn max_difficulty(&self) -> u32 {
+        256
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::solver::NonceSource;
+
+    fn sequential(max_iterations: u64) -> SolverConfig {
+        SolverConfig {
+            max_iterations: Some(max_iterations),
+            ..SolverConfig::new(NonceSource::Sequential { start: 0, end: u64::MAX })
+        }
+    }
+
+    #[test]
+    fn test_leading_zero_target() {
+        assert_eq!(LeadingZeroPuzzle::target(0), [0xffu8; 32]);
+        let target = LeadingZeroPuzzle::target(12);
+        assert_eq!(&target[..3], &[0x00, 0x0f, 0xff]);
+
+        let mut hash = [0xffu8; 32];
+        hash[0] = 0x00;
+        hash[1] = 0x0f;
+        assert!(LeadingZeroPuzzle.meets_target(&hash, 12));
+        assert!(!LeadingZeroPuzzle.meets_target(&hash, 13));
+        assert!(LeadingZeroPuzzle.meets_target(&[0u8; 32], 256));
+    }
+
+    #[test]
+    fn test_expected_work() {
+        assert_eq!(LeadingZeroPuzzle.expected_work(0), 1.0);
+        assert_eq!(LeadingZeroPuzzle.expected_work(10), 1024.0);
+
+        // Difficulty 1: 31 chances of 1/256 each.
+        let d1 = SelfSimilarPuzzle.expected_work(1);
+        assert!((d1 - 1.0 / (1.0 - (255.0f64 / 256.0).powi(31))).abs() < 1e-9);
+        // Difficulty 2: 15 chances of 1/65536 each, so roughly 4369 hashes.
+        assert!((SelfSimilarPuzzle.expected_work(2) - 65536.0 / 15.0).abs() < 1.0);
+        // Work grows with difficulty and stops growing once the pattern is capped at 8 bytes.
+        let cap = SelfSimilarPuzzle.max_difficulty();
+        assert_eq!(cap, 8);
+        for d in 1..cap {
+            assert!(SelfSimilarPuzzle.expected_work(d + 1) > SelfSimilarPuzzle.expected_work(d));
+        }
+        assert_eq!(SelfSimilarPuzzle.expected_work(cap), SelfSimilarPuzzle.expected_work(20));
+        assert!(SelfSimilarPuzzle.expected_work(8).is_finite());
+    }
+
+    /// Both puzzles solve and verify through the shared trait methods, and the attempts
+    /// they need land in the same ballpark as `expected_work`.
+    #[test]
+    fn test_solve_and_verify_each_puzzle() {
+        fn check<P: FractalPuzzle>(puzzle: &P, difficulty: u32) {
+            let mut total = 0u64;
+            for i in 0..20u64 {
+                let data = i.to_le_bytes();
+                match puzzle.solve(&data, difficulty, &sequential(10_000_000)) {
+                    SolveOutcome::Solved { nonce, hash, attempts } => {
+                        assert!(puzzle.verify(&data, nonce, difficulty));
+                        assert_eq!(hash, pof_hash(&data, nonce));
+                        total += attempts;
+                    }
+                    other => panic!("expected a solution, got {:?}", other),
+                }
+            }
+            let mean = total as f64 / 20.0;
+            let expected = puzzle.expected_work(difficulty);
+            assert!(mean > expected / 4.0 && mean < expected * 4.0, "mean {} expected {}", mean, expected);
+        }
+        check(&SelfSimilarPuzzle, 1);
+        check(&SelfSimilarPuzzle, 2);
+        check(&LeadingZeroPuzzle, 8);
+    }
+}
@@ -0,0 +1,191 @@
+// retarget.rs
+// Difficulty retargeting from observed triad production times
+
+use super::puzzle::FractalPuzzle;
+
+/// Parameters of the retarget algorithm.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetargetConfig {
+    /// Desired number of seconds between consecutive triads.
+    pub target_interval_secs: u64,
+    /// Number of triads per retarget period. Difficulty changes once per period and is
+    /// computed from the intervals between the last `window + 1` timestamps.
+    pub window: usize,
+    /// Largest change in difficulty applied by a single retarget, in either direction.
+    pub max_step: u32,
+    pub min_difficulty: u32,
+    /// Upper bound on difficulty; the puzzle's own `max_difficulty` applies if lower.
+    pub max_difficulty: u32,
+}
+
+impl Default for RetargetConfig {
+    fn default() -> Self {
+        RetargetConfig {
+            target_interval_secs: 10,
+            window: 16,
+            max_step: 1,
+            min_difficulty: 1,
+            max_difficulty: 32,
+        }
+    }
+}
+
+/// Average of the last `window` intervals between timestamps, or None if there are fewer
+/// than two timestamps. Timestamps are taken in production order; an out-of-order pair
+/// counts as a zero interval rather than a negative one.
+pub fn observed_interval(timestamps: &[u64], window: usize) -> Option<f64> {
+    let recent = &timestamps[timestamps.len().saturating_sub(window.max(1) + 1)..];
+    if recent.len() < 2 {
+        return None;
+    }
+    let total: u64 = recent.windows(2).map(|pair| pair[1].saturating_sub(pair[0])).sum();
+    Some(total as f64 / (recent.len() - 1) as f64)
+}
+
+/// Returns true when producing the `triad_count`-th triad closed a retarget period,
+/// i.e. the next triad should be mined at a retargeted difficulty.
+/// Retargeting only at period boundaries means every interval measured was produced at
+/// the same difficulty; retargeting after every triad lets stale intervals from earlier
+/// difficulties drive the controller into oscillation.
+pub fn is_retarget_point(triad_count: u64, config: &RetargetConfig) -> bool {
+    let window = config.window.max(1) as u64;
+    triad_count > 0 && triad_count.is_multiple_of(window)
+}
+
+/// Computes the next difficulty for `puzzle` from recent triad timestamps, oldest first.
+/// Call it at each `is_retarget_point`.
+///
+/// Intervals scale with the work a solution takes, so the work wanted next is the current
+/// `expected_work` times target / observed. Of the difficulties within `max_step` of the
+/// current one and within `min_difficulty..=max_difficulty`, capped at the puzzle's own
+/// `max_difficulty`, the one whose expected work is nearest that on a log scale is taken.
+/// How far off the interval must be before a step is taken is up to the puzzle: a step
+/// doubles the work of `LeadingZeroPuzzle` but multiplies that of `SelfSimilarPuzzle` by
+/// hundreds or more. With too few timestamps the difficulty is returned unchanged.
+pub fn retarget<P: FractalPuzzle>(puzzle: &P, current: u32, timestamps: &[u64], config: &RetargetConfig) -> u32 {
+    let observed = match observed_interval(timestamps, config.window) {
+        Some(observed) => observed,
+        None => return current,
+    };
+    let ceiling = config.max_difficulty.min(puzzle.max_difficulty());
+    let floor = config.min_difficulty.min(ceiling);
+    let current = current.clamp(floor, ceiling);
+    let lowest = current.saturating_sub(config.max_step).max(floor);
+    let highest = current.saturating_add(config.max_step).min(ceiling);
+
+    let target = config.target_interval_secs as f64;
+    // A zero interval means triads are arriving faster than the clock resolution.
+    if observed == 0.0 {
+        return highest;
+    }
+    if target == 0.0 {
+        return lowest;
+    }
+    let wanted = puzzle.expected_work(current).ln() + (target / observed).ln();
+    let distance = |difficulty: u32| (puzzle.expected_work(difficulty).ln() - wanted).abs();
+    (lowest..=highest)
+        .min_by(|&a, &b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::puzzle::{LeadingZeroPuzzle, SelfSimilarPuzzle};
+
+    fn evenly_spaced(count: u64, interval: u64) -> Vec<u64> {
+        (0..count).map(|i| 1_000 + i * interval).collect()
+    }
+
+    #[test]
+    fn test_observed_interval() {
+        assert_eq!(observed_interval(&[], 4), None);
+        assert_eq!(observed_interval(&[5], 4), None);
+        assert_eq!(observed_interval(&[0, 100, 110, 120, 130], 3), Some(10.0));
+        assert_eq!(observed_interval(&[0, 100, 110, 120, 130], 4), Some(32.5));
+        assert_eq!(observed_interval(&[10, 5, 20], 8), Some(7.5));
+    }
+
+    #[test]
+    fn test_is_retarget_point() {
+        let config = RetargetConfig { window: 4, ..RetargetConfig::default() };
+        let points: Vec<u64> = (0..13).filter(|&n| is_retarget_point(n, &config)).collect();
+        assert_eq!(points, vec![4, 8, 12]);
+    }
+
+    #[test]
+    fn test_retarget_direction_and_clamp() {
+        let config = RetargetConfig { max_step: 2, ..RetargetConfig::default() };
+        let puzzle = LeadingZeroPuzzle;
+        // On target: unchanged.
+        assert_eq!(retarget(&puzzle, 5, &evenly_spaced(10, 10), &config), 5);
+        // Twice as fast: one step up, since each step doubles the work.
+        assert_eq!(retarget(&puzzle, 5, &evenly_spaced(10, 5), &config), 6);
+        // Far too fast: clamped to max_step.
+        assert_eq!(retarget(&puzzle, 5, &evenly_spaced(10, 0), &config), 7);
+        // Four times too slow: two steps down.
+        assert_eq!(retarget(&puzzle, 5, &evenly_spaced(10, 40), &config), 3);
+        // Never below the minimum.
+        assert_eq!(retarget(&puzzle, 1, &evenly_spaced(10, 1_000), &config), 1);
+        // Not enough history.
+        assert_eq!(retarget(&puzzle, 5, &[1_000], &config), 5);
+    }
+
+    #[test]
+    fn test_retarget_follows_the_puzzle_work() {
+        let config = RetargetConfig { max_step: 2, ..RetargetConfig::default() };
+        let puzzle = SelfSimilarPuzzle;
+        // A self-similar step multiplies the work by hundreds, so being five times too fast
+        // is nearer the current work than the next step's.
+        assert_eq!(retarget(&puzzle, 2, &evenly_spaced(10, 2), &config), 2);
+        let step = puzzle.expected_work(3) / puzzle.expected_work(2);
+        let interval = (10.0 * step) as u64;
+        assert_eq!(retarget(&puzzle, 3, &evenly_spaced(10, interval), &config), 2);
+        // Past the longest pattern more difficulty adds no work, so it is never set.
+        assert_eq!(retarget(&puzzle, 7, &evenly_spaced(10, 0), &config), 8);
+        assert_eq!(retarget(&puzzle, 8, &evenly_spaced(10, 0), &config), 8);
+        assert_eq!(retarget(&puzzle, 12, &evenly_spaced(10, 10), &config), 8);
+    }
+
+    /// Simulates a miner hashing at a fixed rate, whose solve time is the puzzle's expected
+    /// work at the current difficulty, and checks that retargeting once per period settles
+    /// the interval near the target, from starting difficulties both far too low and far
+    /// too high.
+    #[test]
+    fn test_retarget_converges_in_simulation() {
+        let config = RetargetConfig {
+            target_interval_secs: 60,
+            window: 8,
+            max_step: 1,
+            min_difficulty: 1,
+            max_difficulty: 32,
+        };
+        // At 16 hashes a second difficulty 10 takes 64s.
+        let puzzle = LeadingZeroPuzzle;
+        let solve_time = |difficulty: u32| (puzzle.expected_work(difficulty) / 16.0) as u64;
+
+        for start in [1u32, 20] {
+            let mut difficulty = start;
+            let mut timestamps = vec![0u64];
+            let mut history = Vec::new();
+            for count in 1..=200u64 {
+                let now = timestamps[timestamps.len() - 1] + solve_time(difficulty);
+                timestamps.push(now);
+                if is_retarget_point(count, &config) {
+                    difficulty = retarget(&puzzle, difficulty, &timestamps, &config);
+                }
+                history.push(difficulty);
+            }
+
+            let settled = &history[history.len() - 50..];
+            assert!(
+                settled.iter().all(|&d| (9..=10).contains(&d)),
+                "start {} settled at {:?}",
+                start,
+                settled
+            );
+            let interval = observed_interval(&timestamps, 50).unwrap();
+            assert!(interval > 30.0 && interval < 120.0, "start {} interval {}", start, interval);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+// solver.rs
+// Nonce sources, budgets and cancellation for the Proof-of-Fractal solver
+
+use rand::rngs::OsRng;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where the solver draws candidate nonces from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NonceSource {
+    /// Every nonce in `start..end`, in order. The search is exhausted once the range runs out.
+    Sequential { start: u64, end: u64 },
+    /// Nonces from a ChaCha8 generator seeded with the given value, so runs are reproducible.
+    Seeded(u64),
+    /// Nonces from the operating system RNG.
+    Os,
+}
+
+impl NonceSource {
+    /// Returns the stream of candidate nonces for this source.
+    pub fn nonces(&self) -> Box<dyn Iterator<Item = u64> + Send> {
+        match *self {
+            NonceSource::Sequential { start, end } => Box::new(start..end),
+            NonceSource::Seeded(seed) => {
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                Box::new(std::iter::repeat_with(move || rng.next_u64()))
+            }
+            NonceSource::Os => Box::new(std::iter::repeat_with(|| OsRng.next_u64())),
+        }
+    }
+}
+
+/// Shared flag that asks a running solver to stop. Clones observe the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Requests cancellation; every solver holding a clone of this token stops at its next attempt.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Limits and inputs for a single solve. Any combination of limits may be set;
+/// the solver stops at whichever is hit first.
+#[derive(Clone, Debug)]
+pub struct SolverConfig {
+    pub source: NonceSource,
+    /// Maximum number of nonces to try.
+    pub max_iterations: Option<u64>,
+    /// Wall-clock limit for the search.
+    pub timeout: Option<Duration>,
+    pub cancel: Option<CancellationToken>,
+}
+
+impl SolverConfig {
+    /// Creates a config drawing from `source` with no iteration budget, timeout or cancellation.
+    pub fn new(source: NonceSource) -> Self {
+        SolverConfig {
+            source,
+            max_iterations: None,
+            timeout: None,
+            cancel: None,
+        }
+    }
+}
+
+/// Result of a solve attempt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SolveOutcome {
+    /// A nonce meeting the difficulty was found after `attempts` tries.
+    Solved { nonce: u64, hash: [u8; 32], attempts: u64 },
+    /// The nonce source or the iteration budget ran out.
+    Exhausted { attempts: u64 },
+    /// The timeout elapsed before a solution was found.
+    TimedOut { attempts: u64 },
+    /// The cancellation token was triggered.
+    Cancelled { attempts: u64 },
+}
+
+impl SolveOutcome {
+    pub fn is_solved(&self) -> bool {
+        matches!(self, SolveOutcome::Solved { .. })
+    }
+
+    /// Number of nonces tried, whatever the outcome.
+    pub fn attempts(&self) -> u64 {
+        match *self {
+            SolveOutcome::Solved { attempts, .. }
+            | SolveOutcome::Exhausted { attempts }
+            | SolveOutcome::TimedOut { attempts }
+            | SolveOutcome::Cancelled { attempts } => attempts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequential_source() {
+        let nonces: Vec<u64> = NonceSource::Sequential { start: 5, end: 8 }.nonces().collect();
+        assert_eq!(nonces, vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn test_seeded_source_is_reproducible() {
+        let a: Vec<u64> = NonceSource::Seeded(42).nonces().take(16).collect();
+        let b: Vec<u64> = NonceSource::Seeded(42).nonces().take(16).collect();
+        let c: Vec<u64> = NonceSource::Seeded(43).nonces().take(16).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_cancellation_token_is_shared() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        token.cancel();
+        assert!(clone.is_cancelled());
+    }
+}
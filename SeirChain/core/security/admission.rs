@@ -0,0 +1,419 @@
+// admission.rs
+// Anti-Sybil admission: what a new node must present before it can hold paths or join a committee
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr};
+use crate::core::consensus::puzzle::{FractalPuzzle, LeadingZeroPuzzle};
+use crate::core::consensus::solver::{NonceSource, SolveOutcome, SolverConfig};
+use crate::core::security::keys::{Keyring, Keystore};
+use crate::interface::economics::waclanium_token::WaclaniumToken;
+
+/// What a node puts up to make identities costly to mint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Bond {
+    /// A nonce whose hash with the node's ID and public key starts with enough zero bits.
+    Work { nonce: u64 },
+    /// WAC the node has staked in `WaclaniumToken` and keeps staked while admitted.
+    Stake { amount: u64 },
+}
+
+/// Parameters of admission.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdmissionConfig {
+    /// Leading zero bits a work bond must reach.
+    pub work_difficulty: u32,
+    /// Smallest stake bond accepted.
+    pub min_stake_bond: u64,
+    /// Most nodes admitted from one subnet within `window_secs`.
+    pub max_per_subnet: usize,
+    pub window_secs: u64,
+    /// How far a join request's timestamp may lie from the time it is checked, either way.
+    pub max_request_age_secs: u64,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        AdmissionConfig {
+            work_difficulty: 20,
+            min_stake_bond: 100,
+            max_per_subnet: 4,
+            window_secs: 3600,
+            max_request_age_secs: 300,
+        }
+    }
+}
+
+/// A node's signed request to join, binding its ID to a public key, an address and a bond.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JoinRequest {
+    pub node_id: String,
+    pub public_key: VerifyingKey,
+    pub address: IpAddr,
+    pub timestamp: u64,
+    pub bond: Bond,
+    pub signature: Signature,
+}
+
+impl JoinRequest {
+    /// Builds a request for `node_id` signed with `key`.
+    pub fn sign(node_id: &str, address: IpAddr, timestamp: u64, bond: Bond, key: &SigningKey) -> Self {
+        let public_key = key.verifying_key();
+        let message = Self::signing_bytes(node_id, &public_key, &address, timestamp, &bond);
+        JoinRequest {
+            node_id: node_id.to_string(),
+            public_key,
+            address,
+            timestamp,
+            bond,
+            signature: key.sign(&message),
+        }
+    }
+
+    /// Bytes a request signs: every field but the signature.
+    pub fn signing_bytes(node_id: &str, public_key: &VerifyingKey, address: &IpAddr, timestamp: u64, bond: &Bond) -> Vec<u8> {
+        let mut bytes = b"seirchain-join".to_vec();
+        bytes.extend_from_slice(&(node_id.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(node_id.as_bytes());
+        bytes.extend_from_slice(public_key.as_bytes());
+        match address {
+            IpAddr::V4(v4) => bytes.extend_from_slice(&v4.octets()),
+            IpAddr::V6(v6) => bytes.extend_from_slice(&v6.octets()),
+        }
+        bytes.extend_from_slice(&timestamp.to_le_bytes());
+        let (tag, value) = match bond {
+            Bond::Work { nonce } => (0u8, *nonce),
+            Bond::Stake { amount } => (1u8, *amount),
+        };
+        bytes.push(tag);
+        bytes.extend_from_slice(&value.to_le_bytes());
+        bytes
+    }
+
+    /// Returns true if the signature was made by the key the request presents.
+    pub fn verify_signature(&self) -> bool {
+        let message = Self::signing_bytes(&self.node_id, &self.public_key, &self.address, self.timestamp, &self.bond);
+        self.public_key.verify_strict(&message, &self.signature).is_ok()
+    }
+}
+
+/// Input a work bond is hashed over, so work done for one identity cannot be reused for another.
+pub fn work_input(node_id: &str, public_key: &VerifyingKey) -> Vec<u8> {
+    let mut data = node_id.as_bytes().to_vec();
+    data.extend_from_slice(public_key.as_bytes());
+    data
+}
+
+/// Searches for a work bond nonce for `node_id` and `public_key` at `difficulty`.
+pub fn solve_work(node_id: &str, public_key: &VerifyingKey, difficulty: u32) -> Option<u64> {
+    let config = SolverConfig::new(NonceSource::Sequential { start: 0, end: u64::MAX });
+    match LeadingZeroPuzzle.solve(&work_input(node_id, public_key), difficulty, &config) {
+        SolveOutcome::Solved { nonce, .. } => Some(nonce),
+        _ => None,
+    }
+}
+
+/// The subnet an address is rate-limited under: its /24 for IPv4, its /48 for IPv6.
+pub fn subnet_of(address: &IpAddr) -> String {
+    match address {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2])
+        }
+    }
+}
+
+/// AdmissionControl decides which node IDs are real identities. A node is admitted once it
+/// presents a recent signed join request with a valid bond from the address it connects
+/// from, and no subnet may admit more than `max_per_subnet` nodes per window. A node
+/// admitted on a stake bond stays admitted only while it keeps the bond staked.
+#[derive(Clone, Debug, Default)]
+pub struct AdmissionControl {
+    pub config: AdmissionConfig,
+    admitted: BTreeMap<String, VerifyingKey>,
+    stake_bonds: BTreeMap<String, u64>,
+    recent: HashMap<String, VecDeque<u64>>,
+}
+
+impl AdmissionControl {
+    pub fn new(config: AdmissionConfig) -> Self {
+        AdmissionControl {
+            config,
+            admitted: BTreeMap::new(),
+            stake_bonds: BTreeMap::new(),
+            recent: HashMap::new(),
+        }
+    }
+
+    /// Admits the node `request` names at time `now`, checking stake bonds against `token`.
+    /// `peer` is the address the request actually arrived from; the request must name it,
+    /// and it is what the subnet limit counts against.
+    pub fn admit(&mut self, request: &JoinRequest, peer: IpAddr, token: &WaclaniumToken, now: u64) -> Result<(), String> {
+        if request.node_id.is_empty() {
+            return Err("Node ID is empty".to_string());
+        }
+        if self.admitted.contains_key(&request.node_id) {
+            return Err(format!("{} is already admitted", request.node_id));
+        }
+        if self.admitted.values().any(|key| key == &request.public_key) {
+            return Err("Public key already belongs to an admitted node".to_string());
+        }
+        if !request.verify_signature() {
+            return Err("Join request signature is invalid".to_string());
+        }
+        if request.timestamp.abs_diff(now) > self.config.max_request_age_secs {
+            return Err(format!("Join request timestamp {} is too far from {}", request.timestamp, now));
+        }
+        if request.address != peer {
+            return Err(format!("Join request names {} but came from {}", request.address, peer));
+        }
+        match request.bond {
+            Bond::Work { nonce } => {
+                let data = work_input(&request.node_id, &request.public_key);
+                if !LeadingZeroPuzzle.verify(&data, nonce, self.config.work_difficulty) {
+                    return Err("Work bond does not meet the admission difficulty".to_string());
+                }
+            }
+            Bond::Stake { amount } => {
+                if amount < self.config.min_stake_bond {
+                    return Err(format!("Stake bond {} is below the minimum {}", amount, self.config.min_stake_bond));
+                }
+                if token.get_stake(&request.node_id) < amount {
+                    return Err("Stake bond exceeds the node's stake".to_string());
+                }
+            }
+        }
+
+        let recent = self.recent.entry(subnet_of(&peer)).or_default();
+        while recent.front().is_some_and(|&at| now.saturating_sub(at) >= self.config.window_secs) {
+            recent.pop_front();
+        }
+        if recent.len() >= self.config.max_per_subnet {
+            return Err(format!("Too many admissions from {}", subnet_of(&peer)));
+        }
+        recent.push_back(now);
+        self.admitted.insert(request.node_id.clone(), request.public_key);
+        if let Bond::Stake { amount } = request.bond {
+            self.stake_bonds.insert(request.node_id.clone(), amount);
+        }
+        Ok(())
+    }
+
+    /// Revokes every node admitted on a stake bond whose stake in `token` has fallen below
+    /// it, e.g. after unstaking or being slashed.
+    /// Returns the revoked node IDs.
+    pub fn recheck_bonds(&mut self, token: &WaclaniumToken) -> Vec<String> {
+        let broken: Vec<String> = self
+            .stake_bonds
+            .iter()
+            .filter(|(node, &bond)| token.get_stake(node) < bond)
+            .map(|(node, _)| node.clone())
+            .collect();
+        for node in &broken {
+            self.revoke(node);
+        }
+        broken
+    }
+
+    /// Admits every node `keys` holds a key for, as nodes this process runs itself and
+    /// serves from `address`. Each presents a work bond solved here.
+    pub fn admit_keystore(&mut self, keys: &Keystore, address: IpAddr, token: &WaclaniumToken, now: u64) -> Result<(), String> {
+        for node in keys.nodes() {
+            let key = keys.key(&node).ok_or_else(|| format!("No key for {}", node))?;
+            let nonce = solve_work(&node, &key.verifying_key(), self.config.work_difficulty)
+                .ok_or_else(|| format!("No work bond found for {}", node))?;
+            self.admit(&JoinRequest::sign(&node, address, now, Bond::Work { nonce }, key), address, token, now)?;
+        }
+        Ok(())
+    }
+
+    /// Admits every node `keys` holds a key for, as `admit_keystore` does, into a fresh
+    /// admission control for nodes that all run in this process and join from localhost
+    /// now. The subnet limit is raised to cover them all.
+    pub fn admit_local(keys: &Keystore, work_difficulty: u32, token: &WaclaniumToken) -> Result<Self, String> {
+        let config = AdmissionConfig {
+            work_difficulty,
+            max_per_subnet: keys.nodes().len(),
+            ..AdmissionConfig::default()
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut admission = AdmissionControl::new(config);
+        admission.admit_keystore(keys, Ipv4Addr::LOCALHOST.into(), token, now)?;
+        Ok(admission)
+    }
+
+    /// Withdraws a node's admission.
+    /// Returns true if it was admitted.
+    pub fn revoke(&mut self, node_id: &str) -> bool {
+        self.stake_bonds.remove(node_id);
+        self.admitted.remove(node_id).is_some()
+    }
+
+    pub fn is_admitted(&self, node_id: &str) -> bool {
+        self.admitted.contains_key(node_id)
+    }
+
+    /// The key a node was admitted with.
+    pub fn key(&self, node_id: &str) -> Option<&VerifyingKey> {
+        self.admitted.get(node_id)
+    }
+
+    /// The keys of every admitted node, for checking their consensus votes.
+    pub fn keyring(&self) -> Keyring {
+        let mut keyring = Keyring::new();
+        for (node, key) in &self.admitted {
+            keyring.insert(node, *key);
+        }
+        keyring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::security::keys::node_ids;
+
+    fn key(node: &str) -> SigningKey {
+        Keystore::derive(&[node.to_string()], &[9u8; 32]).key(node).unwrap().clone()
+    }
+
+    fn config() -> AdmissionConfig {
+        AdmissionConfig { work_difficulty: 8, max_per_subnet: 2, window_secs: 100, ..AdmissionConfig::default() }
+    }
+
+    /// Admits `request` as arriving from the address it names.
+    fn admit(admission: &mut AdmissionControl, request: &JoinRequest, token: &WaclaniumToken, now: u64) -> Result<(), String> {
+        admission.admit(request, request.address, token, now)
+    }
+
+    fn work_request(node: &str, address: &str) -> JoinRequest {
+        let key = key(node);
+        let nonce = solve_work(node, &key.verifying_key(), config().work_difficulty).unwrap();
+        JoinRequest::sign(node, address.parse().unwrap(), 0, Bond::Work { nonce }, &key)
+    }
+
+    #[test]
+    fn test_bonds() {
+        let mut token = WaclaniumToken::new(0, 1_000_000, 1);
+        let mut admission = AdmissionControl::new(config());
+
+        assert!(admit(&mut admission, &work_request("node1", "10.0.0.1"), &token, 10).is_ok());
+        assert!(admission.is_admitted("node1"));
+        assert_eq!(admission.keyring().get("node1"), Some(&key("node1").verifying_key()));
+
+        // Work done for one identity does not carry over to another.
+        let mut stolen = work_request("node1", "10.0.1.1");
+        stolen.node_id = "node2".to_string();
+        assert!(admit(&mut admission, &stolen, &token, 10).is_err());
+        let Bond::Work { nonce } = stolen.bond else { unreachable!() };
+        let reused = JoinRequest::sign("node2", "10.0.1.1".parse().unwrap(), 0, Bond::Work { nonce }, &key("node2"));
+        assert!(admit(&mut admission, &reused, &token, 10).is_err());
+
+        // A stake bond must be held and meet the minimum.
+        let bond = |amount| JoinRequest::sign("node3", "10.0.2.1".parse().unwrap(), 0, Bond::Stake { amount }, &key("node3"));
+        assert!(admit(&mut admission, &bond(100), &token, 10).is_err());
+        token.mint("node3", 500).unwrap();
+        token.stake("node3", 150).unwrap();
+        assert!(admit(&mut admission, &bond(50), &token, 10).is_err());
+        assert!(admit(&mut admission, &bond(200), &token, 10).is_err());
+        assert!(admit(&mut admission, &bond(150), &token, 10).is_ok());
+        assert!(!admission.is_admitted("node2"));
+    }
+
+    #[test]
+    fn test_identity_checks() {
+        let token = WaclaniumToken::new(0, 1_000_000, 1);
+        let mut admission = AdmissionControl::new(config());
+        let request = work_request("node1", "10.0.0.1");
+        assert!(admit(&mut admission, &request, &token, 10).is_ok());
+        assert!(admit(&mut admission, &request, &token, 10).is_err());
+
+        // The same key cannot back a second identity.
+        let key1 = key("node1");
+        let nonce = solve_work("node9", &key1.verifying_key(), config().work_difficulty).unwrap();
+        let twin = JoinRequest::sign("node9", "10.0.5.1".parse().unwrap(), 0, Bond::Work { nonce }, &key1);
+        assert!(admit(&mut admission, &twin, &token, 10).is_err());
+
+        let mut forged = work_request("node2", "10.0.0.2");
+        forged.timestamp += 1;
+        assert!(admit(&mut admission, &forged, &token, 10).is_err());
+
+        assert!(admission.revoke("node1"));
+        assert!(!admission.is_admitted("node1"));
+        assert!(!admission.revoke("node1"));
+    }
+
+    #[test]
+    fn test_subnet_rate_limit() {
+        let token = WaclaniumToken::new(0, 1_000_000, 1);
+        let mut admission = AdmissionControl::new(config());
+        assert_eq!(subnet_of(&"10.0.0.7".parse().unwrap()), "10.0.0.0/24");
+        assert_eq!(subnet_of(&"2001:db8:1:2::1".parse().unwrap()), "2001:db8:1::/48");
+
+        assert!(admit(&mut admission, &work_request("node1", "10.0.0.1"), &token, 0).is_ok());
+        assert!(admit(&mut admission, &work_request("node2", "10.0.0.2"), &token, 50).is_ok());
+        assert!(admit(&mut admission, &work_request("node3", "10.0.0.3"), &token, 60).is_err());
+        // Another subnet is unaffected, and the first slot frees once the window has passed.
+        assert!(admit(&mut admission, &work_request("node3", "10.0.9.3"), &token, 60).is_ok());
+        assert!(admit(&mut admission, &work_request("node4", "10.0.0.4"), &token, 100).is_ok());
+        assert!(admit(&mut admission, &work_request("node5", "10.0.0.5"), &token, 120).is_err());
+
+        // Naming another subnet does not get round the limit: the request must name the
+        // address it really came from, and that address is what is counted.
+        let elsewhere = work_request("node5", "10.0.7.5");
+        assert!(admission.admit(&elsewhere, "10.0.0.5".parse().unwrap(), &token, 120).is_err_and(|e| e.contains("came from")));
+        assert!(admission.admit(&elsewhere, "10.0.7.5".parse().unwrap(), &token, 120).is_ok());
+    }
+
+    #[test]
+    fn test_stale_requests_are_refused() {
+        let token = WaclaniumToken::new(0, 1_000_000, 1);
+        let mut admission = AdmissionControl::new(config());
+        let max_age = config().max_request_age_secs;
+        let request = work_request("node1", "10.0.0.1");
+        assert!(admit(&mut admission, &request, &token, max_age + 1).is_err_and(|e| e.contains("too far")));
+        let early = JoinRequest { timestamp: max_age + 1, ..request.clone() };
+        assert!(admit(&mut admission, &early, &token, 0).is_err());
+        assert!(admit(&mut admission, &request, &token, max_age).is_ok());
+    }
+
+    #[test]
+    fn test_unstaking_below_the_bond_revokes() {
+        let mut token = WaclaniumToken::new(0, 1_000_000, 1);
+        let mut admission = AdmissionControl::new(config());
+        for (node, address) in [("node1", "10.0.0.1"), ("node2", "10.0.1.1")] {
+            token.mint(node, 500).unwrap();
+            token.stake(node, 200).unwrap();
+            let request = JoinRequest::sign(node, address.parse().unwrap(), 0, Bond::Stake { amount: 150 }, &key(node));
+            admit(&mut admission, &request, &token, 0).unwrap();
+        }
+        assert!(admit(&mut admission, &work_request("node3", "10.0.2.1"), &token, 0).is_ok());
+
+        // Stake above the bond may be withdrawn; going below it costs the admission.
+        token.unstake("node1", 50).unwrap();
+        assert!(admission.recheck_bonds(&token).is_empty());
+        token.unstake("node1", 1).unwrap();
+        assert_eq!(admission.recheck_bonds(&token), vec!["node1".to_string()]);
+        assert!(!admission.is_admitted("node1"));
+        assert!(admission.is_admitted("node2"));
+        assert!(admission.is_admitted("node3"));
+        assert!(admission.recheck_bonds(&token).is_empty());
+    }
+
+    #[test]
+    fn test_admit_local_covers_every_node() {
+        let nodes = node_ids(6);
+        let keys = Keystore::derive(&nodes, &[9u8; 32]);
+        let admission = AdmissionControl::admit_local(&keys, 4, &WaclaniumToken::new(0, 1_000_000, 1)).unwrap();
+        assert_eq!(admission.config.max_per_subnet, 6);
+        assert!(nodes.iter().all(|node| admission.key(node) == Some(&keys.key(node).unwrap().verifying_key())));
+    }
+}
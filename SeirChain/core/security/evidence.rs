@@ -0,0 +1,328 @@
+// evidence.rs
+// Validation of misbehaviour evidence, and the slashing applied once it is committed
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use crate::core::consensus::puzzle::{pof_hash, FractalPuzzle, SelfSimilarPuzzle};
+use crate::core::security::keys::Keyring;
+use crate::core::security::node_scoring::NodeEvent;
+use crate::core::security::redundant_paths::RedundantPathSecurity;
+use crate::core::triad_matrix::encoding::TriadHeader;
+use crate::core::triad_matrix::evidence::Evidence;
+use crate::core::triad_matrix::triad_structure::Triad;
+use crate::interface::economics::waclanium_token::WaclaniumToken;
+
+// Validation sits here rather than with the type in `triad_matrix::evidence` because it
+// judges Proof-of-Fractal with the consensus puzzles.
+impl Evidence {
+    /// Checks the evidence against `keyring`, judging Proof-of-Fractal with the
+    /// self-similar puzzle. See `validate_with` for other variants.
+    pub fn validate(&self, keyring: &Keyring) -> Result<(), String> {
+        self.validate_with(&SelfSimilarPuzzle, keyring)
+    }
+
+    /// `validate` for an arbitrary puzzle variant.
+    pub fn validate_with<P: FractalPuzzle>(&self, puzzle: &P, keyring: &Keyring) -> Result<(), String> {
+        match self {
+            Evidence::DoubleVote { kind, first, second } => {
+                if first.sender != second.sender {
+                    return Err("Votes were cast by different nodes".to_string());
+                }
+                // Views and sequences start over in every consensus instance, so votes only
+                // conflict within the same one.
+                if (first.context, first.view, first.sequence) != (second.context, second.view, second.sequence) {
+                    return Err("Votes are for different rounds".to_string());
+                }
+                if first.digest == second.digest {
+                    return Err("Votes are for the same proposal".to_string());
+                }
+                if !first.verify(*kind, keyring) || !second.verify(*kind, keyring) {
+                    return Err("Vote signature is invalid".to_string());
+                }
+            }
+            Evidence::InvalidPof { header } => {
+                if !header.verify(keyring) {
+                    return Err("Header signature is invalid".to_string());
+                }
+                if header_pof_holds(puzzle, &header.header) {
+                    return Err("Header's Proof-of-Fractal is valid".to_string());
+                }
+            }
+            Evidence::ConflictingHeaders { first, second } => {
+                if first.signer != second.signer {
+                    return Err("Headers were signed by different nodes".to_string());
+                }
+                if (first.context, first.view, first.sequence) != (second.context, second.view, second.sequence) {
+                    return Err("Headers are for different rounds".to_string());
+                }
+                if first.header.hash() == second.header.hash() {
+                    return Err("Headers are the same".to_string());
+                }
+                if !first.verify(keyring) || !second.verify(keyring) {
+                    return Err("Header signature is invalid".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The header-only form of `verify_triad_pof_with`.
+fn header_pof_holds<P: FractalPuzzle>(puzzle: &P, header: &TriadHeader) -> bool {
+    let pof = &header.proof_of_fractal_data;
+    let hash = pof_hash(&header.pof_preimage(), pof.nonce);
+    hash == pof.hash && puzzle.meets_target(&hash, pof.difficulty)
+}
+
+/// How much of an offender's stake is taken, and where it goes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlashingConfig {
+    /// Percentage of the offender's stake taken per offence, capped at 100.
+    pub slash_percent: u64,
+    /// Account credited with slashed stake; None burns it.
+    pub beneficiary: Option<String>,
+}
+
+impl Default for SlashingConfig {
+    fn default() -> Self {
+        SlashingConfig { slash_percent: 10, beneficiary: None }
+    }
+}
+
+/// One penalty applied for one piece of evidence.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlashRecord {
+    pub offender: String,
+    pub evidence_hash: [u8; 32],
+    pub amount: u64,
+    pub at: u64,
+}
+
+/// Slasher turns committed evidence into penalties: it takes a share of the offender's
+/// stake, records the misbehaviour against its score and demotes it. Each offence is
+/// punished once, however many Triads carry evidence of it. Proof-of-Fractal evidence is
+/// judged with `puzzle`, which must be the puzzle the offender's sub-fractal mines with.
+#[derive(Clone, Debug)]
+pub struct Slasher<P: FractalPuzzle = SelfSimilarPuzzle> {
+    pub config: SlashingConfig,
+    pub puzzle: P,
+    processed: HashSet<[u8; 32]>,
+    records: Vec<SlashRecord>,
+}
+
+impl Slasher {
+    /// A slasher for sub-fractals mining the self-similar puzzle.
+    pub fn new(config: SlashingConfig) -> Self {
+        Slasher::with_puzzle(SelfSimilarPuzzle, config)
+    }
+}
+
+impl Default for Slasher {
+    fn default() -> Self {
+        Slasher::new(SlashingConfig::default())
+    }
+}
+
+impl<P: FractalPuzzle> Slasher<P> {
+    /// A slasher for sub-fractals mining `puzzle`.
+    pub fn with_puzzle(puzzle: P, config: SlashingConfig) -> Self {
+        Slasher { config, puzzle, processed: HashSet::new(), records: Vec::new() }
+    }
+
+    /// Validates `evidence` and penalises its offender at time `now`.
+    pub fn slash(
+        &mut self,
+        evidence: &Evidence,
+        keyring: &Keyring,
+        token: &mut WaclaniumToken,
+        security: &mut RedundantPathSecurity,
+        now: u64,
+    ) -> Result<SlashRecord, String> {
+        if self.processed.contains(&evidence.offence()) {
+            return Err("Offence was already punished".to_string());
+        }
+        evidence.validate_with(&self.puzzle, keyring)?;
+
+        let offender = evidence.offender();
+        // At most the whole stake, so the share always fits back into a u64.
+        let share = token.get_stake(offender) as u128 * self.config.slash_percent.min(100) as u128 / 100;
+        let share = share as u64;
+        let amount = token.slash(offender, share, self.config.beneficiary.as_deref());
+        security.record_event(offender, NodeEvent::Misbehaviour, now);
+        security.remove_promoted_node(offender);
+        // The slashed stake may no longer cover the offender's admission bond.
+        security.recheck_bonds(token);
+
+        let record = SlashRecord { offender: offender.to_string(), evidence_hash: evidence.hash(), amount, at: now };
+        self.processed.insert(evidence.offence());
+        self.records.push(record.clone());
+        Ok(record)
+    }
+
+    /// Slashes for every piece of evidence a committed Triad carries, at the Triad's
+    /// timestamp. Evidence that fails validation or of an offence already punished is skipped.
+    pub fn slash_triad(
+        &mut self,
+        triad: &Triad,
+        keyring: &Keyring,
+        token: &mut WaclaniumToken,
+        security: &mut RedundantPathSecurity,
+    ) -> Vec<SlashRecord> {
+        triad
+            .evidence
+            .iter()
+            .filter_map(|evidence| self.slash(evidence, keyring, token, security, triad.timestamp).ok())
+            .collect()
+    }
+
+    /// Every penalty applied, oldest first.
+    pub fn records(&self) -> &[SlashRecord] {
+        &self.records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::proof_of_fractal::ProofOfFractal;
+    use crate::core::consensus::puzzle::LeadingZeroPuzzle;
+    use crate::core::consensus::solver::{NonceSource, SolverConfig};
+    use crate::core::security::keys::Keystore;
+    use crate::core::triad_matrix::evidence::{SignedHeader, Vote, VoteKind};
+
+    fn keys() -> Keystore {
+        Keystore::derive(&["node1".to_string(), "node2".to_string()], &[3u8; 32])
+    }
+
+    fn vote(digest: u8, sender: &str) -> Vote {
+        vote_in([0u8; 32], digest, sender)
+    }
+
+    fn vote_in(context: [u8; 32], digest: u8, sender: &str) -> Vote {
+        Vote::sign(VoteKind::Prepare, context, 0, 1, [digest; 32], sender, keys().key(sender).unwrap())
+    }
+
+    fn signed(sequence: u64, timestamp: u64, signer: &str) -> SignedHeader {
+        signed_in([0u8; 32], sequence, timestamp, signer)
+    }
+
+    fn signed_in(context: [u8; 32], sequence: u64, timestamp: u64, signer: &str) -> SignedHeader {
+        let mut triad = Triad::new();
+        triad.timestamp = timestamp;
+        SignedHeader::sign(context, 0, sequence, triad.header(), signer, keys().key(signer).unwrap())
+    }
+
+    fn conflicting(first: SignedHeader, second: SignedHeader) -> Evidence {
+        Evidence::ConflictingHeaders { first: Box::new(first), second: Box::new(second) }
+    }
+
+    #[test]
+    fn test_double_vote() {
+        let keyring = keys().keyring();
+        let evidence = Evidence::DoubleVote { kind: VoteKind::Prepare, first: vote(1, "node1"), second: vote(2, "node1") };
+        assert!(evidence.validate(&keyring).is_ok());
+        assert_eq!(evidence.offender(), "node1");
+
+        let same = Evidence::DoubleVote { kind: VoteKind::Prepare, first: vote(1, "node1"), second: vote(1, "node1") };
+        assert!(same.validate(&keyring).is_err());
+        let different_nodes = Evidence::DoubleVote { kind: VoteKind::Prepare, first: vote(1, "node1"), second: vote(2, "node2") };
+        assert!(different_nodes.validate(&keyring).is_err());
+        // Prepares cannot be passed off as commits.
+        let wrong_kind = Evidence::DoubleVote { kind: VoteKind::Commit, first: vote(1, "node1"), second: vote(2, "node1") };
+        assert!(wrong_kind.validate(&keyring).is_err());
+        // Votes in different consensus instances never conflict, whatever their rounds.
+        let other_instance = vote_in([1u8; 32], 2, "node1");
+        let different_contexts = Evidence::DoubleVote { kind: VoteKind::Prepare, first: vote(1, "node1"), second: other_instance };
+        assert!(different_contexts.validate(&keyring).is_err());
+    }
+
+    #[test]
+    fn test_header_evidence() {
+        let keyring = keys().keyring();
+        // An unsolved header never meets its difficulty.
+        assert!(Evidence::InvalidPof { header: signed(1, 5, "node1") }.validate(&keyring).is_ok());
+        let mut forged = signed(1, 5, "node1");
+        forged.signer = "node2".to_string();
+        assert!(Evidence::InvalidPof { header: forged }.validate(&keyring).is_err());
+
+        let conflict = conflicting(signed(1, 5, "node2"), signed(1, 6, "node2"));
+        assert!(conflict.validate(&keyring).is_ok());
+        let successive = conflicting(signed(1, 5, "node2"), signed(2, 6, "node2"));
+        assert!(successive.validate(&keyring).is_err());
+        let other_instance = conflicting(signed(1, 5, "node2"), signed_in([1u8; 32], 1, 6, "node2"));
+        assert!(other_instance.validate(&keyring).is_err());
+        let repeated = conflicting(signed(1, 5, "node2"), signed(1, 5, "node2"));
+        assert!(repeated.validate(&keyring).is_err());
+    }
+
+    #[test]
+    fn test_slashing() {
+        let keyring = keys().keyring();
+        let mut token = WaclaniumToken::new(0, 1_000_000, 1);
+        token.mint("node1", 1_000).unwrap();
+        token.stake("node1", 500).unwrap();
+        let mut security = RedundantPathSecurity::new();
+        security.promote_node("node1");
+
+        let mut slasher = Slasher::default();
+        let evidence = Evidence::DoubleVote { kind: VoteKind::Prepare, first: vote(1, "node1"), second: vote(2, "node1") };
+        let record = slasher.slash(&evidence, &keyring, &mut token, &mut security, 10).unwrap();
+        assert_eq!(record.amount, 50);
+        assert_eq!(token.get_stake("node1"), 450);
+        assert_eq!(token.total_supply(), 950);
+        assert!(!security.is_node_promoted("node1"));
+        assert_eq!(security.scoring.stats("node1").unwrap().misbehaviours, 1);
+
+        // The same offence is punished once, however its evidence is presented.
+        assert!(slasher.slash(&evidence, &keyring, &mut token, &mut security, 11).is_err());
+        let swapped = Evidence::DoubleVote { kind: VoteKind::Prepare, first: vote(2, "node1"), second: vote(1, "node1") };
+        assert_ne!(swapped.hash(), evidence.hash());
+        assert!(slasher.slash(&swapped, &keyring, &mut token, &mut security, 11).is_err());
+        assert_eq!(slasher.records().len(), 1);
+
+        // Redistributed stake goes to the beneficiary instead of being burnt.
+        let mut slasher = Slasher::new(SlashingConfig { slash_percent: 20, beneficiary: Some("treasury".to_string()) });
+        let mut triad = Triad::new();
+        triad.timestamp = 20;
+        triad.evidence = vec![
+            conflicting(signed(1, 5, "node1"), signed(1, 6, "node1")),
+            Evidence::DoubleVote { kind: VoteKind::Prepare, first: vote(1, "node1"), second: vote(1, "node1") },
+        ];
+        let records = slasher.slash_triad(&triad, &keyring, &mut token, &mut security);
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].amount, records[0].at), (90, 20));
+        assert_eq!(token.get_balance("treasury"), 90);
+        assert_eq!(token.total_supply(), 950);
+    }
+
+    #[test]
+    fn test_slashing_large_stake() {
+        let keyring = keys().keyring();
+        let mut token = WaclaniumToken::new(0, u64::MAX, 1);
+        token.mint("node1", u64::MAX).unwrap();
+        token.stake("node1", u64::MAX).unwrap();
+        let mut slasher = Slasher::new(SlashingConfig { slash_percent: 50, beneficiary: None });
+        let evidence = Evidence::DoubleVote { kind: VoteKind::Prepare, first: vote(1, "node1"), second: vote(2, "node1") };
+        let record = slasher.slash(&evidence, &keyring, &mut token, &mut RedundantPathSecurity::new(), 1).unwrap();
+        assert_eq!(record.amount, u64::MAX / 2);
+    }
+
+    #[test]
+    fn test_slashing_judges_pof_with_the_sub_fractal_puzzle() {
+        let keyring = keys().keyring();
+        // A header solved with the leading-zero puzzle fails the self-similar one.
+        let mut triad = Triad::new();
+        let config = SolverConfig::new(NonceSource::Sequential { start: 0, end: u64::MAX });
+        assert!(ProofOfFractal::with_puzzle(LeadingZeroPuzzle, 4).solve_triad(&mut triad, &config).is_solved());
+        let evidence = Evidence::InvalidPof { header: SignedHeader::sign([0u8; 32], 0, 1, triad.header(), "node1", keys().key("node1").unwrap()) };
+
+        let mut token = WaclaniumToken::new(0, 1_000_000, 1);
+        token.mint("node1", 1_000).unwrap();
+        token.stake("node1", 1_000).unwrap();
+        let mut security = RedundantPathSecurity::new();
+        let mut slasher = Slasher::with_puzzle(LeadingZeroPuzzle, SlashingConfig::default());
+        assert!(slasher.slash(&evidence, &keyring, &mut token, &mut security, 1).is_err());
+        assert_eq!(token.get_stake("node1"), 1_000);
+        assert!(Slasher::default().slash(&evidence, &keyring, &mut token, &mut security, 1).is_ok());
+    }
+}
@@ -0,0 +1,138 @@
+// keys.rs
+// Node signing keys, and the keyring of public keys that consensus votes are checked against
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// IDs `node1` to `node{count}`, the names simulations and tests give their nodes.
+pub fn node_ids(count: usize) -> Vec<String> {
+    (1..=count).map(|i| format!("node{}", i)).collect()
+}
+
+/// Signing keys held by this process, by node ID. A deployed node holds only its own; a
+/// simulation that runs a whole sub-fractal in-process holds one per node it runs.
+#[derive(Clone, Default)]
+pub struct Keystore {
+    keys: BTreeMap<String, SigningKey>,
+}
+
+impl Keystore {
+    pub fn new() -> Self {
+        Keystore::default()
+    }
+
+    /// Generates a fresh key for each of `nodes`.
+    pub fn generate<R: RngCore + CryptoRng>(nodes: &[String], rng: &mut R) -> Self {
+        Keystore {
+            keys: nodes.iter().map(|node| (node.clone(), SigningKey::generate(rng))).collect(),
+        }
+    }
+
+    /// Derives each node's key from SHA-256 over `seed` and its ID. Anyone who knows the
+    /// seed knows every key, so this is only for simulations and tests, where it keeps
+    /// seeded runs reproducible.
+    pub fn derive(nodes: &[String], seed: &[u8; 32]) -> Self {
+        let keys = nodes
+            .iter()
+            .map(|node| {
+                let mut hasher = Sha256::new();
+                hasher.update(seed);
+                hasher.update(node.as_bytes());
+                (node.clone(), SigningKey::from_bytes(&hasher.finalize().into()))
+            })
+            .collect();
+        Keystore { keys }
+    }
+
+    /// Adds or replaces the key for `node`.
+    pub fn insert(&mut self, node: &str, key: SigningKey) {
+        self.keys.insert(node.to_string(), key);
+    }
+
+    pub fn key(&self, node: &str) -> Option<&SigningKey> {
+        self.keys.get(node)
+    }
+
+    /// The node IDs this store holds keys for.
+    pub fn nodes(&self) -> Vec<String> {
+        self.keys.keys().cloned().collect()
+    }
+
+    /// Signs `message` as `node`, or returns None if this store lacks its key.
+    pub fn sign(&self, node: &str, message: &[u8]) -> Option<Signature> {
+        self.keys.get(node).map(|key| key.sign(message))
+    }
+
+    /// The public half of every key held.
+    pub fn keyring(&self) -> Keyring {
+        Keyring {
+            keys: self.keys.iter().map(|(node, key)| (node.clone(), key.verifying_key())).collect(),
+        }
+    }
+}
+
+/// Public keys of the nodes a verifier recognises, by node ID.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keyring {
+    keys: BTreeMap<String, VerifyingKey>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Keyring::default()
+    }
+
+    /// Adds or replaces the public key for `node`.
+    pub fn insert(&mut self, node: &str, key: VerifyingKey) {
+        self.keys.insert(node.to_string(), key);
+    }
+
+    pub fn get(&self, node: &str) -> Option<&VerifyingKey> {
+        self.keys.get(node)
+    }
+
+    pub fn contains(&self, node: &str) -> bool {
+        self.keys.contains_key(node)
+    }
+
+    /// Returns true if `signature` over `message` was made by `node`'s key. Unknown nodes
+    /// never verify.
+    pub fn verify(&self, node: &str, message: &[u8], signature: &Signature) -> bool {
+        self.keys.get(node).is_some_and(|key| key.verify_strict(message, signature).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes() -> Vec<String> {
+        vec!["node1".to_string(), "node2".to_string()]
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let keystore = Keystore::generate(&nodes(), &mut rand::rngs::OsRng);
+        let keyring = keystore.keyring();
+        let signature = keystore.sign("node1", b"triad").unwrap();
+
+        assert!(keyring.verify("node1", b"triad", &signature));
+        assert!(!keyring.verify("node2", b"triad", &signature));
+        assert!(!keyring.verify("node1", b"other", &signature));
+        assert!(!keyring.verify("node3", b"triad", &signature));
+        assert!(keystore.sign("node3", b"triad").is_none());
+    }
+
+    #[test]
+    fn test_derived_keys_are_reproducible() {
+        let a = Keystore::derive(&nodes(), &[1u8; 32]);
+        let b = Keystore::derive(&nodes(), &[1u8; 32]);
+        let c = Keystore::derive(&nodes(), &[2u8; 32]);
+        assert_eq!(a.keyring(), b.keyring());
+        assert_ne!(a.keyring(), c.keyring());
+        assert_ne!(a.keyring().get("node1"), a.keyring().get("node2"));
+    }
+}
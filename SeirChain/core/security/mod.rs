@@ -1 +1,5 @@
+pub mod admission;
+pub mod evidence;
+pub mod keys;
+pub mod node_scoring;
 pub mod redundant_paths;
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// Something a node did that bears on whether it should be promoted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeEvent {
+    /// The node proposed a Triad whose Proof-of-Fractal solution committed.
+    PofSolved,
+    /// The node cast a vote toward a quorum that committed.
+    ValidVote,
+    /// The node reported in as alive.
+    Heartbeat,
+    /// The node was caught misbehaving, e.g. equivocating or voting twice.
+    Misbehaviour,
+}
+
+/// Weights, decay and thresholds of the scoring subsystem.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScoringConfig {
+    pub pof_solved: f64,
+    pub valid_vote: f64,
+    pub heartbeat: f64,
+    /// Subtracted for each misbehaviour.
+    pub misbehaviour_penalty: f64,
+    /// Seconds over which a score halves when nothing new is recorded.
+    pub half_life_secs: u64,
+    /// A node is promoted once its score reaches this.
+    pub promote_threshold: f64,
+    /// A promoted node is demoted once its score falls below this. Keeping it under the
+    /// promotion threshold stops a node hovering near one value from flapping.
+    pub demote_threshold: f64,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        ScoringConfig {
+            pof_solved: 10.0,
+            valid_vote: 1.0,
+            heartbeat: 0.5,
+            misbehaviour_penalty: 50.0,
+            half_life_secs: 3600,
+            promote_threshold: 50.0,
+            demote_threshold: 20.0,
+        }
+    }
+}
+
+/// A node's decayed score as of `updated_at`, and counts of what it was built from.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NodeScore {
+    pub score: f64,
+    pub updated_at: u64,
+    pub pof_solves: u64,
+    pub valid_votes: u64,
+    pub heartbeats: u64,
+    pub misbehaviours: u64,
+}
+
+/// Direction of a promotion change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PromotionChange {
+    Promoted,
+    Demoted,
+}
+
+/// One automatic promotion or demotion, with the score that triggered it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PromotionRecord {
+    pub node: String,
+    pub change: PromotionChange,
+    pub at: u64,
+    pub score: f64,
+}
+
+/// NodeScoring keeps a decaying performance score per node. Times are in seconds, e.g.
+/// Triad timestamps.
+#[derive(Clone, Debug, Default)]
+pub struct NodeScoring {
+    pub config: ScoringConfig,
+    scores: HashMap<String, NodeScore>,
+}
+
+impl NodeScoring {
+    /// Creates a scoring subsystem with the given configuration and no scores.
+    pub fn new(config: ScoringConfig) -> Self {
+        NodeScoring {
+            config,
+            scores: HashMap::new(),
+        }
+    }
+
+    /// Decays `score` from its last update to `now`. Times before the last update count
+    /// as no time at all.
+    fn decayed(&self, score: &NodeScore, now: u64) -> f64 {
+        let elapsed = now.saturating_sub(score.updated_at) as f64;
+        let half_life = self.config.half_life_secs.max(1) as f64;
+        score.score * 0.5f64.powf(elapsed / half_life)
+    }
+
+    /// Records `event` for `node` at `now` and returns its new score.
+    pub fn record(&mut self, node: &str, event: NodeEvent, now: u64) -> f64 {
+        let weight = match event {
+            NodeEvent::PofSolved => self.config.pof_solved,
+            NodeEvent::ValidVote => self.config.valid_vote,
+            NodeEvent::Heartbeat => self.config.heartbeat,
+            NodeEvent::Misbehaviour => -self.config.misbehaviour_penalty,
+        };
+        let current = self.scores.get(node).map_or(0.0, |score| self.decayed(score, now));
+        let entry = self.scores.entry(node.to_string()).or_default();
+        entry.score = current + weight;
+        entry.updated_at = entry.updated_at.max(now);
+        match event {
+            NodeEvent::PofSolved => entry.pof_solves += 1,
+            NodeEvent::ValidVote => entry.valid_votes += 1,
+            NodeEvent::Heartbeat => entry.heartbeats += 1,
+            NodeEvent::Misbehaviour => entry.misbehaviours += 1,
+        }
+        entry.score
+    }
+
+    /// The score of `node` decayed to `now`; 0 for a node never scored.
+    pub fn score(&self, node: &str, now: u64) -> f64 {
+        self.scores.get(node).map_or(0.0, |score| self.decayed(score, now))
+    }
+
+    /// What has been recorded for `node`, as of its last update.
+    pub fn stats(&self, node: &str) -> Option<&NodeScore> {
+        self.scores.get(node)
+    }
+
+    /// Every node with a score, in no particular order.
+    pub fn nodes(&self) -> Vec<String> {
+        self.scores.keys().cloned().collect()
+    }
+
+    /// The change `node` is due at `now` given whether it is `promoted`, if any.
+    pub fn decide(&self, node: &str, promoted: bool, now: u64) -> Option<PromotionChange> {
+        let score = self.score(node, now);
+        if !promoted && score >= self.config.promote_threshold {
+            Some(PromotionChange::Promoted)
+        } else if promoted && score < self.config.demote_threshold {
+            Some(PromotionChange::Demoted)
+        } else {
+            None
+        }
+    }
+}
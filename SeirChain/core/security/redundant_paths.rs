@@ -1,5 +1,43 @@
 use std::collections::HashSet;
+use std::net::IpAddr;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use crate::core::security::admission::{AdmissionConfig, AdmissionControl, JoinRequest};
+use crate::core::security::node_scoring::{NodeEvent, NodeScoring, PromotionChange, PromotionRecord, ScoringConfig};
+use crate::interface::economics::waclanium_token::WaclaniumToken;
+use crate::network::routing::multi_path_fractal::MultiPathFractalRouting;
+
+/// Node-disjoint paths that must agree on a triad before it is accepted, unless configured otherwise.
+pub const DEFAULT_MIN_DISJOINT_PATHS: usize = 2;
+
+/// A triad hash as it arrived over one path, listed from the sending node to the receiving one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathDelivery {
+    pub path: Vec<String>,
+    pub triad_hash: [u8; 32],
+}
+
+/// The outcome of checking a triad against the hashes delivered over several paths.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathValidation {
+    /// Node-disjoint paths needed for acceptance.
+    pub required: usize,
+    /// As many node-disjoint routes as there are over the hops that delivered the triad's hash.
+    pub agreeing: Vec<Vec<String>>,
+    /// Paths that delivered the triad's hash but are not in `agreeing`, since they share a node with it.
+    pub overlapping: Vec<Vec<String>>,
+    /// Deliveries of a different hash.
+    pub disagreeing: Vec<PathDelivery>,
+    /// Paths that do not follow the routing topology between the expected endpoints.
+    pub unrouted: Vec<Vec<String>>,
+}
+
+impl PathValidation {
+    /// Returns true if enough node-disjoint paths delivered the triad's hash.
+    pub fn is_accepted(&self) -> bool {
+        self.agreeing.len() >= self.required
+    }
+}
 
 /// RedundantPathSecurity manages multi-path validation and node promotion for security using cryptographic hashes.
 pub struct RedundantPathSecurity {
@@ -7,6 +45,14 @@ pub struct RedundantPathSecurity {
     pub active_paths: HashSet<[u8; 32]>,
     /// Set of promoted node hashes.
     pub promoted_nodes: HashSet<[u8; 32]>,
+    /// Performance scores that drive automatic promotion.
+    pub scoring: NodeScoring,
+    /// Automatic promotions and demotions, oldest first.
+    pub promotion_history: Vec<PromotionRecord>,
+    /// Node-disjoint paths that must deliver matching triad hashes before a triad is accepted.
+    pub min_disjoint_paths: usize,
+    /// Identity checks new nodes must pass before they can be promoted or hold a path. None admits any ID.
+    pub admission: Option<AdmissionControl>,
 }
 
 impl RedundantPathSecurity {
@@ -15,6 +61,26 @@ impl RedundantPathSecurity {
         RedundantPathSecurity {
             active_paths: HashSet::new(),
             promoted_nodes: HashSet::new(),
+            scoring: NodeScoring::default(),
+            promotion_history: Vec::new(),
+            min_disjoint_paths: DEFAULT_MIN_DISJOINT_PATHS,
+            admission: None,
+        }
+    }
+
+    /// Creates a new RedundantPathSecurity instance that only promotes nodes, or adds paths to them, once they are admitted.
+    pub fn with_admission(config: AdmissionConfig) -> Self {
+        RedundantPathSecurity {
+            admission: Some(AdmissionControl::new(config)),
+            ..RedundantPathSecurity::new()
+        }
+    }
+
+    /// Creates a new RedundantPathSecurity instance that scores nodes with the given configuration.
+    pub fn with_scoring(config: ScoringConfig) -> Self {
+        RedundantPathSecurity {
+            scoring: NodeScoring::new(config),
+            ..RedundantPathSecurity::new()
         }
     }
 
@@ -28,15 +94,108 @@ impl RedundantPathSecurity {
         hash_arr
     }
 
-    /// Validates that there are active redundant paths.
-    /// Returns true if there is at least one active path.
+    /// Validates that there are enough active redundant paths.
+    /// Returns true if at least `min_disjoint_paths` paths are active. Active paths are only IDs, so this cannot tell
+    /// whether they share nodes; `validate_triad` checks that against the routing topology.
     pub fn validate_paths(&self) -> bool {
-        !self.active_paths.is_empty()
+        self.active_paths.len() >= self.min_disjoint_paths.max(1)
+    }
+
+    /// Checks the hashes delivered for a triad travelling from `source` to `destination`.
+    /// Deliveries whose path is not a route in `routing` between those nodes are set aside. Of the rest, the hops of
+    /// those carrying `triad_hash` form a graph, and the most routes through it that share no node but the endpoints are
+    /// counted, found by maximum flow as in `MultiPathFractalRouting::disjoint_paths`; the triad is accepted once
+    /// `min_disjoint_paths` are counted. Deliveries of any other hash are reported as disagreeing.
+    pub fn validate_triad(
+        &self,
+        routing: &MultiPathFractalRouting,
+        source: &str,
+        destination: &str,
+        triad_hash: &[u8; 32],
+        deliveries: &[PathDelivery],
+    ) -> PathValidation {
+        let mut validation = PathValidation {
+            required: self.min_disjoint_paths.max(1),
+            ..PathValidation::default()
+        };
+        let mut matching = Vec::new();
+        for delivery in deliveries {
+            let path = &delivery.path;
+            let routed = path.first().is_some_and(|n| n == source)
+                && path.last().is_some_and(|n| n == destination)
+                && routing.is_route(path);
+            if !routed {
+                validation.unrouted.push(path.clone());
+            } else if &delivery.triad_hash != triad_hash {
+                validation.disagreeing.push(delivery.clone());
+            } else {
+                matching.push(path.clone());
+            }
+        }
+
+        matching.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+        matching.dedup();
+        let mut hops = MultiPathFractalRouting::new();
+        for path in &matching {
+            for hop in path.windows(2) {
+                hops.add_link(&hop[0], &hop[1]);
+            }
+        }
+        validation.agreeing = hops.disjoint_paths(source, destination);
+        validation.overlapping = matching.into_iter().filter(|path| !validation.agreeing.contains(path)).collect();
+        validation
+    }
+
+    /// Admits a new node at time `now` if its join request, received from `peer`, passes admission control.
+    pub fn admit_node(&mut self, request: &JoinRequest, peer: IpAddr, token: &WaclaniumToken, now: u64) -> Result<(), String> {
+        match &mut self.admission {
+            Some(admission) => admission.admit(request, peer, token, now),
+            None => Err("Admission control is not enabled".to_string()),
+        }
+    }
+
+    /// Withdraws the admission, promotion and path of every node whose stake in `token` no longer covers its bond.
+    /// Returns the revoked node IDs.
+    pub fn recheck_bonds(&mut self, token: &WaclaniumToken) -> Vec<String> {
+        let revoked = self.admission.as_mut().map(|admission| admission.recheck_bonds(token)).unwrap_or_default();
+        for node_id in &revoked {
+            self.remove_promoted_node(node_id);
+            self.remove_path(node_id);
+        }
+        revoked
+    }
+
+    /// Unstakes `amount` of `node_id`'s stake in `token`, revoking its admission if that breaks its stake bond.
+    pub fn unstake(&mut self, token: &mut WaclaniumToken, node_id: &str, amount: u64) -> Result<(), String> {
+        token.unstake(node_id, amount)?;
+        self.recheck_bonds(token);
+        Ok(())
     }
 
-    /// Adds a new active path by its ID.
+    /// Withdraws a node's admission and its promotion.
+    /// Returns true if the node was admitted.
+    pub fn revoke_admission(&mut self, node_id: &str) -> bool {
+        let revoked = self.admission.as_mut().is_some_and(|admission| admission.revoke(node_id));
+        if revoked {
+            self.remove_promoted_node(node_id);
+            self.remove_path(node_id);
+        }
+        revoked
+    }
+
+    /// Checks if a node has been admitted.
+    /// Returns true for every node when admission control is not enabled.
+    pub fn is_admitted(&self, node_id: &str) -> bool {
+        self.admission.as_ref().is_none_or(|admission| admission.is_admitted(node_id))
+    }
+
+    /// Adds a new active path by its ID. With admission control enabled, path IDs name the node the path leads to
+    /// and only admitted nodes may have one.
     /// Returns true if the path was newly inserted.
     pub fn add_path(&mut self, path_id: &str) -> bool {
+        if !self.is_admitted(path_id) {
+            return false;
+        }
         let hash = Self::hash_id(path_id);
         self.active_paths.insert(hash)
     }
@@ -48,9 +207,12 @@ impl RedundantPathSecurity {
         self.active_paths.remove(&hash)
     }
 
-    /// Promotes a node by its ID.
+    /// Promotes a node by its ID. With admission control enabled, only admitted nodes can be promoted.
     /// Returns true if the node was newly promoted.
     pub fn promote_node(&mut self, node_id: &str) -> bool {
+        if !self.is_admitted(node_id) {
+            return false;
+        }
         let hash = Self::hash_id(node_id);
         self.promoted_nodes.insert(hash)
     }
@@ -69,6 +231,48 @@ impl RedundantPathSecurity {
         self.promoted_nodes.contains(&hash)
     }
 
+    /// Records an event for a node at time `now`, then promotes or demotes the node if its score crossed a threshold.
+    /// Returns the change made, if any.
+    pub fn record_event(&mut self, node_id: &str, event: NodeEvent, now: u64) -> Option<PromotionChange> {
+        self.scoring.record(node_id, event, now);
+        self.update_promotion(node_id, now)
+    }
+
+    /// Re-evaluates every scored node at time `now`, so nodes whose scores decayed below the demotion threshold are demoted.
+    /// Nodes promoted by hand and never scored are left alone. Returns the changes made.
+    pub fn update_promotions(&mut self, now: u64) -> Vec<PromotionRecord> {
+        let mut nodes = self.scoring.nodes();
+        nodes.sort();
+        let start = self.promotion_history.len();
+        for node in nodes {
+            self.update_promotion(&node, now);
+        }
+        self.promotion_history[start..].to_vec()
+    }
+
+    fn update_promotion(&mut self, node_id: &str, now: u64) -> Option<PromotionChange> {
+        let change = self.scoring.decide(node_id, self.is_node_promoted(node_id), now)?;
+        let changed = match change {
+            PromotionChange::Promoted => self.promote_node(node_id),
+            PromotionChange::Demoted => self.remove_promoted_node(node_id),
+        };
+        if !changed {
+            return None;
+        }
+        self.promotion_history.push(PromotionRecord {
+            node: node_id.to_string(),
+            change,
+            at: now,
+            score: self.scoring.score(node_id, now),
+        });
+        Some(change)
+    }
+
+    /// Returns the automatic promotions and demotions of a node, oldest first.
+    pub fn promotion_history_of(&self, node_id: &str) -> Vec<&PromotionRecord> {
+        self.promotion_history.iter().filter(|record| record.node == node_id).collect()
+    }
+
     /// Lists all active paths as a vector of hex strings.
     pub fn list_active_paths(&self) -> Vec<String> {
         self.active_paths.iter().map(|h| hex::encode(h)).collect()
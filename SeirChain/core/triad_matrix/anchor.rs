@@ -0,0 +1,173 @@
+// anchor.rs
+// Fractal Merkle Anchor (FMA): verifies a Triad back to genesis by recomputation
+
+use std::fmt;
+use super::encoding::TriadRecord;
+use super::merkle;
+use super::triad_structure::Triad;
+
+/// The first broken link found while anchoring a Triad.
+/// `level` counts up from the leaf: 0 is the leaf, 1 its parent, and so on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AnchorError {
+    /// The stored merkle root does not match the recomputed one.
+    MerkleRootMismatch { level: usize },
+    /// The transaction list hits the duplicate-leaf ambiguity.
+    MutatedMerkleTree { level: usize },
+    /// The Triad's `parent_hash` is not the header hash of the Triad above it.
+    ParentHashMismatch { level: usize },
+    /// The Triad above does not list this Triad's hash among its children.
+    ChildNotCommitted { level: usize },
+    /// The topmost Triad has a non-zero `parent_hash`, so it is not genesis.
+    NotGenesis { level: usize },
+}
+
+impl fmt::Display for AnchorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnchorError::MerkleRootMismatch { level } => write!(f, "Merkle root mismatch at level {}", level),
+            AnchorError::MutatedMerkleTree { level } => write!(f, "Mutated Merkle tree at level {}", level),
+            AnchorError::ParentHashMismatch { level } => write!(f, "Parent hash mismatch between level {} and {}", level, level + 1),
+            AnchorError::ChildNotCommitted { level } => write!(f, "Level {} does not commit to its child", level),
+            AnchorError::NotGenesis { level } => write!(f, "Top of path at level {} is not genesis", level),
+        }
+    }
+}
+
+impl std::error::Error for AnchorError {}
+
+/// Verifies a leaf Triad against the path of its ancestors, ordered from the parent up to genesis.
+/// At every level the merkle root is recomputed from the transactions, the `parent_hash` link is
+/// checked against the header above, and the header above must commit to the child's hash.
+/// Returns the content hash of genesis, which anchors the whole path.
+pub fn verify_anchor(leaf: &Triad, ancestors: &[TriadRecord]) -> Result<[u8; 32], AnchorError> {
+    let mut current = leaf.to_record();
+    check_merkle_root(&current, 0)?;
+
+    for (i, parent) in ancestors.iter().enumerate() {
+        let level = i + 1;
+        check_merkle_root(parent, level)?;
+        if current.header.parent_hash != parent.header.hash() {
+            return Err(AnchorError::ParentHashMismatch { level: i });
+        }
+        let child_hash = current.hash();
+        if !parent.child_hashes.contains(&Some(child_hash)) {
+            return Err(AnchorError::ChildNotCommitted { level });
+        }
+        current = parent.clone();
+    }
+
+    if current.header.parent_hash != [0u8; 32] {
+        return Err(AnchorError::NotGenesis { level: ancestors.len() });
+    }
+    Ok(current.hash())
+}
+
+fn check_merkle_root(record: &TriadRecord, level: usize) -> Result<(), AnchorError> {
+    let leaves: Vec<[u8; 32]> = record.transactions.iter().map(|tx| tx.hash()).collect();
+    if merkle::is_mutated(leaves.clone()) {
+        return Err(AnchorError::MutatedMerkleTree { level });
+    }
+    if merkle::merkle_root(leaves) != record.header.merkle_root {
+        return Err(AnchorError::MerkleRootMismatch { level });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::triad_matrix::matrix::{TernaryCoordinate, TriadMatrix};
+    use crate::core::triad_matrix::triad_structure::Transaction;
+
+    fn tx(amount: u64) -> Transaction {
+        Transaction {
+            sender: "user1".to_string(),
+            receiver: "user2".to_string(),
+            amount,
+            timestamp: amount,
+        }
+    }
+
+    /// Builds a matrix with genesis plus `n` Triads and returns the deepest coordinate.
+    fn build_matrix(n: u64) -> (TriadMatrix, TernaryCoordinate) {
+        let mut matrix = TriadMatrix::new(Triad::genesis(Some(vec![tx(0)]))).unwrap();
+        let mut last = TernaryCoordinate::root();
+        for i in 1..=n {
+            let mut triad = Triad::new();
+            triad.insert_transaction(tx(i));
+            triad.parent_hash = matrix.next_parent_hash();
+            last = matrix.insert(triad).unwrap();
+        }
+        (matrix, last)
+    }
+
+    #[test]
+    fn test_valid_path_anchors_to_genesis() {
+        let (matrix, leaf) = build_matrix(13);
+        assert_eq!(leaf.depth(), 3);
+        let path = matrix.anchor_path(&leaf).unwrap();
+        assert_eq!(path.len(), 3);
+        let anchor = verify_anchor(matrix.get(&leaf).unwrap(), &path).unwrap();
+        assert_eq!(anchor, matrix.genesis().hash());
+    }
+
+    #[test]
+    fn test_genesis_anchors_itself() {
+        let (matrix, _) = build_matrix(0);
+        assert_eq!(verify_anchor(matrix.genesis(), &[]), Ok(matrix.genesis().hash()));
+    }
+
+    #[test]
+    fn test_tampered_ancestor_transactions() {
+        let (matrix, leaf) = build_matrix(13);
+        let mut path = matrix.anchor_path(&leaf).unwrap();
+        path[1].transactions[0].amount += 1;
+        assert_eq!(
+            verify_anchor(matrix.get(&leaf).unwrap(), &path),
+            Err(AnchorError::MerkleRootMismatch { level: 2 })
+        );
+    }
+
+    #[test]
+    fn test_tampered_leaf_transactions() {
+        let (matrix, leaf) = build_matrix(4);
+        let path = matrix.anchor_path(&leaf).unwrap();
+        let mut triad = matrix.get(&leaf).unwrap().to_record().into_triad();
+        triad.transactions.push(tx(99));
+        assert_eq!(verify_anchor(&triad, &path), Err(AnchorError::MerkleRootMismatch { level: 0 }));
+    }
+
+    #[test]
+    fn test_broken_parent_link() {
+        let (matrix, leaf) = build_matrix(13);
+        let mut path = matrix.anchor_path(&leaf).unwrap();
+        path[1].header.parent_hash = [9u8; 32];
+        assert_eq!(
+            verify_anchor(matrix.get(&leaf).unwrap(), &path),
+            Err(AnchorError::ParentHashMismatch { level: 1 })
+        );
+    }
+
+    #[test]
+    fn test_child_not_committed() {
+        let (matrix, leaf) = build_matrix(13);
+        let mut path = matrix.anchor_path(&leaf).unwrap();
+        let slot = *leaf.digits().last().unwrap() as usize;
+        path[0].child_hashes[slot] = Some([1u8; 32]);
+        assert_eq!(
+            verify_anchor(matrix.get(&leaf).unwrap(), &path),
+            Err(AnchorError::ChildNotCommitted { level: 1 })
+        );
+    }
+
+    #[test]
+    fn test_path_must_end_at_genesis() {
+        let (matrix, leaf) = build_matrix(13);
+        let path = matrix.anchor_path(&leaf).unwrap();
+        assert_eq!(
+            verify_anchor(matrix.get(&leaf).unwrap(), &path[..1]),
+            Err(AnchorError::NotGenesis { level: 1 })
+        );
+    }
+}
@@ -0,0 +1,640 @@
+// encoding.rs
+// Canonical, versioned binary encoding and content hashing for Triads
+
+use ed25519_dalek::Signature;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use super::merkle;
+use super::triad_structure::{ProofOfFractalData, Transaction, Triad};
+use super::evidence::{Evidence, SignedHeader, Vote, VoteKind};
+
+/// Version byte written at the start of every encoded Triad header. Records in any other
+/// version are rejected, so every Triad has exactly one encoding and one hash.
+pub const TRIAD_ENCODING_VERSION: u8 = 1;
+
+/// Length in bytes of an encoded `TriadHeader`.
+pub const TRIAD_HEADER_LEN: usize = 1 + 32 + 32 + 8 + 32 + 8 + 4 + 32;
+
+/// The fixed-size part of a Triad: everything except transactions and children.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TriadHeader {
+    pub version: u8,
+    pub merkle_root: [u8; 32],
+    pub parent_hash: [u8; 32],
+    pub timestamp: u64,
+    /// Merkle root over the hashes of the Triad's evidence. All zero when there is none.
+    pub evidence_root: [u8; 32],
+    pub proof_of_fractal_data: ProofOfFractalData,
+}
+
+/// A self-contained, serializable snapshot of a Triad.
+/// Boxed children are replaced by their content hashes so a record can be stored
+/// or sent on its own and the subtree resolved separately.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TriadRecord {
+    pub header: TriadHeader,
+    pub transactions: Vec<Transaction>,
+    pub child_hashes: [Option<[u8; 32]>; 3],
+    pub evidence: Vec<Evidence>,
+}
+
+impl TriadHeader {
+    /// Appends the canonical header bytes to `out`.
+    /// Layout: version | merkle_root | parent_hash | timestamp (u64 LE) | evidence_root |
+    /// nonce (u64 LE) | difficulty (u32 LE) | pof hash.
+    pub fn encode_into(&self, out: &mut Vec<u8>) {
+        out.push(self.version);
+        out.extend_from_slice(&self.merkle_root);
+        out.extend_from_slice(&self.parent_hash);
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+        out.extend_from_slice(&self.evidence_root);
+        out.extend_from_slice(&self.proof_of_fractal_data.nonce.to_le_bytes());
+        out.extend_from_slice(&self.proof_of_fractal_data.difficulty.to_le_bytes());
+        out.extend_from_slice(&self.proof_of_fractal_data.hash);
+    }
+
+    /// Returns the canonical header bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(TRIAD_HEADER_LEN);
+        self.encode_into(&mut out);
+        out
+    }
+
+    /// Hashes the header alone. This is the value children store in `parent_hash`,
+    /// so it deliberately excludes child hashes (which would make the link circular).
+    pub fn hash(&self) -> [u8; 32] {
+        sha256(&self.encode())
+    }
+
+    /// Bytes the Proof-of-Fractal puzzle is solved over:
+    /// merkle_root | parent_hash | difficulty (u32 LE) | timestamp (u64 LE) | evidence_root,
+    /// the evidence root so that evidence cannot be stripped from a solved Triad.
+    /// The nonce and PoF hash are left out since they are the solution itself.
+    pub fn pof_preimage(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(32 + 32 + 4 + 8 + 32);
+        out.extend_from_slice(&self.merkle_root);
+        out.extend_from_slice(&self.parent_hash);
+        out.extend_from_slice(&self.proof_of_fractal_data.difficulty.to_le_bytes());
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+        out.extend_from_slice(&self.evidence_root);
+        out
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, String> {
+        let version = reader.u8()?;
+        if version != TRIAD_ENCODING_VERSION {
+            return Err(format!("Unsupported triad encoding version {}", version));
+        }
+        let merkle_root = reader.hash()?;
+        let parent_hash = reader.hash()?;
+        let timestamp = reader.u64()?;
+        let evidence_root = reader.hash()?;
+        let nonce = reader.u64()?;
+        let difficulty = reader.u32()?;
+        let hash = reader.hash()?;
+        Ok(TriadHeader {
+            version,
+            merkle_root,
+            parent_hash,
+            timestamp,
+            evidence_root,
+            proof_of_fractal_data: ProofOfFractalData { nonce, difficulty, hash },
+        })
+    }
+}
+
+impl TriadRecord {
+    /// Encodes the record canonically:
+    /// header | child mask (u8, bit i = child i present) | present child hashes |
+    /// transaction count (u32 LE) | transactions | evidence count (u32 LE) | evidence.
+    /// Each transaction is sender and receiver as length-prefixed (u32 LE) UTF-8,
+    /// followed by amount and timestamp as u64 LE. See `Evidence::encode` for evidence.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(TRIAD_HEADER_LEN + 1 + 3 * 32 + 4);
+        self.header.encode_into(&mut out);
+        encode_children(&self.child_hashes, &mut out);
+        out.extend_from_slice(&(self.transactions.len() as u32).to_le_bytes());
+        for tx in &self.transactions {
+            encode_str(&tx.sender, &mut out);
+            encode_str(&tx.receiver, &mut out);
+            out.extend_from_slice(&tx.amount.to_le_bytes());
+            out.extend_from_slice(&tx.timestamp.to_le_bytes());
+        }
+        out.extend_from_slice(&(self.evidence.len() as u32).to_le_bytes());
+        for evidence in &self.evidence {
+            encode_evidence(evidence, &mut out);
+        }
+        out
+    }
+
+    /// Decodes a record produced by `encode`.
+    /// Rejects unknown versions, unknown child mask bits, evidence that does not match the
+    /// evidence root and trailing bytes so that every valid record has exactly one encoding.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let header = TriadHeader::decode(&mut reader)?;
+
+        let mask = reader.u8()?;
+        if mask & !0b111 != 0 {
+            return Err(format!("Invalid child mask {:#04x}", mask));
+        }
+        let mut child_hashes = [None; 3];
+        for (i, slot) in child_hashes.iter_mut().enumerate() {
+            if mask & (1 << i) != 0 {
+                *slot = Some(reader.hash()?);
+            }
+        }
+
+        let tx_count = reader.u32()? as usize;
+        let mut transactions = Vec::with_capacity(tx_count.min(reader.remaining() / 24));
+        for _ in 0..tx_count {
+            transactions.push(Transaction {
+                sender: reader.string()?,
+                receiver: reader.string()?,
+                amount: reader.u64()?,
+                timestamp: reader.u64()?,
+            });
+        }
+
+        let count = reader.u32()? as usize;
+        let mut evidence = Vec::new();
+        for _ in 0..count {
+            evidence.push(decode_evidence(&mut reader)?);
+        }
+        if evidence_root(&evidence) != header.evidence_root {
+            return Err("Evidence does not match the evidence root".to_string());
+        }
+
+        if reader.remaining() != 0 {
+            return Err(format!("{} trailing bytes after triad record", reader.remaining()));
+        }
+        Ok(TriadRecord { header, transactions, child_hashes, evidence })
+    }
+
+    /// Content hash of the record; equal to `Triad::hash` of the triad it was taken from.
+    pub fn hash(&self) -> [u8; 32] {
+        content_hash(&self.header, &self.child_hashes)
+    }
+
+    /// Returns true if the header's merkle and evidence roots are those of the record's
+    /// transactions and evidence, so a signature over the header covers them too.
+    pub fn roots_match(&self) -> bool {
+        let leaves = self.transactions.iter().map(|tx| tx.hash()).collect();
+        merkle::merkle_root(leaves) == self.header.merkle_root
+            && evidence_root(&self.evidence) == self.header.evidence_root
+    }
+
+    /// Rebuilds a Triad without children. The caller reattaches the subtree
+    /// from `child_hashes`; until then the Triad's `hash` differs from the record's.
+    pub fn into_triad(self) -> Triad {
+        Triad {
+            transactions: self.transactions,
+            child_references: [None, None, None],
+            merkle_root: self.header.merkle_root,
+            proof_of_fractal_data: self.header.proof_of_fractal_data,
+            parent_hash: self.header.parent_hash,
+            timestamp: self.header.timestamp,
+            evidence: self.evidence,
+        }
+    }
+}
+
+impl Triad {
+    /// Returns the header of this Triad.
+    pub fn header(&self) -> TriadHeader {
+        TriadHeader {
+            version: TRIAD_ENCODING_VERSION,
+            merkle_root: self.merkle_root,
+            parent_hash: self.parent_hash,
+            timestamp: self.timestamp,
+            evidence_root: evidence_root(&self.evidence),
+            proof_of_fractal_data: self.proof_of_fractal_data.clone(),
+        }
+    }
+
+    /// Hash of the header only; children link to their parent through this value.
+    pub fn header_hash(&self) -> [u8; 32] {
+        self.header().hash()
+    }
+
+    /// Bytes the Proof-of-Fractal puzzle for this Triad is solved over.
+    /// See `TriadHeader::pof_preimage`.
+    pub fn pof_preimage(&self) -> Vec<u8> {
+        self.header().pof_preimage()
+    }
+
+    /// Content hashes of the three children, `None` where a slot is empty.
+    pub fn child_hashes(&self) -> [Option<[u8; 32]>; 3] {
+        let mut hashes = [None; 3];
+        for (slot, child) in hashes.iter_mut().zip(self.child_references.iter()) {
+            *slot = child.as_ref().map(|c| c.hash());
+        }
+        hashes
+    }
+
+    /// Content hash (ID) of the Triad. Commits to the merkle root, parent hash, timestamp,
+    /// evidence root, Proof-of-Fractal data and, recursively, to every child.
+    pub fn hash(&self) -> [u8; 32] {
+        content_hash(&self.header(), &self.child_hashes())
+    }
+
+    /// Takes a serializable snapshot of this Triad.
+    pub fn to_record(&self) -> TriadRecord {
+        TriadRecord {
+            header: self.header(),
+            transactions: self.transactions.clone(),
+            child_hashes: self.child_hashes(),
+            evidence: self.evidence.clone(),
+        }
+    }
+
+    /// Canonical byte encoding of this Triad. See `TriadRecord::encode`.
+    pub fn encode(&self) -> Vec<u8> {
+        self.to_record().encode()
+    }
+}
+
+impl Evidence {
+    /// Canonical bytes of the evidence: a tag (u8: 0 double vote, 1 invalid PoF,
+    /// 2 conflicting headers) followed, for a double vote, by the vote kind (u8: 0 prepare,
+    /// 1 commit) and both votes, otherwise by the signed headers. A vote is its context, view
+    /// and sequence (u64 LE), digest, sender as length-prefixed UTF-8 and the 64-byte
+    /// signature; a signed header is its context, view and sequence (u64 LE), the encoded
+    /// header, signer and signature.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_evidence(self, &mut out);
+        out
+    }
+
+    /// Hash of the canonical bytes; the leaves of a Triad's evidence root.
+    pub fn hash(&self) -> [u8; 32] {
+        sha256(&self.encode())
+    }
+}
+
+/// Merkle root over the hashes of `evidence`, all zero if there is none.
+pub fn evidence_root(evidence: &[Evidence]) -> [u8; 32] {
+    merkle::merkle_root(evidence.iter().map(Evidence::hash).collect())
+}
+
+fn encode_evidence(evidence: &Evidence, out: &mut Vec<u8>) {
+    match evidence {
+        Evidence::DoubleVote { kind, first, second } => {
+            out.push(0);
+            out.push(*kind as u8);
+            encode_vote(first, out);
+            encode_vote(second, out);
+        }
+        Evidence::InvalidPof { header } => {
+            out.push(1);
+            encode_signed_header(header, out);
+        }
+        Evidence::ConflictingHeaders { first, second } => {
+            out.push(2);
+            encode_signed_header(first, out);
+            encode_signed_header(second, out);
+        }
+    }
+}
+
+fn encode_vote(vote: &Vote, out: &mut Vec<u8>) {
+    out.extend_from_slice(&vote.context);
+    out.extend_from_slice(&vote.view.to_le_bytes());
+    out.extend_from_slice(&vote.sequence.to_le_bytes());
+    out.extend_from_slice(&vote.digest);
+    encode_str(&vote.sender, out);
+    out.extend_from_slice(&vote.signature.to_bytes());
+}
+
+fn encode_signed_header(header: &SignedHeader, out: &mut Vec<u8>) {
+    out.extend_from_slice(&header.context);
+    out.extend_from_slice(&header.view.to_le_bytes());
+    out.extend_from_slice(&header.sequence.to_le_bytes());
+    header.header.encode_into(out);
+    encode_str(&header.signer, out);
+    out.extend_from_slice(&header.signature.to_bytes());
+}
+
+fn decode_evidence(reader: &mut Reader) -> Result<Evidence, String> {
+    match reader.u8()? {
+        0 => {
+            let kind = match reader.u8()? {
+                0 => VoteKind::Prepare,
+                1 => VoteKind::Commit,
+                kind => return Err(format!("Unknown vote kind {}", kind)),
+            };
+            let first = decode_vote(reader)?;
+            let second = decode_vote(reader)?;
+            Ok(Evidence::DoubleVote { kind, first, second })
+        }
+        1 => Ok(Evidence::InvalidPof { header: decode_signed_header(reader)? }),
+        2 => {
+            let first = Box::new(decode_signed_header(reader)?);
+            let second = Box::new(decode_signed_header(reader)?);
+            Ok(Evidence::ConflictingHeaders { first, second })
+        }
+        tag => Err(format!("Unknown evidence tag {}", tag)),
+    }
+}
+
+fn decode_vote(reader: &mut Reader) -> Result<Vote, String> {
+    Ok(Vote {
+        context: reader.hash()?,
+        view: reader.u64()?,
+        sequence: reader.u64()?,
+        digest: reader.hash()?,
+        sender: reader.string()?,
+        signature: reader.signature()?,
+    })
+}
+
+fn decode_signed_header(reader: &mut Reader) -> Result<SignedHeader, String> {
+    Ok(SignedHeader {
+        context: reader.hash()?,
+        view: reader.u64()?,
+        sequence: reader.u64()?,
+        header: TriadHeader::decode(reader)?,
+        signer: reader.string()?,
+        signature: reader.signature()?,
+    })
+}
+
+fn content_hash(header: &TriadHeader, child_hashes: &[Option<[u8; 32]>; 3]) -> [u8; 32] {
+    let mut bytes = header.encode();
+    encode_children(child_hashes, &mut bytes);
+    sha256(&bytes)
+}
+
+fn encode_children(child_hashes: &[Option<[u8; 32]>; 3], out: &mut Vec<u8>) {
+    let mask = child_hashes.iter().enumerate()
+        .filter(|(_, h)| h.is_some())
+        .fold(0u8, |mask, (i, _)| mask | (1 << i));
+    out.push(mask);
+    for hash in child_hashes.iter().flatten() {
+        out.extend_from_slice(hash);
+    }
+}
+
+fn encode_str(s: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    let result = Sha256::digest(bytes);
+    let mut hash_arr = [0u8; 32];
+    hash_arr.copy_from_slice(&result);
+    hash_arr
+}
+
+/// Minimal cursor over an encoded record.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.remaining() < len {
+            return Err(format!("Unexpected end of triad record at byte {}", self.pos));
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn hash(&mut self) -> Result<[u8; 32], String> {
+        let mut buf = [0u8; 32];
+        buf.copy_from_slice(self.take(32)?);
+        Ok(buf)
+    }
+
+    fn signature(&mut self) -> Result<Signature, String> {
+        let mut buf = [0u8; 64];
+        buf.copy_from_slice(self.take(64)?);
+        Ok(Signature::from_bytes(&buf))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "Invalid UTF-8 in triad record".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_triad() -> Triad {
+        let mut triad = Triad::genesis(Some(vec![
+            Transaction {
+                sender: "genesis".to_string(),
+                receiver: "user1".to_string(),
+                amount: 100,
+                timestamp: 0,
+            },
+            Transaction {
+                sender: "user1".to_string(),
+                receiver: "user2".to_string(),
+                amount: 25,
+                timestamp: 1,
+            },
+        ]));
+        triad.proof_of_fractal_data = ProofOfFractalData {
+            nonce: 42,
+            difficulty: 2,
+            hash: [7u8; 32],
+        };
+        triad.timestamp = 1_700_000_000;
+        triad
+    }
+
+    fn double_vote() -> Evidence {
+        let keys = crate::core::security::keys::Keystore::derive(&["node1".to_string()], &[1u8; 32]);
+        let key = keys.key("node1").unwrap();
+        Evidence::DoubleVote {
+            kind: VoteKind::Commit,
+            first: Vote::sign(VoteKind::Commit, [5u8; 32], 0, 1, [1u8; 32], "node1", key),
+            second: Vote::sign(VoteKind::Commit, [5u8; 32], 0, 1, [2u8; 32], "node1", key),
+        }
+    }
+
+    fn conflicting_headers() -> Evidence {
+        let keys = crate::core::security::keys::Keystore::derive(&["node2".to_string()], &[1u8; 32]);
+        let key = keys.key("node2").unwrap();
+        let mut later = sample_triad();
+        later.timestamp += 1;
+        Evidence::ConflictingHeaders {
+            first: Box::new(SignedHeader::sign([5u8; 32], 0, 1, sample_triad().header(), "node2", key)),
+            second: Box::new(SignedHeader::sign([5u8; 32], 0, 1, later.header(), "node2", key)),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut triad = sample_triad();
+        let mut child = Triad::new();
+        child.parent_hash = triad.header_hash();
+        triad.add_child(1, child).unwrap();
+
+        let record = triad.to_record();
+        let decoded = TriadRecord::decode(&triad.encode()).unwrap();
+        assert_eq!(decoded, record);
+        assert_eq!(decoded.hash(), triad.hash());
+        assert_eq!(decoded.child_hashes[0], None);
+        assert!(decoded.child_hashes[1].is_some());
+    }
+
+    #[test]
+    fn test_golden_vector() {
+        let triad = sample_triad();
+        assert_eq!(hex::encode(triad.encode()), GOLDEN_ENCODING);
+        assert_eq!(hex::encode(triad.header_hash()), GOLDEN_HEADER_HASH);
+        assert_eq!(hex::encode(triad.hash()), GOLDEN_HASH);
+    }
+
+    #[test]
+    fn test_empty_triad_golden_vector() {
+        assert_eq!(hex::encode(Triad::new().hash()), GOLDEN_EMPTY_HASH);
+    }
+
+    #[test]
+    fn test_evidence_round_trip() {
+        let mut triad = sample_triad();
+        triad.evidence = vec![double_vote(), conflicting_headers()];
+        let record = triad.to_record();
+        assert_ne!(record.header.evidence_root, [0u8; 32]);
+
+        let bytes = triad.encode();
+        let decoded = TriadRecord::decode(&bytes).unwrap();
+        assert_eq!(decoded, record);
+        assert_eq!(decoded.encode(), bytes);
+        assert_eq!(decoded.clone().into_triad().hash(), triad.hash());
+        assert_eq!(decoded.evidence[1].offender(), "node2");
+
+        // Evidence is committed to by the header and the PoF preimage, so it cannot be
+        // dropped or swapped without changing both.
+        let mut stripped = triad.clone();
+        stripped.evidence.pop();
+        assert_ne!(stripped.header_hash(), triad.header_hash());
+        assert_ne!(stripped.pof_preimage(), triad.pof_preimage());
+
+        let mut forged = record.clone();
+        forged.evidence.reverse();
+        assert!(TriadRecord::decode(&forged.encode()).is_err());
+    }
+
+    #[test]
+    fn test_pof_preimage() {
+        let triad = sample_triad();
+        let preimage = triad.pof_preimage();
+        assert_eq!(preimage.len(), 108);
+        assert_eq!(&preimage[..32], &triad.merkle_root);
+        assert_eq!(&preimage[64..68], &2u32.to_le_bytes());
+        assert_eq!(&preimage[68..76], &1_700_000_000u64.to_le_bytes());
+        assert_eq!(&preimage[76..], &[0u8; 32]);
+
+        // The solution fields are not part of the puzzle input.
+        let mut solved = sample_triad();
+        solved.proof_of_fractal_data.nonce = 7;
+        solved.proof_of_fractal_data.hash = [1u8; 32];
+        assert_eq!(solved.pof_preimage(), preimage);
+
+        let mut later = sample_triad();
+        later.timestamp += 1;
+        assert_ne!(later.pof_preimage(), preimage);
+    }
+
+    #[test]
+    fn test_hash_commits_to_fields() {
+        let base = sample_triad().hash();
+
+        let mut triad = sample_triad();
+        triad.parent_hash = [1u8; 32];
+        assert_ne!(triad.hash(), base);
+
+        let mut triad = sample_triad();
+        triad.proof_of_fractal_data.nonce += 1;
+        assert_ne!(triad.hash(), base);
+
+        let mut triad = sample_triad();
+        triad.timestamp += 1;
+        assert_ne!(triad.hash(), base);
+
+        let mut triad = sample_triad();
+        triad.evidence.push(double_vote());
+        assert_ne!(triad.hash(), base);
+
+        let mut triad = sample_triad();
+        triad.add_child(2, Triad::new()).unwrap();
+        assert_ne!(triad.hash(), base);
+        // Adding a child must not change the link hash children point at.
+        assert_eq!(triad.header_hash(), sample_triad().header_hash());
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_input() {
+        let bytes = sample_triad().encode();
+
+        let mut bad_version = bytes.clone();
+        bad_version[0] = TRIAD_ENCODING_VERSION + 1;
+        assert!(TriadRecord::decode(&bad_version).is_err());
+        bad_version[0] = 0;
+        assert!(TriadRecord::decode(&bad_version).is_err());
+        bad_version[0] = 3;
+        assert!(TriadRecord::decode(&bad_version).is_err());
+
+        let mut bad_mask = bytes.clone();
+        bad_mask[TRIAD_HEADER_LEN] = 0b1000;
+        assert!(TriadRecord::decode(&bad_mask).is_err());
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(TriadRecord::decode(&trailing).is_err());
+
+        assert!(TriadRecord::decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    // Golden vectors for `sample_triad` and `Triad::new()`. These must never change;
+    // a changed value means nodes would disagree on IDs.
+    const GOLDEN_ENCODING: &str = concat!(
+        "01",
+        "4c2f15db6e09314d322da9d0c81c87ab05d9883ff154494568e73228e6db014c",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "00f1536500000000",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "2a00000000000000",
+        "02000000",
+        "0707070707070707070707070707070707070707070707070707070707070707",
+        "00",
+        "02000000",
+        "0700000067656e65736973", "050000007573657231", "6400000000000000", "0000000000000000",
+        "050000007573657231", "050000007573657232", "1900000000000000", "0100000000000000",
+        "00000000",
+    );
+    const GOLDEN_HEADER_HASH: &str = "bd59dbcc8fac721e175feab5dbbfae39307fd7e0fb3c1ebeaf322478ffe6af06";
+    const GOLDEN_HASH: &str = "c7ba66160245c4b74c12172604398bdedd7618ddb2a196b20e28fcaa9fef4004";
+    const GOLDEN_EMPTY_HASH: &str = "dd5c4df720ec648a48a06a0a6f7aa85c99aa2d84b4d921a729d933e8bd568eae";
+}
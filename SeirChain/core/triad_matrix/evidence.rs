@@ -0,0 +1,148 @@
+// evidence.rs
+// Signed votes and headers, and the proofs of misbehaviour built from them that Triads carry
+
+use ed25519_dalek::{Signature, Signer, SigningKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use super::encoding::TriadHeader;
+use crate::core::security::keys::Keyring;
+
+/// The phase a vote is cast in. It is part of what gets signed, so a prepare can never be
+/// passed off as a commit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VoteKind {
+    Prepare,
+    Commit,
+}
+
+/// A prepare or commit vote for the proposal with `digest` in round (`view`, `sequence`)
+/// of the consensus instance named by `context`, signed by its sender. Views and sequences
+/// start over in every instance, so without the context votes from different instances
+/// would look like votes for the same round.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vote {
+    pub context: [u8; 32],
+    pub view: u64,
+    pub sequence: u64,
+    pub digest: [u8; 32],
+    pub sender: String,
+    pub signature: Signature,
+}
+
+impl Vote {
+    /// What a vote signs: its kind, context, round and digest.
+    pub fn signing_bytes(kind: VoteKind, context: &[u8; 32], view: u64, sequence: u64, digest: &[u8; 32]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(81);
+        bytes.push(kind as u8);
+        bytes.extend_from_slice(context);
+        bytes.extend_from_slice(&view.to_le_bytes());
+        bytes.extend_from_slice(&sequence.to_le_bytes());
+        bytes.extend_from_slice(digest);
+        bytes
+    }
+
+    /// Casts a vote as `sender`, signed with its `key`.
+    pub fn sign(kind: VoteKind, context: [u8; 32], view: u64, sequence: u64, digest: [u8; 32], sender: &str, key: &SigningKey) -> Self {
+        Vote {
+            context,
+            view,
+            sequence,
+            digest,
+            sender: sender.to_string(),
+            signature: key.sign(&Vote::signing_bytes(kind, &context, view, sequence, &digest)),
+        }
+    }
+
+    /// Returns true if the vote was signed as a `kind` vote by its sender's key in `keyring`.
+    pub fn verify(&self, kind: VoteKind, keyring: &Keyring) -> bool {
+        let bytes = Vote::signing_bytes(kind, &self.context, self.view, self.sequence, &self.digest);
+        keyring.verify(&self.sender, &bytes, &self.signature)
+    }
+}
+
+/// A Triad header signed by the node that proposed it in round (`view`, `sequence`) of the
+/// consensus instance named by `context`; a leader's pre-prepare carries this signature.
+/// A proposer signs at most one header per round.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedHeader {
+    pub context: [u8; 32],
+    pub view: u64,
+    pub sequence: u64,
+    pub header: TriadHeader,
+    pub signer: String,
+    pub signature: Signature,
+}
+
+impl SignedHeader {
+    /// What a header signature covers: a domain tag, the context, the round and the
+    /// encoded header.
+    pub fn signing_bytes(context: &[u8; 32], view: u64, sequence: u64, header: &TriadHeader) -> Vec<u8> {
+        let mut bytes = b"seirchain-header".to_vec();
+        bytes.extend_from_slice(context);
+        bytes.extend_from_slice(&view.to_le_bytes());
+        bytes.extend_from_slice(&sequence.to_le_bytes());
+        header.encode_into(&mut bytes);
+        bytes
+    }
+
+    /// Signs `header` for round (`view`, `sequence`) of `context` as `signer` with its `key`.
+    pub fn sign(context: [u8; 32], view: u64, sequence: u64, header: TriadHeader, signer: &str, key: &SigningKey) -> Self {
+        let signature = key.sign(&SignedHeader::signing_bytes(&context, view, sequence, &header));
+        SignedHeader { context, view, sequence, header, signer: signer.to_string(), signature }
+    }
+
+    /// Returns true if the signature was made by the signer's key in `keyring`.
+    pub fn verify(&self, keyring: &Keyring) -> bool {
+        let bytes = SignedHeader::signing_bytes(&self.context, self.view, self.sequence, &self.header);
+        keyring.verify(&self.signer, &bytes, &self.signature)
+    }
+}
+
+/// Proof that a node misbehaved, checkable by anyone holding its public key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Evidence {
+    /// Two votes of the same kind by one node for different proposals in the same round of
+    /// the same context.
+    DoubleVote { kind: VoteKind, first: Vote, second: Vote },
+    /// A header whose Proof-of-Fractal does not hold, signed by its proposer.
+    InvalidPof { header: SignedHeader },
+    /// Two different headers signed by the same node for the same round of the same context.
+    ConflictingHeaders { first: Box<SignedHeader>, second: Box<SignedHeader> },
+}
+
+impl Evidence {
+    /// The node the evidence incriminates.
+    pub fn offender(&self) -> &str {
+        match self {
+            Evidence::DoubleVote { first, .. } => &first.sender,
+            Evidence::InvalidPof { header } => &header.signer,
+            Evidence::ConflictingHeaders { first, .. } => &first.signer,
+        }
+    }
+
+    /// Identifies the offence rather than the proof of it: two proofs of the same double
+    /// vote, or one proof with its halves swapped, name the same offence.
+    pub fn offence(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.offender().as_bytes());
+        match self {
+            Evidence::DoubleVote { kind, first, .. } => {
+                hasher.update([0, *kind as u8]);
+                hasher.update(first.context);
+                hasher.update(first.view.to_le_bytes());
+                hasher.update(first.sequence.to_le_bytes());
+            }
+            Evidence::InvalidPof { header } => {
+                hasher.update([1]);
+                hasher.update(header.header.hash());
+            }
+            Evidence::ConflictingHeaders { first, .. } => {
+                hasher.update([2]);
+                hasher.update(first.context);
+                hasher.update(first.view.to_le_bytes());
+                hasher.update(first.sequence.to_le_bytes());
+            }
+        }
+        hasher.finalize().into()
+    }
+}
@@ -0,0 +1,397 @@
+// matrix.rs
+// TriadMatrix owns the ternary tree of Triads and addresses it by ternary coordinate
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use super::encoding::TriadRecord;
+use super::triad_structure::Triad;
+
+/// Position of a Triad in the matrix: the child index (0, 1 or 2) taken at each level
+/// from the genesis Triad, written as dot-separated digits such as "0.1.2".
+/// The genesis Triad itself has the empty coordinate "".
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TernaryCoordinate {
+    digits: Vec<u8>,
+}
+
+impl TernaryCoordinate {
+    /// Returns the coordinate of the genesis Triad.
+    pub fn root() -> Self {
+        TernaryCoordinate { digits: Vec::new() }
+    }
+
+    /// Builds a coordinate from child indices. Returns an error if any index is not 0, 1 or 2.
+    pub fn from_digits(digits: Vec<u8>) -> Result<Self, String> {
+        if digits.iter().any(|&d| d >= 3) {
+            return Err("Coordinate digits must be 0, 1, or 2".to_string());
+        }
+        Ok(TernaryCoordinate { digits })
+    }
+
+    /// Maps a breadth-first index (genesis = 0, its children = 1..=3, ...) to a coordinate.
+    pub fn from_index(mut index: u64) -> Self {
+        let mut digits = Vec::new();
+        while index > 0 {
+            digits.push(((index - 1) % 3) as u8);
+            index = (index - 1) / 3;
+        }
+        digits.reverse();
+        TernaryCoordinate { digits }
+    }
+
+    /// Returns the breadth-first index of this coordinate, or None if it overflows u64.
+    pub fn to_index(&self) -> Option<u64> {
+        self.digits.iter().try_fold(0u64, |index, &d| {
+            index.checked_mul(3)?.checked_add(1 + d as u64)
+        })
+    }
+
+    /// Child indices from the genesis Triad down to this position.
+    pub fn digits(&self) -> &[u8] {
+        &self.digits
+    }
+
+    /// Number of levels below the genesis Triad.
+    pub fn depth(&self) -> usize {
+        self.digits.len()
+    }
+
+    /// Returns the parent coordinate, or None for the genesis Triad.
+    pub fn parent(&self) -> Option<Self> {
+        let (_, parent) = self.digits.split_last()?;
+        Some(TernaryCoordinate { digits: parent.to_vec() })
+    }
+
+    /// Returns the coordinate of the child at `index`.
+    pub fn child(&self, index: u8) -> Result<Self, String> {
+        if index >= 3 {
+            return Err("Child index must be 0, 1, or 2".to_string());
+        }
+        let mut digits = self.digits.clone();
+        digits.push(index);
+        Ok(TernaryCoordinate { digits })
+    }
+}
+
+impl fmt::Display for TernaryCoordinate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.digits.iter().map(|d| d.to_string()).collect();
+        write!(f, "{}", parts.join("."))
+    }
+}
+
+impl FromStr for TernaryCoordinate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(TernaryCoordinate::root());
+        }
+        let digits = s.split('.')
+            .map(|part| match part {
+                "0" => Ok(0),
+                "1" => Ok(1),
+                "2" => Ok(2),
+                _ => Err(format!("Invalid ternary coordinate: {}", s)),
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        Ok(TernaryCoordinate { digits })
+    }
+}
+
+/// TriadMatrix owns the whole Triad tree, rooted at the genesis Triad.
+/// Triads are placed breadth-first, so the tree stays complete and both insertion
+/// and lookup walk at most log3(N) levels.
+pub struct TriadMatrix {
+    genesis: Triad,
+    count: u64,
+}
+
+impl TriadMatrix {
+    /// Creates a matrix rooted at the given genesis Triad.
+    /// Returns an error if genesis already has children; the matrix places every Triad itself.
+    pub fn new(genesis: Triad) -> Result<Self, String> {
+        if genesis.child_references.iter().any(|c| c.is_some()) {
+            return Err("Genesis triad must not have children".to_string());
+        }
+        Ok(TriadMatrix { genesis, count: 1 })
+    }
+
+    /// Returns the genesis Triad.
+    pub fn genesis(&self) -> &Triad {
+        &self.genesis
+    }
+
+    /// Number of Triads in the matrix, including genesis.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Work of every Triad in the matrix combined, each weighed by `work`, e.g.
+    /// `fork_choice::triad_work` with the puzzle the Triads were solved for.
+    pub fn total_work<F: Fn(&Triad) -> u128>(&self, work: F) -> u128 {
+        self.iter_pre_order().map(|(_, triad)| work(triad)).sum()
+    }
+
+    /// Work accumulated on the branch from genesis down to the Triad at `coordinate`, each
+    /// Triad weighed by `work`. Returns None if the coordinate is empty.
+    pub fn cumulative_work<F: Fn(&Triad) -> u128>(&self, coordinate: &TernaryCoordinate, work: F) -> Option<u128> {
+        let mut triad = &self.genesis;
+        let mut total = work(triad);
+        for &d in coordinate.digits() {
+            triad = triad.get_child(d as usize)?;
+            total += work(triad);
+        }
+        Some(total)
+    }
+
+    /// Depth of the deepest Triad; 0 when the matrix only holds genesis.
+    pub fn depth(&self) -> usize {
+        TernaryCoordinate::from_index(self.count - 1).depth()
+    }
+
+    /// Coordinate the next inserted Triad will occupy.
+    pub fn next_coordinate(&self) -> TernaryCoordinate {
+        TernaryCoordinate::from_index(self.count)
+    }
+
+    /// The `parent_hash` the next inserted Triad must carry.
+    pub fn next_parent_hash(&self) -> [u8; 32] {
+        let parent = self.next_coordinate().parent().unwrap_or_default();
+        self.get(&parent)
+            .expect("breadth-first placement keeps every parent slot filled")
+            .header_hash()
+    }
+
+    /// Looks up the Triad at a coordinate.
+    pub fn get(&self, coordinate: &TernaryCoordinate) -> Option<&Triad> {
+        coordinate.digits().iter().try_fold(&self.genesis, |triad, &d| triad.get_child(d as usize))
+    }
+
+    /// Timestamps of the last `count` Triads inserted, oldest first, for difficulty retargeting.
+    /// Breadth-first placement means insertion order is breadth-first index order.
+    pub fn recent_timestamps(&self, count: usize) -> Vec<u64> {
+        let start = self.count.saturating_sub(count as u64);
+        (start..self.count)
+            .filter_map(|index| self.get(&TernaryCoordinate::from_index(index)))
+            .map(|triad| triad.timestamp)
+            .collect()
+    }
+
+    /// Snapshots the ancestors of the Triad at `coordinate`, from its parent up to genesis,
+    /// in the form `anchor::verify_anchor` expects. Returns None if the coordinate is empty.
+    pub fn anchor_path(&self, coordinate: &TernaryCoordinate) -> Option<Vec<TriadRecord>> {
+        self.get(coordinate)?;
+        let mut path = Vec::with_capacity(coordinate.depth());
+        let mut current = coordinate.parent();
+        while let Some(ancestor) = current {
+            path.push(self.get(&ancestor)?.to_record());
+            current = ancestor.parent();
+        }
+        Some(path)
+    }
+
+    /// Inserts a Triad at the next free slot in breadth-first order.
+    /// The Triad must have no children and its `parent_hash` must equal the header hash
+    /// of the Triad it is placed under. Returns the coordinate it was placed at.
+    pub fn insert(&mut self, triad: Triad) -> Result<TernaryCoordinate, String> {
+        if triad.child_references.iter().any(|c| c.is_some()) {
+            return Err("Inserted triad must not have children".to_string());
+        }
+        let coordinate = self.next_coordinate();
+        let (&slot, parent_digits) = coordinate.digits().split_last()
+            .expect("next coordinate is never genesis");
+
+        let mut parent = &mut self.genesis;
+        for &d in parent_digits {
+            parent = parent.child_references[d as usize]
+                .as_deref_mut()
+                .ok_or_else(|| format!("Missing triad on the path to {}", coordinate))?;
+        }
+        if triad.parent_hash != parent.header_hash() {
+            let parent_coordinate = coordinate.parent().unwrap_or_default();
+            return Err(format!("Triad parent_hash does not match the triad at \"{}\"", parent_coordinate));
+        }
+        parent.add_child(slot as usize, triad)?;
+        self.count += 1;
+        Ok(coordinate)
+    }
+
+    /// Iterates over all Triads depth-first, visiting each parent before its children.
+    pub fn iter_pre_order(&self) -> PreOrderIter<'_> {
+        PreOrderIter {
+            stack: vec![(TernaryCoordinate::root(), &self.genesis)],
+        }
+    }
+
+    /// Iterates over all Triads breadth-first, one level at a time.
+    pub fn iter_level_order(&self) -> LevelOrderIter<'_> {
+        LevelOrderIter {
+            queue: VecDeque::from(vec![(TernaryCoordinate::root(), &self.genesis)]),
+        }
+    }
+}
+
+fn children_of<'a>(coordinate: &TernaryCoordinate, triad: &'a Triad) -> Vec<(TernaryCoordinate, &'a Triad)> {
+    (0..3u8)
+        .filter_map(|i| triad.get_child(i as usize).map(|child| {
+            (coordinate.child(i).expect("index below 3"), child)
+        }))
+        .collect()
+}
+
+/// Pre-order iterator returned by `TriadMatrix::iter_pre_order`.
+pub struct PreOrderIter<'a> {
+    stack: Vec<(TernaryCoordinate, &'a Triad)>,
+}
+
+impl<'a> Iterator for PreOrderIter<'a> {
+    type Item = (TernaryCoordinate, &'a Triad);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (coordinate, triad) = self.stack.pop()?;
+        self.stack.extend(children_of(&coordinate, triad).into_iter().rev());
+        Some((coordinate, triad))
+    }
+}
+
+/// Level-order iterator returned by `TriadMatrix::iter_level_order`.
+pub struct LevelOrderIter<'a> {
+    queue: VecDeque<(TernaryCoordinate, &'a Triad)>,
+}
+
+impl<'a> Iterator for LevelOrderIter<'a> {
+    type Item = (TernaryCoordinate, &'a Triad);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (coordinate, triad) = self.queue.pop_front()?;
+        self.queue.extend(children_of(&coordinate, triad));
+        Some((coordinate, triad))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::triad_matrix::triad_structure::Transaction;
+
+    fn linked_triad(matrix: &TriadMatrix, amount: u64) -> Triad {
+        let mut triad = Triad::new();
+        triad.insert_transaction(Transaction {
+            sender: "user1".to_string(),
+            receiver: "user2".to_string(),
+            amount,
+            timestamp: amount,
+        });
+        triad.parent_hash = matrix.next_parent_hash();
+        triad.timestamp = 1_000 + amount;
+        triad
+    }
+
+    fn matrix_with(n: u64) -> TriadMatrix {
+        let mut matrix = TriadMatrix::new(Triad::genesis(None)).unwrap();
+        for i in 0..n {
+            let triad = linked_triad(&matrix, i);
+            matrix.insert(triad).unwrap();
+        }
+        matrix
+    }
+
+    #[test]
+    fn test_coordinate_parse_and_display() {
+        let coordinate: TernaryCoordinate = "0.1.2".parse().unwrap();
+        assert_eq!(coordinate.digits(), &[0, 1, 2]);
+        assert_eq!(coordinate.to_string(), "0.1.2");
+        assert_eq!(coordinate.parent().unwrap().to_string(), "0.1");
+        assert_eq!("".parse::<TernaryCoordinate>().unwrap(), TernaryCoordinate::root());
+        assert!("0.3".parse::<TernaryCoordinate>().is_err());
+        assert!("0..1".parse::<TernaryCoordinate>().is_err());
+    }
+
+    #[test]
+    fn test_coordinate_index_round_trip() {
+        for index in 0..200 {
+            let coordinate = TernaryCoordinate::from_index(index);
+            assert_eq!(coordinate.to_index(), Some(index));
+        }
+        assert_eq!(TernaryCoordinate::from_index(4).to_string(), "0.0");
+        assert_eq!(TernaryCoordinate::from_index(12).to_string(), "2.2");
+    }
+
+    #[test]
+    fn test_breadth_first_insertion() {
+        let matrix = matrix_with(5);
+        assert_eq!(matrix.count(), 6);
+        assert_eq!(matrix.depth(), 2);
+        for coordinate in ["", "0", "1", "2", "0.0", "0.1"] {
+            assert!(matrix.get(&coordinate.parse().unwrap()).is_some(), "missing {}", coordinate);
+        }
+        assert!(matrix.get(&"0.2".parse().unwrap()).is_none());
+        assert_eq!(matrix.next_coordinate().to_string(), "0.2");
+    }
+
+    #[test]
+    fn test_insert_enforces_parent_hash() {
+        let mut matrix = matrix_with(3);
+        let mut triad = linked_triad(&matrix, 99);
+        triad.parent_hash = matrix.genesis().header_hash(); // next slot is under "0", not genesis
+        assert!(matrix.insert(triad).is_err());
+        assert_eq!(matrix.count(), 4);
+
+        let triad = linked_triad(&matrix, 99);
+        let expected = matrix.get(&"0".parse().unwrap()).unwrap().header_hash();
+        assert_eq!(triad.parent_hash, expected);
+        assert_eq!(matrix.insert(triad).unwrap().to_string(), "0.0");
+    }
+
+    #[test]
+    fn test_insert_rejects_triad_with_children() {
+        let mut matrix = matrix_with(0);
+        let mut triad = linked_triad(&matrix, 1);
+        triad.add_child(0, Triad::new()).unwrap();
+        assert!(matrix.insert(triad).is_err());
+    }
+
+    #[test]
+    fn test_new_rejects_genesis_with_children() {
+        let mut genesis = Triad::genesis(None);
+        genesis.add_child(0, Triad::new()).unwrap();
+        assert!(TriadMatrix::new(genesis).is_err());
+    }
+
+    #[test]
+    fn test_recent_timestamps() {
+        let matrix = matrix_with(5);
+        assert_eq!(matrix.recent_timestamps(3), vec![1_002, 1_003, 1_004]);
+        // Asking for more than exist returns genesis (timestamp 0) and everything after it.
+        assert_eq!(matrix.recent_timestamps(10), vec![0, 1_000, 1_001, 1_002, 1_003, 1_004]);
+        assert!(matrix.recent_timestamps(0).is_empty());
+    }
+
+    #[test]
+    fn test_work_accumulates_along_branches() {
+        // Weigh each Triad by its transaction amount plus one; genesis has none.
+        let matrix = matrix_with(4);
+        let weight = |triad: &Triad| triad.transactions.iter().map(|tx| tx.amount as u128 + 1).sum::<u128>();
+        let work = |c: &str| matrix.cumulative_work(&c.parse().unwrap(), weight).unwrap();
+        assert_eq!(work(""), 0);
+        assert_eq!(work("0"), 1);
+        assert_eq!(work("2"), 3);
+        assert_eq!(work("0.0"), 1 + 4);
+        assert_eq!(matrix.total_work(weight), 1 + 2 + 3 + 4);
+        assert!(matrix.cumulative_work(&"0.1".parse().unwrap(), weight).is_none());
+    }
+
+    #[test]
+    fn test_iterators() {
+        let matrix = matrix_with(5);
+        let pre_order: Vec<String> = matrix.iter_pre_order().map(|(c, _)| c.to_string()).collect();
+        assert_eq!(pre_order, vec!["", "0", "0.0", "0.1", "1", "2"]);
+        let level_order: Vec<String> = matrix.iter_level_order().map(|(c, _)| c.to_string()).collect();
+        assert_eq!(level_order, vec!["", "0", "1", "2", "0.0", "0.1"]);
+        for (coordinate, triad) in matrix.iter_level_order() {
+            assert_eq!(matrix.get(&coordinate).unwrap().hash(), triad.hash());
+        }
+    }
+}
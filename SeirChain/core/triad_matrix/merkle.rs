@@ -0,0 +1,228 @@
+// merkle.rs
+// Merkle tree construction and inclusion proofs for Triad transactions
+//
+// Odd-leaf rule: when a level has an odd number of nodes, the last node is paired
+// with itself. A single leaf is its own root and an empty tree has the zero root.
+//
+// Because of that rule, the trees over [a, b, c] and [a, b, c, c] share a root
+// (the CVE-2012-2459 ambiguity). Two defences live here:
+// - `is_mutated` flags leaf lists where a real pair hashes identical nodes, so a
+//   Triad carrying such a list can be rejected.
+// - `verify` binds a proof to its leaf count and only accepts a node paired with
+//   itself at the odd-leaf position, so no proof can place a leaf in the duplicated slot.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Proof that a transaction hash is the `index`-th leaf of a tree of `leaf_count` leaves.
+/// `siblings` lists the node paired with the running hash at each level, leaf level first.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub leaf_count: usize,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+/// Hashes two child nodes into their parent.
+pub fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    let result = hasher.finalize();
+    let mut hash_arr = [0u8; 32];
+    hash_arr.copy_from_slice(&result);
+    hash_arr
+}
+
+/// Builds every level of the tree, from the leaves up to the single root.
+/// Returns no levels for an empty leaf list.
+pub fn merkle_levels(leaves: Vec<[u8; 32]>) -> Vec<Vec<[u8; 32]>> {
+    if leaves.is_empty() {
+        return Vec::new();
+    }
+    let mut levels = vec![leaves];
+    while levels[levels.len() - 1].len() > 1 {
+        let level = &levels[levels.len() - 1];
+        let next = level.chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        levels.push(next);
+    }
+    levels
+}
+
+/// Computes the Merkle root of the given leaves, or the zero hash if there are none.
+pub fn merkle_root(leaves: Vec<[u8; 32]>) -> [u8; 32] {
+    merkle_levels(leaves)
+        .last()
+        .map(|level| level[0])
+        .unwrap_or([0u8; 32])
+}
+
+/// Returns true if any real pair in the tree hashes two identical nodes, which means
+/// another, shorter leaf list produces the same root.
+pub fn is_mutated(leaves: Vec<[u8; 32]>) -> bool {
+    merkle_levels(leaves).iter().any(|level| {
+        level.chunks(2).any(|pair| pair.len() == 2 && pair[0] == pair[1])
+    })
+}
+
+/// Builds an inclusion proof for the leaf at `index`.
+pub fn prove(leaves: Vec<[u8; 32]>, index: usize) -> Result<MerkleProof, String> {
+    let leaf_count = leaves.len();
+    if index >= leaf_count {
+        return Err(format!("Leaf index {} out of range for {} leaves", index, leaf_count));
+    }
+    let levels = merkle_levels(leaves);
+    let mut position = index;
+    let mut siblings = Vec::with_capacity(levels.len() - 1);
+    for level in &levels[..levels.len() - 1] {
+        let sibling = if position.is_multiple_of(2) {
+            *level.get(position + 1).unwrap_or(&level[position])
+        } else {
+            level[position - 1]
+        };
+        siblings.push(sibling);
+        position /= 2;
+    }
+    Ok(MerkleProof { index, leaf_count, siblings })
+}
+
+/// Verifies that `tx_hash` is included under `root` at the position the proof claims.
+pub fn verify(proof: &MerkleProof, tx_hash: &[u8; 32], root: &[u8; 32]) -> bool {
+    if proof.index >= proof.leaf_count {
+        return false;
+    }
+    let mut hash = *tx_hash;
+    let mut position = proof.index;
+    let mut width = proof.leaf_count;
+    let mut siblings = proof.siblings.iter();
+
+    while width > 1 {
+        let sibling = match siblings.next() {
+            Some(sibling) => sibling,
+            None => return false,
+        };
+        let is_odd_tail = position == width - 1 && !width.is_multiple_of(2);
+        // Only the odd tail may be paired with itself; anywhere else an identical
+        // sibling is the duplicated-leaf mutation.
+        if is_odd_tail != (*sibling == hash) {
+            return false;
+        }
+        hash = if position.is_multiple_of(2) {
+            hash_pair(&hash, sibling)
+        } else {
+            hash_pair(sibling, &hash)
+        };
+        position /= 2;
+        width = width.div_ceil(2);
+    }
+
+    siblings.next().is_none() && hash == *root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::triad_matrix::triad_structure::{Transaction, Triad};
+
+    fn tx(amount: u64) -> Transaction {
+        Transaction {
+            sender: "user1".to_string(),
+            receiver: "user2".to_string(),
+            amount,
+            timestamp: amount,
+        }
+    }
+
+    fn triad_with(amounts: &[u64]) -> Triad {
+        Triad::genesis(Some(amounts.iter().map(|&a| tx(a)).collect()))
+    }
+
+    #[test]
+    fn test_prove_and_verify_every_leaf() {
+        for size in 1..=9u64 {
+            let amounts: Vec<u64> = (0..size).collect();
+            let triad = triad_with(&amounts);
+            for index in 0..size as usize {
+                let proof = triad.prove_transaction(index).unwrap();
+                let tx_hash = triad.transactions[index].hash();
+                assert!(verify(&proof, &tx_hash, &triad.merkle_root), "size {} index {}", size, index);
+            }
+            assert!(triad.prove_transaction(size as usize).is_err());
+        }
+    }
+
+    #[test]
+    fn test_single_leaf_is_root() {
+        let triad = triad_with(&[1]);
+        assert_eq!(triad.merkle_root, tx(1).hash());
+        let proof = triad.prove_transaction(0).unwrap();
+        assert!(proof.siblings.is_empty());
+        assert!(verify(&proof, &tx(1).hash(), &triad.merkle_root));
+    }
+
+    #[test]
+    fn test_verify_rejects_tampering() {
+        let triad = triad_with(&[1, 2, 3, 4, 5]);
+        let proof = triad.prove_transaction(2).unwrap();
+        let tx_hash = tx(3).hash();
+
+        assert!(!verify(&proof, &tx(4).hash(), &triad.merkle_root));
+        assert!(!verify(&proof, &tx_hash, &[0u8; 32]));
+
+        let mut bad_sibling = proof.clone();
+        bad_sibling.siblings[0][0] ^= 1;
+        assert!(!verify(&bad_sibling, &tx_hash, &triad.merkle_root));
+
+        let mut bad_index = proof.clone();
+        bad_index.index = 3;
+        assert!(!verify(&bad_index, &tx_hash, &triad.merkle_root));
+
+        let mut extra_sibling = proof.clone();
+        extra_sibling.siblings.push([0u8; 32]);
+        assert!(!verify(&extra_sibling, &tx_hash, &triad.merkle_root));
+
+        let mut out_of_range = proof;
+        out_of_range.index = 5;
+        assert!(!verify(&out_of_range, &tx_hash, &triad.merkle_root));
+    }
+
+    #[test]
+    fn test_odd_leaf_duplication_rule() {
+        let triad = triad_with(&[1, 2, 3]);
+        let (a, b, c) = (tx(1).hash(), tx(2).hash(), tx(3).hash());
+        assert_eq!(triad.merkle_root, hash_pair(&hash_pair(&a, &b), &hash_pair(&c, &c)));
+
+        let proof = triad.prove_transaction(2).unwrap();
+        assert_eq!(proof.siblings[0], c);
+        assert!(verify(&proof, &c, &triad.merkle_root));
+    }
+
+    #[test]
+    fn test_duplicate_leaf_ambiguity() {
+        // CVE-2012-2459: duplicating the odd tail leaves the root unchanged.
+        let honest = triad_with(&[1, 2, 3]);
+        let mutated = triad_with(&[1, 2, 3, 3]);
+        assert_eq!(honest.merkle_root, mutated.merkle_root);
+        assert!(!honest.is_merkle_mutated());
+        assert!(mutated.is_merkle_mutated());
+
+        // A proof for the duplicated slot does not verify, even though the root matches.
+        let c = tx(3).hash();
+        let duplicated_slot = mutated.prove_transaction(3).unwrap();
+        assert!(!verify(&duplicated_slot, &c, &honest.merkle_root));
+
+        // Neither does an honest proof re-labelled as coming from the longer list.
+        let mut relabelled = honest.prove_transaction(2).unwrap();
+        relabelled.leaf_count = 4;
+        assert!(!verify(&relabelled, &c, &honest.merkle_root));
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        assert_eq!(merkle_root(Vec::new()), [0u8; 32]);
+        assert!(prove(Vec::new(), 0).is_err());
+        assert!(!is_mutated(Vec::new()));
+    }
+}
@@ -1 +1,6 @@
+pub mod anchor;
+pub mod encoding;
+pub mod evidence;
+pub mod matrix;
+pub mod merkle;
 pub mod triad_structure;
@@ -1,17 +1,24 @@
 // triad_structure.rs
 // Defines the Triad structure, core dependency for SeirChain
 
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use super::merkle::{self, MerkleProof};
+use super::evidence::Evidence;
 
+#[derive(Clone)]
 pub struct Triad {
     pub transactions: Vec<Transaction>,
     pub child_references: [Option<Box<Triad>>; 3],
     pub merkle_root: [u8; 32],
     pub proof_of_fractal_data: ProofOfFractalData,
     pub parent_hash: [u8; 32],
+    pub timestamp: u64,
+    /// Proof of misbehaviour this Triad puts on record, committed to by the header's evidence root.
+    pub evidence: Vec<Evidence>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
     pub sender: String,
     pub receiver: String,
@@ -19,6 +26,7 @@ pub struct Transaction {
     pub timestamp: u64,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProofOfFractalData {
     pub nonce: u64,
     pub difficulty: u32,
@@ -33,6 +41,8 @@ impl Triad {
             merkle_root: [0u8; 32],
             proof_of_fractal_data: ProofOfFractalData::new(),
             parent_hash: [0u8; 32],
+            timestamp: 0,
+            evidence: Vec::new(),
         }
     }
 
@@ -44,33 +54,32 @@ impl Triad {
             merkle_root: [0u8; 32],
             proof_of_fractal_data: ProofOfFractalData::new(),
             parent_hash: [0u8; 32], // No parent for genesis
+            timestamp: 0,
+            evidence: Vec::new(),
         };
         triad.calculate_merkle_root();
         triad
     }
 
+    /// Recomputes the Merkle root over the transaction hashes.
+    /// See `merkle` for the odd-leaf duplication rule.
     pub fn calculate_merkle_root(&mut self) {
-        let mut hashes: Vec<[u8; 32]> = self.transactions.iter()
-            .map(|tx| tx.hash())
-            .collect();
-
-        while hashes.len() > 1 {
-            let mut new_hashes = Vec::new();
-            for i in (0..hashes.len()).step_by(2) {
-                let left = hashes[i];
-                let right = if i + 1 < hashes.len() { hashes[i + 1] } else { left };
-                let mut hasher = Sha256::new();
-                hasher.update(&left);
-                hasher.update(&right);
-                let result = hasher.finalize();
-                let mut hash_arr = [0u8; 32];
-                hash_arr.copy_from_slice(&result);
-                new_hashes.push(hash_arr);
-            }
-            hashes = new_hashes;
-        }
+        self.merkle_root = merkle::merkle_root(self.transaction_hashes());
+    }
+
+    /// Builds a Merkle inclusion proof for the transaction at `index`.
+    pub fn prove_transaction(&self, index: usize) -> Result<MerkleProof, String> {
+        merkle::prove(self.transaction_hashes(), index)
+    }
+
+    /// Returns true if the transaction list hits the duplicate-leaf ambiguity, i.e. a
+    /// shorter list would produce the same Merkle root. Such a Triad should be rejected.
+    pub fn is_merkle_mutated(&self) -> bool {
+        merkle::is_mutated(self.transaction_hashes())
+    }
 
-        self.merkle_root = if !hashes.is_empty() { hashes[0] } else { [0u8; 32] };
+    fn transaction_hashes(&self) -> Vec<[u8; 32]> {
+        self.transactions.iter().map(|tx| tx.hash()).collect()
     }
 
     pub fn insert_transaction(&mut self, transaction: Transaction) {
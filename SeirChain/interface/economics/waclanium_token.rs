@@ -139,6 +139,27 @@ impl WaclaniumToken {
         Ok(())
     }
 
+    /// Takes up to `amount` from a user's stake as a penalty and credits it to `beneficiary`,
+    /// or burns it if there is none. Returns the amount actually taken.
+    pub fn slash(&mut self, user: &str, amount: u64, beneficiary: Option<&str>) -> u64 {
+        let stake = self.get_stake(user);
+        let taken = amount.min(stake);
+        self.stakes.insert(user.to_string(), stake - taken);
+        match beneficiary {
+            Some(to) => {
+                let balance = self.get_balance(to);
+                self.balances.insert(to.to_string(), balance + taken);
+            }
+            None => self.total_supply -= taken,
+        }
+        taken
+    }
+
+    /// Gets the total supply in circulation, staked tokens included.
+    pub fn total_supply(&self) -> u64 {
+        self.total_supply
+    }
+
     /// Gets the balance of a user.
     pub fn get_balance(&self, user: &str) -> u64 {
         self.balances.get(user).cloned().unwrap_or(0)
@@ -212,6 +233,21 @@ mod tests {
         assert_eq!(token.get_stake("user1"), 0);
     }
 
+    #[test]
+    fn test_slash() {
+        let mut token = WaclaniumToken::new(1000, 10000, 1);
+        token.mint("user1", 100).unwrap();
+        token.stake("user1", 80).unwrap();
+        assert_eq!(token.slash("user1", 30, None), 30);
+        assert_eq!(token.get_stake("user1"), 50);
+        assert_eq!(token.total_supply(), 1070);
+        // Never more than is staked; the balance is untouched.
+        assert_eq!(token.slash("user1", 70, Some("user2")), 50);
+        assert_eq!(token.get_balance("user2"), 50);
+        assert_eq!(token.get_balance("user1"), 20);
+        assert_eq!(token.total_supply(), 1070);
+    }
+
     #[test]
     fn test_mint() {
         let mut token = WaclaniumToken::new(1000, 10000, 1);
@@ -11,6 +11,8 @@ pub enum TriadActivity {
     ConsensusReached(String),
     /// Proof of Fractal puzzle was solved with a nonce.
     ProofOfFractalSolved(u64),
+    /// A Triad, given by its hex hash, reached finality depth.
+    TriadFinalized(String),
     /// Other activity with a description.
     Other(String),
 }
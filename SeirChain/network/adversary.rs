@@ -0,0 +1,524 @@
+// adversary.rs
+// Byzantine behaviours for adversarial consensus tests, and the safety check they must not break
+
+use ed25519_dalek::SigningKey;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::{BTreeMap, BTreeSet};
+use crate::core::consensus::pbft::{ConsensusMessage, ConsensusTransport, LocalTransport, PbftReplica, ViewChange};
+use crate::core::consensus::puzzle::{pof_hash, FractalPuzzle, SelfSimilarPuzzle};
+use crate::core::consensus::solver::{NonceSource, SolveOutcome, SolverConfig};
+use crate::core::triad_matrix::encoding::TriadRecord;
+use crate::core::triad_matrix::evidence::{Vote, VoteKind};
+
+/// Misbehaviour assigned to a node: it sees everything the node sends to each recipient
+/// and decides what actually goes out. The node's replica itself stays honest, so its
+/// own state shows what an honest replica would have done.
+pub trait ByzantineBehaviour {
+    /// Returns the messages `from` really sends to `to` in place of `message`.
+    fn outgoing(&mut self, from: &str, to: &str, message: ConsensusMessage, rng: &mut dyn RngCore) -> Vec<ConsensusMessage>;
+
+    /// Shows the behaviour a message `from` sends `to`, whoever they are, as an attacker
+    /// watching the whole network would see it. Does nothing unless overridden.
+    fn observe(&mut self, _from: &str, _to: &str, _message: &ConsensusMessage) {}
+}
+
+/// Sends nothing at all, as if crashed, while still receiving.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Silent;
+
+impl ByzantineBehaviour for Silent {
+    fn outgoing(&mut self, _from: &str, _to: &str, _message: ConsensusMessage, _rng: &mut dyn RngCore) -> Vec<ConsensusMessage> {
+        Vec::new()
+    }
+}
+
+/// A different proposal for the same round: the same Triad one second later, its
+/// Proof-of-Fractal solved again with `puzzle` at the same difficulty so that it is as
+/// valid as the original.
+pub fn conflicting_proposal<P: FractalPuzzle>(record: &TriadRecord, puzzle: &P) -> TriadRecord {
+    let mut conflicting = record.clone();
+    let header = &mut conflicting.header;
+    header.timestamp = header.timestamp.wrapping_add(1);
+    let config = SolverConfig::new(NonceSource::Sequential { start: 0, end: u64::MAX });
+    let outcome = puzzle.solve(&header.pof_preimage(), header.proof_of_fractal_data.difficulty, &config);
+    if let SolveOutcome::Solved { nonce, hash, .. } = outcome {
+        header.proof_of_fractal_data.nonce = nonce;
+        header.proof_of_fractal_data.hash = hash;
+    }
+    conflicting
+}
+
+/// Replaces the proposal a pre-prepare or new-view message carries with `change` of it,
+/// signed again with the sender's `key` so honest replicas cannot tell it from the original
+/// by its signature. Other messages pass through.
+fn replace_proposal(message: ConsensusMessage, key: &SigningKey, change: impl Fn(&TriadRecord) -> TriadRecord) -> ConsensusMessage {
+    match message {
+        ConsensusMessage::PrePrepare { context, view, sequence, proposal, sender, .. } => {
+            ConsensusMessage::pre_prepare(context, view, sequence, change(&proposal), &sender, key)
+        }
+        ConsensusMessage::NewView { context, view, view_changes, proposal, sender, .. } => {
+            let proposal = proposal.map(|(sequence, record)| (sequence, Box::new(change(&record))));
+            ConsensusMessage::new_view(context, view, view_changes, proposal, &sender, key)
+        }
+        other => other,
+    }
+}
+
+/// As leader, shows each recipient at random either its real proposal or a conflicting one,
+/// both signed with the node's own key and both carrying a valid Proof-of-Fractal for
+/// `puzzle`.
+#[derive(Clone, Debug)]
+pub struct EquivocatingLeader<P: FractalPuzzle = SelfSimilarPuzzle> {
+    key: SigningKey,
+    pub puzzle: P,
+}
+
+impl EquivocatingLeader {
+    /// An equivocating leader signing with the node's `key`.
+    pub fn new(key: SigningKey) -> Self {
+        EquivocatingLeader::with_puzzle(key, SelfSimilarPuzzle)
+    }
+}
+
+impl<P: FractalPuzzle> EquivocatingLeader<P> {
+    /// An equivocating leader whose conflicting proposals solve `puzzle`.
+    pub fn with_puzzle(key: SigningKey, puzzle: P) -> Self {
+        EquivocatingLeader { key, puzzle }
+    }
+}
+
+impl<P: FractalPuzzle> ByzantineBehaviour for EquivocatingLeader<P> {
+    fn outgoing(&mut self, _from: &str, _to: &str, message: ConsensusMessage, rng: &mut dyn RngCore) -> Vec<ConsensusMessage> {
+        if !rng.gen_bool(0.5) {
+            return vec![message];
+        }
+        vec![replace_proposal(message, &self.key, |record| conflicting_proposal(record, &self.puzzle))]
+    }
+}
+
+/// Takes part in proposals and view changes but never votes, or only never commits.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VoteWithholder {
+    pub commits_only: bool,
+}
+
+impl ByzantineBehaviour for VoteWithholder {
+    fn outgoing(&mut self, _from: &str, _to: &str, message: ConsensusMessage, _rng: &mut dyn RngCore) -> Vec<ConsensusMessage> {
+        match message {
+            ConsensusMessage::Commit(_) => Vec::new(),
+            ConsensusMessage::Prepare(_) if !self.commits_only => Vec::new(),
+            other => vec![other],
+        }
+    }
+}
+
+/// Votes across sub-fractals, as a node sitting in two of them could to back both sides:
+/// each vote goes out alongside one for the same round, signed with the node's own key,
+/// for the latest proposal it has seen made in a sub-fractal it is not part of. Until it
+/// has seen one it votes honestly.
+#[derive(Clone, Debug)]
+pub struct DoubleVoter {
+    key: SigningKey,
+    /// Digests of the proposals seen on the network, oldest first, with who they went to.
+    proposals: Vec<([u8; 32], BTreeSet<String>)>,
+}
+
+impl DoubleVoter {
+    /// A double voter signing with the node's `key`.
+    pub fn new(key: SigningKey) -> Self {
+        DoubleVoter { key, proposals: Vec::new() }
+    }
+
+    /// The latest proposal seen that was not sent to `node`, so was made elsewhere.
+    fn foreign_proposal(&self, node: &str) -> Option<[u8; 32]> {
+        self.proposals.iter().rev().find(|(_, recipients)| !recipients.contains(node)).map(|(digest, _)| *digest)
+    }
+
+    fn second_vote(&self, kind: VoteKind, vote: &Vote) -> Option<Vote> {
+        let digest = self.foreign_proposal(&vote.sender).filter(|digest| *digest != vote.digest)?;
+        Some(Vote::sign(kind, vote.context, vote.view, vote.sequence, digest, &vote.sender, &self.key))
+    }
+}
+
+impl ByzantineBehaviour for DoubleVoter {
+    fn outgoing(&mut self, _from: &str, _to: &str, message: ConsensusMessage, _rng: &mut dyn RngCore) -> Vec<ConsensusMessage> {
+        let second = match &message {
+            ConsensusMessage::Prepare(vote) => self.second_vote(VoteKind::Prepare, vote).map(ConsensusMessage::Prepare),
+            ConsensusMessage::Commit(vote) => self.second_vote(VoteKind::Commit, vote).map(ConsensusMessage::Commit),
+            _ => None,
+        };
+        second.into_iter().chain([message]).collect()
+    }
+
+    fn observe(&mut self, _from: &str, to: &str, message: &ConsensusMessage) {
+        let digest = match message {
+            ConsensusMessage::PrePrepare { proposal, .. } => proposal.hash(),
+            ConsensusMessage::NewView { proposal: Some((_, proposal)), .. } => proposal.hash(),
+            _ => return,
+        };
+        match self.proposals.iter_mut().find(|(seen, _)| *seen == digest) {
+            Some((_, recipients)) => {
+                recipients.insert(to.to_string());
+            }
+            None => self.proposals.push((digest, BTreeSet::from([to.to_string()]))),
+        }
+    }
+}
+
+/// As leader, proposes Triads whose Proof-of-Fractal nonce has been tampered with, or that
+/// claim difficulty 0 and so carry a PoF any hash satisfies, signed with the node's own key.
+#[derive(Clone, Debug)]
+pub struct InvalidPofProposer {
+    key: SigningKey,
+    pub zero_difficulty: bool,
+}
+
+impl InvalidPofProposer {
+    /// An invalid-PoF proposer signing with the node's `key` that tampers with the nonce.
+    pub fn new(key: SigningKey) -> Self {
+        InvalidPofProposer { key, zero_difficulty: false }
+    }
+
+    /// An invalid-PoF proposer signing with the node's `key` that proposes at difficulty 0.
+    pub fn zero_difficulty(key: SigningKey) -> Self {
+        InvalidPofProposer { key, zero_difficulty: true }
+    }
+
+    fn tamper(record: &TriadRecord, zero_difficulty: bool) -> TriadRecord {
+        let mut tampered = record.clone();
+        let header = &mut tampered.header;
+        if zero_difficulty {
+            // The difficulty is part of the preimage, so the hash is recomputed over it.
+            header.proof_of_fractal_data.difficulty = 0;
+            header.proof_of_fractal_data.hash = pof_hash(&header.pof_preimage(), header.proof_of_fractal_data.nonce);
+        } else {
+            header.proof_of_fractal_data.nonce ^= 1;
+        }
+        tampered
+    }
+}
+
+impl ByzantineBehaviour for InvalidPofProposer {
+    fn outgoing(&mut self, _from: &str, _to: &str, message: ConsensusMessage, _rng: &mut dyn RngCore) -> Vec<ConsensusMessage> {
+        let zero_difficulty = self.zero_difficulty;
+        vec![replace_proposal(message, &self.key, |record| Self::tamper(record, zero_difficulty))]
+    }
+}
+
+/// Tries to drag the group into a view it leads. Alongside its messages it asks each member
+/// for that view in the name of every member of the group, and announces it with a new-view
+/// message resting on those requests. Only its own request carries a real signature; the
+/// rest are signed with the node's own key under the other members' names.
+#[derive(Clone, Debug)]
+pub struct ViewChangeForger {
+    key: SigningKey,
+    /// The group's members in the order messages are broadcast to them, which is the order
+    /// leaders rotate in.
+    members: Vec<String>,
+    /// The recipients already sent the forgeries for each view, so each gets them once.
+    forged: BTreeSet<(u64, String)>,
+}
+
+impl ViewChangeForger {
+    /// A view-change forger signing with the node's `key`.
+    pub fn new(key: SigningKey) -> Self {
+        ViewChangeForger { key, members: Vec::new(), forged: BTreeSet::new() }
+    }
+
+    /// The first view after `view` that `node` leads, going by the members seen so far.
+    fn led_view(&self, node: &str, view: u64) -> Option<u64> {
+        let n = self.members.len() as u64;
+        let position = self.members.iter().position(|member| member == node)? as u64;
+        Some(view + 1 + (position + n - (view + 1) % n) % n)
+    }
+}
+
+impl ByzantineBehaviour for ViewChangeForger {
+    fn outgoing(&mut self, from: &str, to: &str, message: ConsensusMessage, _rng: &mut dyn RngCore) -> Vec<ConsensusMessage> {
+        if !self.members.iter().any(|member| member == to) {
+            self.members.push(to.to_string());
+        }
+        let Some(view) = self.led_view(from, message.round().0) else {
+            return vec![message];
+        };
+        if !self.forged.insert((view, to.to_string())) {
+            return vec![message];
+        }
+        let context = message.context();
+        let changes: Vec<ViewChange> = self
+            .members
+            .iter()
+            .map(|member| ViewChange::sign(context, view, 0, None, member, &self.key))
+            .collect();
+        let mut out = vec![message];
+        out.extend(changes.iter().cloned().map(ConsensusMessage::ViewChange));
+        out.push(ConsensusMessage::new_view(context, view, changes, None, from, &self.key));
+        out
+    }
+}
+
+/// Sends each message as normal, then replays one of the messages it sent earlier.
+#[derive(Clone, Debug, Default)]
+pub struct Replayer {
+    history: Vec<ConsensusMessage>,
+}
+
+impl ByzantineBehaviour for Replayer {
+    fn outgoing(&mut self, _from: &str, _to: &str, message: ConsensusMessage, rng: &mut dyn RngCore) -> Vec<ConsensusMessage> {
+        let mut out = vec![message.clone()];
+        if !self.history.is_empty() {
+            out.push(self.history[rng.gen_range(0..self.history.len())].clone());
+        }
+        self.history.push(message);
+        out
+    }
+}
+
+/// In-process transport in which the nodes given a behaviour misbehave. Everyone else is
+/// delivered to in order and without loss, like `LocalTransport`.
+pub struct AdversarialTransport {
+    inner: LocalTransport,
+    nodes: Vec<String>,
+    behaviours: BTreeMap<String, Box<dyn ByzantineBehaviour>>,
+    rng: ChaCha8Rng,
+}
+
+impl AdversarialTransport {
+    pub fn new(nodes: &[String], seed: u64) -> Self {
+        AdversarialTransport {
+            inner: LocalTransport::new(nodes),
+            nodes: nodes.to_vec(),
+            behaviours: BTreeMap::new(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Assigns `behaviour` to `node`.
+    pub fn with(mut self, node: &str, behaviour: Box<dyn ByzantineBehaviour>) -> Self {
+        self.behaviours.insert(node.to_string(), behaviour);
+        self
+    }
+
+    /// Returns true if `node` has been given a behaviour.
+    pub fn is_byzantine(&self, node: &str) -> bool {
+        self.behaviours.contains_key(node)
+    }
+}
+
+impl ConsensusTransport for AdversarialTransport {
+    fn broadcast(&mut self, from: &str, message: ConsensusMessage) {
+        for behaviour in self.behaviours.values_mut() {
+            for to in &self.nodes {
+                behaviour.observe(from, to, &message);
+            }
+        }
+        let behaviour = match self.behaviours.get_mut(from) {
+            Some(behaviour) => behaviour,
+            None => return self.inner.broadcast(from, message),
+        };
+        for to in &self.nodes {
+            for message in behaviour.outgoing(from, to, message.clone(), &mut self.rng) {
+                self.inner.send(to, message);
+            }
+        }
+    }
+
+    fn receive(&mut self, node: &str) -> Option<ConsensusMessage> {
+        self.inner.receive(node)
+    }
+}
+
+/// Checks PBFT safety across `replicas`: no two of them committed different proposals
+/// under the same sequence number. Pass only the honest replicas; what a Byzantine node
+/// claims to have committed proves nothing.
+pub fn check_safety<'a, I: IntoIterator<Item = &'a PbftReplica>>(replicas: I) -> Result<(), String> {
+    let mut decided: BTreeMap<u64, ([u8; 32], &str)> = BTreeMap::new();
+    for replica in replicas {
+        for committed in replica.committed() {
+            let digest = committed.certificate.digest;
+            match decided.get(&committed.sequence) {
+                Some((other, who)) if *other != digest => {
+                    return Err(format!(
+                        "{} and {} committed different triads at sequence {}",
+                        who,
+                        replica.id(),
+                        committed.sequence
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    decided.insert(committed.sequence, (digest, replica.id()));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::pbft::{drive, Phase};
+    use crate::core::consensus::proof_of_fractal::{solved_triad, verify_triad_pof_at};
+    use crate::core::consensus::puzzle::SelfSimilarPuzzle;
+    use crate::core::security::keys::{node_ids, Keystore};
+    use crate::core::triad_matrix::evidence::Evidence;
+    use crate::core::triad_matrix::triad_structure::Triad;
+
+    fn keys() -> Keystore {
+        Keystore::derive(&node_ids(7), &[0u8; 32])
+    }
+
+    fn replica(id: &str, nodes: &[String], f: usize) -> PbftReplica {
+        let keys = keys();
+        PbftReplica::new(id, nodes.to_vec(), f, keys.key(id).unwrap().clone(), keys.keyring()).unwrap()
+    }
+
+    /// A double voter that has watched node8 propose `foreign` to node9, in a sub-fractal
+    /// it is not part of.
+    fn double_voter(id: &str, foreign: &TriadRecord) -> Box<dyn ByzantineBehaviour> {
+        let key = keys().key(id).unwrap().clone();
+        let mut voter = DoubleVoter::new(key.clone());
+        voter.observe("node8", "node9", &ConsensusMessage::pre_prepare([0u8; 32], 0, 1, foreign.clone(), "node8", &key));
+        Box::new(voter)
+    }
+
+    fn equivocator(id: &str) -> Box<dyn ByzantineBehaviour> {
+        Box::new(EquivocatingLeader::new(keys().key(id).unwrap().clone()))
+    }
+
+    fn solved_record(timestamp: u64) -> TriadRecord {
+        solved_triad(Triad { timestamp, ..Triad::new() }, 1).to_record()
+    }
+
+    /// Runs one proposal through 3f+1 PoF-checking replicas with the given adversaries,
+    /// then asserts safety and that every honest replica committed the honest proposal.
+    /// Returns the honest replicas.
+    fn assert_honest_commit(n: usize, f: usize, adversaries: Vec<(&str, Box<dyn ByzantineBehaviour>)>) -> Vec<PbftReplica> {
+        let nodes = node_ids(n);
+        let mut transport = AdversarialTransport::new(&nodes, 42);
+        for (node, behaviour) in adversaries {
+            transport = transport.with(node, behaviour);
+        }
+        let mut group: Vec<PbftReplica> = nodes
+            .iter()
+            .map(|id| {
+                replica(id, &nodes, f)
+                    .with_view_timeout(3)
+                    .with_validator(Box::new(|record: &TriadRecord| {
+                        verify_triad_pof_at(&SelfSimilarPuzzle, 1, &record.clone().into_triad())
+                    }))
+            })
+            .collect();
+
+        let record = solved_record(1_000);
+        for replica in &mut group {
+            for message in replica.submit(record.clone()) {
+                transport.broadcast(replica.id(), message);
+            }
+        }
+        drive(&mut group, &mut transport, 500);
+
+        let honest: Vec<&PbftReplica> = group.iter().filter(|r| !transport.is_byzantine(r.id())).collect();
+        assert_eq!(check_safety(honest.iter().copied()), Ok(()));
+        for replica in honest {
+            assert_eq!(replica.proposal_phase(&record.hash()), Phase::Committed, "{}", replica.id());
+        }
+        group.retain(|r| !transport.is_byzantine(r.id()));
+        group
+    }
+
+    #[test]
+    fn test_equivocating_leader() {
+        // The conflicting proposal is a different Triad whose PoF holds just the same.
+        let record = solved_record(1_000);
+        let conflicting = conflicting_proposal(&record, &SelfSimilarPuzzle);
+        assert_ne!(conflicting.hash(), record.hash());
+        assert!(verify_triad_pof_at(&SelfSimilarPuzzle, 1, &conflicting.into_triad()));
+
+        assert_honest_commit(4, 1, vec![("node1", equivocator("node1"))]);
+        assert_honest_commit(7, 2, vec![("node1", equivocator("node1")), ("node2", equivocator("node2"))]);
+    }
+
+    #[test]
+    fn test_vote_withholding() {
+        assert_honest_commit(4, 1, vec![("node3", Box::new(VoteWithholder::default()))]);
+        assert_honest_commit(4, 1, vec![("node1", Box::new(VoteWithholder { commits_only: true }))]);
+    }
+
+    #[test]
+    fn test_double_voting() {
+        // Honest replicas keep evidence of the votes for the other sub-fractal's proposal,
+        // once per offence.
+        let foreign = solved_record(2_000);
+        let honest = assert_honest_commit(4, 1, vec![("node2", double_voter("node2", &foreign))]);
+        for replica in &honest {
+            assert!(!replica.evidence().is_empty(), "{}", replica.id());
+            for evidence in replica.evidence() {
+                assert_eq!(evidence.offender(), "node2");
+                assert_eq!(evidence.validate(&keys().keyring()), Ok(()));
+                let Evidence::DoubleVote { first, second, .. } = evidence else { panic!("{:?}", evidence) };
+                assert!(first.digest == foreign.hash() || second.digest == foreign.hash());
+            }
+        }
+        assert_honest_commit(7, 2, vec![("node1", double_voter("node1", &foreign)), ("node4", double_voter("node4", &foreign))]);
+
+        // With no other sub-fractal's proposal to back, it votes like anyone else.
+        let honest = assert_honest_commit(4, 1, vec![("node2", Box::new(DoubleVoter::new(keys().key("node2").unwrap().clone())))]);
+        assert!(honest.iter().all(|replica| replica.evidence().is_empty()));
+    }
+
+    #[test]
+    fn test_forged_view_changes() {
+        let forger = |id: &str| -> Box<dyn ByzantineBehaviour> { Box::new(ViewChangeForger::new(keys().key(id).unwrap().clone())) };
+        // The forged requests do not verify and one real request is short of f+1, so no
+        // honest replica leaves view 0 for the forger's view.
+        let honest = assert_honest_commit(4, 1, vec![("node3", forger("node3"))]);
+        assert!(honest.iter().all(|replica| replica.view() == 0));
+        let honest = assert_honest_commit(7, 2, vec![("node2", forger("node2")), ("node6", forger("node6"))]);
+        assert!(honest.iter().all(|replica| replica.view() == 0));
+    }
+
+    #[test]
+    fn test_invalid_pof_submission() {
+        // Honest replicas keep the leader's signed header as evidence of the invalid PoF.
+        let honest = assert_honest_commit(4, 1, vec![("node1", Box::new(InvalidPofProposer::new(keys().key("node1").unwrap().clone())))]);
+        for replica in &honest {
+            assert!(matches!(replica.evidence(), [evidence @ Evidence::InvalidPof { .. }] if evidence.offender() == "node1"));
+            assert_eq!(replica.evidence()[0].validate(&keys().keyring()), Ok(()));
+        }
+        assert_honest_commit(4, 1, vec![("node1", Box::new(InvalidPofProposer::zero_difficulty(keys().key("node1").unwrap().clone())))]);
+    }
+
+    #[test]
+    fn test_replayed_messages() {
+        assert_honest_commit(4, 1, vec![("node1", Box::new(Replayer::default()))]);
+        assert_honest_commit(7, 2, vec![("node2", Box::new(Replayer::default())), ("node3", equivocator("node3"))]);
+    }
+
+    #[test]
+    fn test_check_safety_detects_conflicting_commits() {
+        let nodes = node_ids(4);
+        // Three colluding signers, more than f = 1, can vote for both sides; two replicas
+        // each shown one side commit different proposals under sequence 1.
+        let keys = keys();
+        let forge = |replica: &mut PbftReplica, record: TriadRecord| {
+            let digest = record.hash();
+            replica.handle(ConsensusMessage::pre_prepare([0u8; 32], 0, 1, record, "node1", keys.key("node1").unwrap()));
+            for sender in ["node1", "node2", "node3"] {
+                let key = keys.key(sender).unwrap();
+                replica.handle(ConsensusMessage::Prepare(Vote::sign(VoteKind::Prepare, [0u8; 32], 0, 1, digest, sender, key)));
+                replica.handle(ConsensusMessage::Commit(Vote::sign(VoteKind::Commit, [0u8; 32], 0, 1, digest, sender, key)));
+            }
+        };
+        let mut a = replica("node3", &nodes, 1);
+        let mut b = replica("node4", &nodes, 1);
+        forge(&mut a, solved_record(1));
+        forge(&mut b, solved_record(2));
+
+        assert!(check_safety([&a]).is_ok());
+        let err = check_safety([&a, &b]).unwrap_err();
+        assert!(err.contains("sequence 1"), "{}", err);
+    }
+}
@@ -1,2 +1,5 @@
+pub mod adversary;
 pub mod p2p;
+pub mod partition;
 pub mod routing;
+pub mod simulator;
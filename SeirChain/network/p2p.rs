@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use tokio::net::{TcpListener, TcpStream};
@@ -7,6 +7,11 @@ use futures::{SinkExt, StreamExt};
 use tokio_serde::formats::Json;
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 use serde::{Deserialize, Serialize};
+use crate::core::consensus::fork_choice::triad_work;
+use crate::core::consensus::pbft::{ConsensusMessage, ConsensusTransport};
+use crate::core::consensus::puzzle::FractalPuzzle;
+use crate::core::security::keys::Keyring;
+use crate::core::triad_matrix::matrix::TriadMatrix;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum P2PMessage {
@@ -15,6 +20,7 @@ pub enum P2PMessage {
     Status(NodeStatus),
     GetPeers,
     Peers(Vec<SocketAddr>),
+    Consensus(ConsensusMessage),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -24,10 +30,28 @@ pub struct NodeStatus {
     pub total_difficulty: u64,
 }
 
+impl NodeStatus {
+    /// Builds the status advertised for a local matrix whose Triads solve `puzzle`.
+    /// `total_difficulty` is the matrix's total expected PoF work, saturating at `u64::MAX`.
+    pub fn from_matrix<P: FractalPuzzle>(node_id: String, matrix: &TriadMatrix, puzzle: &P) -> Self {
+        let work = matrix.total_work(|triad| triad_work(puzzle, triad));
+        NodeStatus {
+            node_id,
+            block_height: matrix.count() - 1,
+            total_difficulty: work.min(u64::MAX as u128) as u64,
+        }
+    }
+}
+
 pub struct P2PNode {
     pub node_id: String,
     pub listener: Arc<TcpListener>,
     pub peers: Arc<Mutex<HashMap<SocketAddr, mpsc::Sender<P2PMessage>>>>,
+    /// Consensus messages received from peers, oldest first.
+    pub consensus_inbox: Arc<Mutex<VecDeque<ConsensusMessage>>>,
+    /// Keys consensus messages from peers are checked against before they are queued; with
+    /// none, everything is queued and left to the replica, which checks them too.
+    pub keyring: Option<Arc<Keyring>>,
 }
 
 impl P2PNode {
@@ -37,9 +61,18 @@ impl P2PNode {
             node_id,
             listener: Arc::new(listener),
             peers: Arc::new(Mutex::new(HashMap::new())),
+            consensus_inbox: Arc::new(Mutex::new(VecDeque::new())),
+            keyring: None,
         })
     }
 
+    /// Drops consensus messages from peers that are not signed by their sender's key in
+    /// `keyring`, instead of queueing them.
+    pub fn with_keyring(mut self, keyring: Keyring) -> Self {
+        self.keyring = Some(Arc::new(keyring));
+        self
+    }
+
     pub async fn run(&self) {
         loop {
             let (socket, addr) = self.listener.accept().await.unwrap();
@@ -49,6 +82,8 @@ impl P2PNode {
             peers.lock().unwrap().insert(addr, tx);
 
             let peers_clone = self.peers.clone();
+            let inbox = self.consensus_inbox.clone();
+            let keyring = self.keyring.clone();
             tokio::spawn(async move {
                 let framed = Framed::new(socket, LengthDelimitedCodec::new());
                 let mut transport: tokio_serde::SymmetricallyFramed<_, P2PMessage, Json<P2PMessage, P2PMessage>> = tokio_serde::SymmetricallyFramed::new(
@@ -58,7 +93,7 @@ impl P2PNode {
 
                 while let Some(Ok(msg)) = transport.next().await {
                     let peers_for_handler = peers_clone.clone();
-                    handle_message(msg, addr, peers_for_handler).await;
+                    handle_message(msg, addr, peers_for_handler, inbox.clone(), keyring.as_deref()).await;
                 }
             });
         }
@@ -94,7 +129,47 @@ impl P2PNode {
     }
 }
 
-async fn handle_message(msg: P2PMessage, from: SocketAddr, peers: Arc<Mutex<HashMap<SocketAddr, mpsc::Sender<P2PMessage>>>>) {
+/// Drives a local consensus replica over the network: broadcasts go to every peer and
+/// back into this node's own inbox, and only this node's replica can receive.
+/// Must be used inside a tokio runtime, since `P2PNode::broadcast` spawns the sends.
+pub struct P2PConsensusTransport<'a> {
+    node: &'a P2PNode,
+}
+
+impl<'a> P2PConsensusTransport<'a> {
+    pub fn new(node: &'a P2PNode) -> Self {
+        P2PConsensusTransport { node }
+    }
+}
+
+impl ConsensusTransport for P2PConsensusTransport<'_> {
+    fn broadcast(&mut self, _from: &str, message: ConsensusMessage) {
+        self.node.consensus_inbox.lock().unwrap().push_back(message.clone());
+        self.node.broadcast(P2PMessage::Consensus(message));
+    }
+
+    fn receive(&mut self, node: &str) -> Option<ConsensusMessage> {
+        if node != self.node.node_id {
+            return None;
+        }
+        self.node.consensus_inbox.lock().unwrap().pop_front()
+    }
+}
+
+async fn handle_message(
+    msg: P2PMessage,
+    from: SocketAddr,
+    peers: Arc<Mutex<HashMap<SocketAddr, mpsc::Sender<P2PMessage>>>>,
+    consensus_inbox: Arc<Mutex<VecDeque<ConsensusMessage>>>,
+    keyring: Option<&Keyring>,
+) {
+    if let P2PMessage::Consensus(message) = msg {
+        if keyring.is_none_or(|keyring| message.verify(keyring)) {
+            consensus_inbox.lock().unwrap().push_back(message);
+        }
+        return;
+    }
+
     let action = {
         let peers_lock = peers.lock().unwrap();
         match msg {
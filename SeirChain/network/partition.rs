@@ -0,0 +1,301 @@
+// partition.rs
+// Partition detection over redundant paths, and fork-choice reconciliation on heal
+
+use std::collections::BTreeMap;
+use crate::core::consensus::fork_choice::{compare_branches, ChainWeight};
+use crate::core::consensus::hierarchical_recursive::HierarchicalRecursiveConsensus;
+use crate::core::consensus::puzzle::{FractalPuzzle, SelfSimilarPuzzle};
+use crate::core::triad_matrix::triad_structure::Triad;
+use super::routing::multi_path_fractal::MultiPathFractalRouting;
+
+/// A change in whether a watched sub-fractal can still reach quorum from the observer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PartitionEvent {
+    /// Fewer than `quorum` members of the sub-fractal are reachable over any path.
+    Split { subfractal: usize, at: u64, reachable: usize, quorum: usize },
+    /// Quorum is reachable again after a split.
+    Healed { subfractal: usize, at: u64 },
+}
+
+/// A sub-fractal watched by a `PartitionMonitor`, with the paths that reached each member
+/// at the last observation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubFractalReach {
+    pub nodes: Vec<String>,
+    /// 2f + 1 for the sub-fractal's fault tolerance f.
+    pub quorum: usize,
+    /// Node-disjoint paths from the observer to each reachable member. The observer
+    /// reaches itself over the single path holding only itself.
+    pub paths: BTreeMap<String, Vec<Vec<String>>>,
+    pub partitioned: bool,
+}
+
+impl SubFractalReach {
+    /// Members reachable over at least one path.
+    pub fn reachable(&self) -> usize {
+        self.paths.len()
+    }
+}
+
+/// Tracks which sub-fractals one node can reach, and over which paths, and flags a
+/// partition when a sub-fractal's reachable members drop below its quorum.
+/// A member counts as reachable over any path through the link graph, not only a direct
+/// link, so a split the redundant paths route around is not reported.
+pub struct PartitionMonitor {
+    pub observer: String,
+    pub subfractals: Vec<SubFractalReach>,
+    pub events: Vec<PartitionEvent>,
+}
+
+impl PartitionMonitor {
+    /// Creates a monitor for `observer` that watches no sub-fractals yet.
+    pub fn new(observer: &str) -> Self {
+        PartitionMonitor {
+            observer: observer.to_string(),
+            subfractals: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Creates a monitor for `observer` that watches every leaf sub-fractal of `hrc`.
+    pub fn from_consensus<P: FractalPuzzle>(observer: &str, hrc: &HierarchicalRecursiveConsensus<P>) -> Self {
+        fn watch_leaves<P: FractalPuzzle>(monitor: &mut PartitionMonitor, hrc: &HierarchicalRecursiveConsensus<P>) {
+            if hrc.children.is_empty() {
+                monitor.watch(hrc.nodes.clone(), hrc.fault_tolerance);
+            }
+            for child in &hrc.children {
+                watch_leaves(monitor, child);
+            }
+        }
+        let mut monitor = PartitionMonitor::new(observer);
+        watch_leaves(&mut monitor, hrc);
+        monitor
+    }
+
+    /// Watches a sub-fractal of `nodes` tolerating `fault_tolerance` faults.
+    /// Returns its index, as used in `PartitionEvent`.
+    pub fn watch(&mut self, nodes: Vec<String>, fault_tolerance: usize) -> usize {
+        self.subfractals.push(SubFractalReach {
+            nodes,
+            quorum: 2 * fault_tolerance + 1,
+            paths: BTreeMap::new(),
+            partitioned: false,
+        });
+        self.subfractals.len() - 1
+    }
+
+    /// Recomputes the paths to every watched node over the links of `routing` and returns
+    /// the splits and heals this observation caused. They are also appended to `events`.
+    pub fn observe(&mut self, routing: &MultiPathFractalRouting, now: u64) -> Vec<PartitionEvent> {
+        let mut changes = Vec::new();
+        for (index, subfractal) in self.subfractals.iter_mut().enumerate() {
+            subfractal.paths = subfractal
+                .nodes
+                .iter()
+                .map(|node| {
+                    let paths = if *node == self.observer {
+                        vec![vec![node.clone()]]
+                    } else {
+                        routing.disjoint_paths(&self.observer, node)
+                    };
+                    (node.clone(), paths)
+                })
+                .filter(|(_, paths)| !paths.is_empty())
+                .collect();
+
+            let partitioned = subfractal.reachable() < subfractal.quorum;
+            if partitioned && !subfractal.partitioned {
+                changes.push(PartitionEvent::Split {
+                    subfractal: index,
+                    at: now,
+                    reachable: subfractal.reachable(),
+                    quorum: subfractal.quorum,
+                });
+            } else if !partitioned && subfractal.partitioned {
+                changes.push(PartitionEvent::Healed { subfractal: index, at: now });
+            }
+            subfractal.partitioned = partitioned;
+        }
+        self.events.extend(changes.iter().cloned());
+        changes
+    }
+
+    /// Returns true if any watched sub-fractal has lost quorum.
+    pub fn is_partitioned(&self) -> bool {
+        self.subfractals.iter().any(|s| s.partitioned)
+    }
+
+    /// Indices of the sub-fractals that have lost quorum.
+    pub fn partitioned_subfractals(&self) -> Vec<usize> {
+        (0..self.subfractals.len()).filter(|&i| self.subfractals[i].partitioned).collect()
+    }
+
+    /// Paths that reached `node` at the last observation; empty if it was unreachable.
+    pub fn paths_to(&self, node: &str) -> Vec<Vec<String>> {
+        self.subfractals
+            .iter()
+            .find_map(|s| s.paths.get(node))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Outcome of reconciling two branches that diverged during a partition.
+#[derive(Clone)]
+pub struct Reconciliation {
+    /// Number of leading Triads both branches share.
+    pub fork_point: usize,
+    /// True if the remote branch won and replaces the local one.
+    pub adopted: bool,
+    /// The winning branch, from its first Triad to its tip.
+    pub branch: Vec<Triad>,
+    /// Triads of the losing branch after the fork point; their transactions need re-submitting.
+    pub orphaned: Vec<Triad>,
+}
+
+fn check_links(branch: &[Triad], name: &str) -> Result<(), String> {
+    for (i, pair) in branch.windows(2).enumerate() {
+        if pair[1].parent_hash != pair[0].header_hash() {
+            return Err(format!("{} branch breaks at Triad {}: parent_hash does not match", name, i + 1));
+        }
+    }
+    Ok(())
+}
+
+/// Reconciles the `local` branch with a `remote` branch once a partition heals, weighing
+/// Triads with the self-similar puzzle. See `reconcile_with` for other variants.
+pub fn reconcile(local: &[Triad], remote: &[Triad]) -> Result<Reconciliation, String> {
+    reconcile_with(&SelfSimilarPuzzle, local, remote)
+}
+
+/// Reconciles the `local` branch with a `remote` branch once a partition heals. Both are
+/// given from their first Triad to their tip, each Triad's `parent_hash` must be the header
+/// hash of the one before, and they must start from the same Triad.
+/// The heavier branch by `compare_branches`, with work judged by `puzzle`, wins; the shared
+/// prefix weighs the same on both sides, so only the Triads after the fork point decide.
+pub fn reconcile_with<P: FractalPuzzle>(puzzle: &P, local: &[Triad], remote: &[Triad]) -> Result<Reconciliation, String> {
+    check_links(local, "Local")?;
+    check_links(remote, "Remote")?;
+    let fork_point = local
+        .iter()
+        .zip(remote)
+        .take_while(|(a, b)| a.hash() == b.hash())
+        .count();
+    if fork_point == 0 {
+        return Err("Branches share no common ancestor".to_string());
+    }
+
+    let adopted = match (ChainWeight::of_branch(puzzle, local), ChainWeight::of_branch(puzzle, remote)) {
+        (Some(l), Some(r)) => compare_branches(&r, &l).is_gt(),
+        _ => false,
+    };
+    let (winner, loser) = if adopted { (remote, local) } else { (local, remote) };
+    Ok(Reconciliation {
+        fork_point,
+        adopted,
+        branch: winner.to_vec(),
+        orphaned: loser[fork_point..].to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::proof_of_fractal::solved_triad;
+    use crate::core::security::keys::node_ids;
+
+    fn mesh(nodes: &[String]) -> MultiPathFractalRouting {
+        let mut routing = MultiPathFractalRouting::new();
+        for (i, a) in nodes.iter().enumerate() {
+            for b in &nodes[i + 1..] {
+                routing.add_link(a, b);
+            }
+        }
+        routing
+    }
+
+    fn hashes(branch: &[Triad]) -> Vec<[u8; 32]> {
+        branch.iter().map(Triad::hash).collect()
+    }
+
+    fn extend(branch: &mut Vec<Triad>, difficulty: u32) {
+        let triad = Triad { parent_hash: branch.last().unwrap().header_hash(), timestamp: branch.len() as u64, ..Triad::new() };
+        branch.push(solved_triad(triad, difficulty));
+    }
+
+    #[test]
+    fn test_split_and_heal_are_flagged() {
+        let nodes = node_ids(4);
+        let mut monitor = PartitionMonitor::new("node1");
+        assert_eq!(monitor.watch(nodes.clone(), 1), 0);
+        let mut routing = mesh(&nodes);
+        assert!(monitor.observe(&routing, 1).is_empty());
+        assert_eq!(monitor.subfractals[0].reachable(), 4);
+        assert_eq!(monitor.paths_to("node1"), vec![vec!["node1".to_string()]]);
+
+        // node1 still reaches node4 through node2 and node3.
+        routing.remove_link("node1", "node4");
+        assert!(monitor.observe(&routing, 2).is_empty());
+        assert_eq!(monitor.paths_to("node4").len(), 2);
+
+        // Cut node3 and node4 off entirely.
+        for (a, b) in [("node1", "node3"), ("node2", "node3"), ("node3", "node4"), ("node2", "node4")] {
+            routing.remove_link(a, b);
+        }
+        assert_eq!(
+            monitor.observe(&routing, 3),
+            vec![PartitionEvent::Split { subfractal: 0, at: 3, reachable: 2, quorum: 3 }]
+        );
+        assert!(monitor.is_partitioned());
+        assert!(monitor.paths_to("node3").is_empty());
+        // A split is reported once, not on every observation.
+        assert!(monitor.observe(&routing, 4).is_empty());
+
+        routing.add_link("node2", "node3");
+        assert_eq!(monitor.observe(&routing, 5), vec![PartitionEvent::Healed { subfractal: 0, at: 5 }]);
+        assert_eq!(monitor.partitioned_subfractals(), Vec::<usize>::new());
+        assert_eq!(monitor.events.len(), 2);
+    }
+
+    #[test]
+    fn test_reconcile_picks_heavier_branch() {
+        let genesis = Triad::genesis(None);
+        let mut light = vec![genesis.clone()];
+        let mut heavy = vec![genesis];
+        for _ in 0..3 {
+            extend(&mut light, 1);
+        }
+        extend(&mut heavy, 2);
+
+        let outcome = reconcile(&light, &heavy).unwrap();
+        assert!(outcome.adopted);
+        assert_eq!(outcome.fork_point, 1);
+        assert_eq!(hashes(&outcome.branch), hashes(&heavy));
+        assert_eq!(hashes(&outcome.orphaned), hashes(&light[1..]));
+
+        let outcome = reconcile(&heavy, &light).unwrap();
+        assert!(!outcome.adopted);
+        assert_eq!(hashes(&outcome.branch), hashes(&heavy));
+
+        // A branch that only extends the other wins without orphaning anything.
+        let prefix = &light[..2];
+        let outcome = reconcile(prefix, &light).unwrap();
+        assert!(outcome.adopted);
+        assert!(outcome.orphaned.is_empty());
+    }
+
+    #[test]
+    fn test_reconcile_rejects_unrelated_or_broken_branches() {
+        let mut ours = vec![Triad::genesis(None)];
+        extend(&mut ours, 1);
+        let mut other_genesis = Triad::genesis(None);
+        other_genesis.timestamp = 7;
+        let mut theirs = vec![other_genesis];
+        extend(&mut theirs, 1);
+        assert!(reconcile(&ours, &theirs).is_err_and(|e| e.contains("no common ancestor")));
+
+        let mut broken = ours.clone();
+        broken[1].parent_hash = [9u8; 32];
+        assert!(reconcile(&broken, &ours).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 
 /// MultiPathFractalRouting manages routing and load balancing in the fractal network.
 pub struct MultiPathFractalRouting {
@@ -6,6 +6,8 @@ pub struct MultiPathFractalRouting {
     pub routing_table: HashMap<String, Vec<String>>,
     /// Load metrics per node for load balancing.
     pub load_metrics: HashMap<String, u32>,
+    /// Links between nodes, recorded in both directions.
+    pub links: HashMap<String, BTreeSet<String>>,
 }
 
 impl MultiPathFractalRouting {
@@ -14,6 +16,7 @@ impl MultiPathFractalRouting {
         MultiPathFractalRouting {
             routing_table: HashMap::new(),
             load_metrics: HashMap::new(),
+            links: HashMap::new(),
         }
     }
 
@@ -65,10 +68,131 @@ impl MultiPathFractalRouting {
         self.routing_table.remove(coordinate).is_some()
     }
 
-    /// Clears the routing table and load metrics.
+    /// Clears the routing table, load metrics and links.
     pub fn clear_routing_table(&mut self) {
         self.routing_table.clear();
         self.load_metrics.clear();
+        self.links.clear();
+    }
+
+    /// Links two nodes in both directions.
+    /// Returns true if the link is new.
+    pub fn add_link(&mut self, a: &str, b: &str) -> bool {
+        if a == b {
+            return false;
+        }
+        self.links.entry(b.to_string()).or_default().insert(a.to_string());
+        self.links.entry(a.to_string()).or_default().insert(b.to_string())
+    }
+
+    /// Removes the link between two nodes.
+    /// Returns true if they were linked.
+    pub fn remove_link(&mut self, a: &str, b: &str) -> bool {
+        if let Some(neighbours) = self.links.get_mut(b) {
+            neighbours.remove(a);
+        }
+        self.links.get_mut(a).is_some_and(|neighbours| neighbours.remove(b))
+    }
+
+    /// Returns true if `path` visits at least two nodes, none twice, and each hop follows a link.
+    pub fn is_route(&self, path: &[String]) -> bool {
+        let distinct: HashSet<&String> = path.iter().collect();
+        path.len() >= 2
+            && distinct.len() == path.len()
+            && path.windows(2).all(|hop| self.links.get(&hop[0]).is_some_and(|n| n.contains(&hop[1])))
+    }
+
+    /// Finds as many paths from `from` to `to` as the links allow such that no two share a node
+    /// other than the endpoints. Each path lists its nodes from `from` to `to`; shorter paths come first.
+    /// Returns no paths if either node has no links or both are the same node.
+    pub fn disjoint_paths(&self, from: &str, to: &str) -> Vec<Vec<String>> {
+        if from == to || !self.links.contains_key(from) || !self.links.contains_key(to) {
+            return Vec::new();
+        }
+        let mut names: Vec<&String> = self.links.keys().collect();
+        names.sort();
+        let index: HashMap<&str, usize> = names.iter().enumerate().map(|(i, name)| (name.as_str(), i)).collect();
+        let (source, sink) = (index[from], index[to]);
+
+        // Each node is split into an entry (2i) and an exit (2i + 1) joined by an edge of capacity one,
+        // so a maximum flow from the source's exit to the sink's entry passes through each node at most once.
+        let mut residual: HashMap<(usize, usize), u32> = HashMap::new();
+        let mut adjacency = vec![Vec::new(); 2 * names.len()];
+        let mut add_edge = |a: usize, b: usize| {
+            residual.insert((a, b), 1);
+            residual.entry((b, a)).or_insert(0);
+            adjacency[a].push(b);
+            adjacency[b].push(a);
+        };
+        for (i, name) in names.iter().enumerate() {
+            if i != source && i != sink {
+                add_edge(2 * i, 2 * i + 1);
+            }
+            for neighbour in &self.links[*name] {
+                if let Some(&j) = index.get(neighbour.as_str()) {
+                    add_edge(2 * i + 1, 2 * j);
+                }
+            }
+        }
+        let forward: HashSet<(usize, usize)> = residual.iter().filter(|(_, &c)| c == 1).map(|(&e, _)| e).collect();
+
+        let (start, end) = (2 * source + 1, 2 * sink);
+        loop {
+            let mut previous = vec![None; adjacency.len()];
+            let mut queue = VecDeque::from([start]);
+            while let Some(a) = queue.pop_front() {
+                if a == end {
+                    break;
+                }
+                for &b in &adjacency[a] {
+                    if b != start && previous[b].is_none() && residual[&(a, b)] > 0 {
+                        previous[b] = Some(a);
+                        queue.push_back(b);
+                    }
+                }
+            }
+            if previous[end].is_none() {
+                break;
+            }
+            let mut b = end;
+            while let Some(a) = previous[b] {
+                *residual.get_mut(&(a, b)).unwrap() -= 1;
+                *residual.get_mut(&(b, a)).unwrap() += 1;
+                b = a;
+            }
+        }
+
+        // Each saturated edge out of the source starts one path; follow saturated edges to the sink.
+        let mut used = HashSet::new();
+        let mut paths = Vec::new();
+        for &first in &adjacency[start] {
+            if !forward.contains(&(start, first)) || residual[&(start, first)] > 0 {
+                continue;
+            }
+            let mut path = vec![names[source].clone()];
+            let mut entry = first;
+            while entry != end {
+                path.push(names[entry / 2].clone());
+                let exit = entry + 1;
+                let next = adjacency[exit]
+                    .iter()
+                    .copied()
+                    .find(|&b| forward.contains(&(exit, b)) && residual[&(exit, b)] == 0 && !used.contains(&(exit, b)));
+                match next {
+                    Some(b) => {
+                        used.insert((exit, b));
+                        entry = b;
+                    }
+                    None => break,
+                }
+            }
+            if entry == end {
+                path.push(names[sink].clone());
+                paths.push(path);
+            }
+        }
+        paths.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+        paths
     }
 
     /// Gets the load metric for a specific node ID.
@@ -149,6 +273,51 @@ mod tests {
         assert_eq!(mpfr.get_load(&node_id), Some(&10));
     }
 
+    fn mesh() -> MultiPathFractalRouting {
+        // a reaches d through b, through c, and through e then f; b also links to c.
+        let mut mpfr = MultiPathFractalRouting::new();
+        for (x, y) in [("a", "b"), ("b", "d"), ("a", "c"), ("c", "d"), ("a", "e"), ("e", "f"), ("f", "d"), ("b", "c")] {
+            mpfr.add_link(x, y);
+        }
+        mpfr
+    }
+
+    fn path(nodes: &[&str]) -> Vec<String> {
+        nodes.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_links_and_routes() {
+        let mut mpfr = mesh();
+        assert!(!mpfr.add_link("b", "a"));
+        assert!(mpfr.is_route(&path(&["a", "b", "c", "d"])));
+        assert!(!mpfr.is_route(&path(&["a", "d"])));
+        assert!(!mpfr.is_route(&path(&["a", "b", "a", "c"])));
+        assert!(!mpfr.is_route(&path(&["a"])));
+
+        assert!(mpfr.remove_link("c", "b"));
+        assert!(!mpfr.remove_link("b", "c"));
+        assert!(!mpfr.is_route(&path(&["a", "b", "c", "d"])));
+    }
+
+    #[test]
+    fn test_disjoint_paths() {
+        let mut mpfr = mesh();
+        let paths = mpfr.disjoint_paths("a", "d");
+        assert_eq!(paths, vec![path(&["a", "b", "d"]), path(&["a", "c", "d"]), path(&["a", "e", "f", "d"])]);
+        assert!(paths.iter().all(|p| mpfr.is_route(p)));
+
+        // Cutting b from d leaves a way through b only via c, which the path through c already uses.
+        mpfr.remove_link("b", "d");
+        assert_eq!(mpfr.disjoint_paths("a", "d").len(), 2);
+
+        // A single node every route passes through leaves one path.
+        mpfr.add_link("d", "g");
+        assert_eq!(mpfr.disjoint_paths("a", "g"), vec![path(&["a", "c", "d", "g"])]);
+        assert!(mpfr.disjoint_paths("a", "a").is_empty());
+        assert!(mpfr.disjoint_paths("a", "z").is_empty());
+    }
+
     #[test]
     fn test_list_nodes() {
         let mut mpfr = MultiPathFractalRouting::new();
@@ -0,0 +1,591 @@
+// simulator.rs
+// Deterministic discrete-event network simulator for consensus testing
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use crate::core::consensus::hierarchical_recursive::HierarchicalRecursiveConsensus;
+use crate::core::consensus::pbft::{ConsensusMessage, ConsensusTransport};
+use crate::core::consensus::puzzle::FractalPuzzle;
+use super::adversary::ByzantineBehaviour;
+use super::routing::multi_path_fractal::MultiPathFractalRouting;
+
+/// A split of the network during ticks `start..end`: nodes in different groups cannot
+/// reach each other. Nodes not listed in any group reach everyone.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Partition {
+    pub start: u64,
+    pub end: u64,
+    pub groups: Vec<Vec<String>>,
+}
+
+impl Partition {
+    fn group_of(&self, node: &str) -> Option<usize> {
+        self.groups.iter().position(|group| group.iter().any(|n| n == node))
+    }
+
+    /// Returns true if the partition separates `a` from `b` at `time`.
+    pub fn separates(&self, a: &str, b: &str, time: u64) -> bool {
+        if time < self.start || time >= self.end {
+            return false;
+        }
+        match (self.group_of(a), self.group_of(b)) {
+            (Some(x), Some(y)) => x != y,
+            _ => false,
+        }
+    }
+}
+
+/// Parameters of a simulated network. Latencies are in ticks, the unit replicas time
+/// out in, and every random choice is drawn from `seed`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SimConfig {
+    pub seed: u64,
+    pub min_latency: u64,
+    pub max_latency: u64,
+    /// Probability that a message between two different nodes is lost.
+    pub drop_rate: f64,
+    pub partitions: Vec<Partition>,
+    /// Timestamp given to every Triad, in place of the wall clock.
+    pub start_time: u64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            seed: 0,
+            min_latency: 1,
+            max_latency: 3,
+            drop_rate: 0.0,
+            partitions: Vec::new(),
+            start_time: 1_700_000_000,
+        }
+    }
+}
+
+/// Why the simulator did not deliver a message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DropReason {
+    Loss,
+    Partition,
+    Byzantine,
+}
+
+/// What happened to one message on one link.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TraceKind {
+    Scheduled { deliver_at: u64 },
+    Delivered,
+    Dropped(DropReason),
+}
+
+/// One entry of the simulation trace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEvent {
+    pub time: u64,
+    pub from: String,
+    pub to: String,
+    pub kind: TraceKind,
+    pub message: ConsensusMessage,
+}
+
+struct InFlight {
+    from: String,
+    message: ConsensusMessage,
+}
+
+struct SimNetwork {
+    config: SimConfig,
+    rng: ChaCha8Rng,
+    now: u64,
+    next_id: u64,
+    // Per recipient, keyed by (delivery time, send order) so delivery is deterministic.
+    queues: BTreeMap<String, BTreeMap<(u64, u64), InFlight>>,
+    behaviours: BTreeMap<String, Box<dyn ByzantineBehaviour>>,
+    trace: Vec<TraceEvent>,
+}
+
+impl SimNetwork {
+    /// Returns true if no active partition separates `a` from `b`.
+    fn connected(&self, a: &str, b: &str) -> bool {
+        !self.config.partitions.iter().any(|p| p.separates(a, b, self.now))
+    }
+
+    fn record(&mut self, from: &str, to: &str, kind: TraceKind, message: &ConsensusMessage) {
+        self.trace.push(TraceEvent {
+            time: self.now,
+            from: from.to_string(),
+            to: to.to_string(),
+            kind,
+            message: message.clone(),
+        });
+    }
+
+    fn send(&mut self, from: &str, to: &str, message: ConsensusMessage) {
+        for behaviour in self.behaviours.values_mut() {
+            behaviour.observe(from, to, &message);
+        }
+        let outgoing = match self.behaviours.get_mut(from) {
+            Some(behaviour) => behaviour.outgoing(from, to, message.clone(), &mut self.rng),
+            None => vec![message.clone()],
+        };
+        if outgoing.is_empty() {
+            self.record(from, to, TraceKind::Dropped(DropReason::Byzantine), &message);
+            return;
+        }
+
+        for message in outgoing {
+            // A node always hears itself, instantly.
+            let deliver_at = if from == to {
+                self.now
+            } else if !self.connected(from, to) {
+                self.record(from, to, TraceKind::Dropped(DropReason::Partition), &message);
+                continue;
+            } else if self.rng.gen::<f64>() < self.config.drop_rate {
+                self.record(from, to, TraceKind::Dropped(DropReason::Loss), &message);
+                continue;
+            } else {
+                let max = self.config.max_latency.max(self.config.min_latency);
+                self.now + self.rng.gen_range(self.config.min_latency..=max)
+            };
+            self.record(from, to, TraceKind::Scheduled { deliver_at }, &message);
+            let id = self.next_id;
+            self.next_id += 1;
+            self.queues
+                .entry(to.to_string())
+                .or_default()
+                .insert((deliver_at, id), InFlight { from: from.to_string(), message });
+        }
+    }
+
+    fn receive(&mut self, node: &str) -> Option<ConsensusMessage> {
+        let queue = self.queues.get_mut(node)?;
+        let key = *queue.keys().next()?;
+        if key.0 > self.now {
+            return None;
+        }
+        let in_flight = queue.remove(&key).expect("key was just read");
+        self.record(&in_flight.from, node, TraceKind::Delivered, &in_flight.message);
+        Some(in_flight.message)
+    }
+}
+
+/// The view of the simulated network given to one sub-fractal's replicas.
+pub struct SimTransport {
+    network: Rc<RefCell<SimNetwork>>,
+    nodes: Vec<String>,
+}
+
+impl ConsensusTransport for SimTransport {
+    fn broadcast(&mut self, from: &str, message: ConsensusMessage) {
+        let mut network = self.network.borrow_mut();
+        for to in &self.nodes {
+            network.send(from, to, message.clone());
+        }
+    }
+
+    fn receive(&mut self, node: &str) -> Option<ConsensusMessage> {
+        self.network.borrow_mut().receive(node)
+    }
+
+    fn tick(&mut self) {
+        self.network.borrow_mut().now += 1;
+    }
+
+    fn connected(&self, a: &str, b: &str) -> bool {
+        self.network.borrow().connected(a, b)
+    }
+}
+
+/// Runs `HierarchicalRecursiveConsensus` over a simulated network on a single thread.
+/// Latency, loss, partitions and the PoF nonce search all draw from the seed and the clock
+/// is virtual, so two simulators built from the same configuration and behaviours produce
+/// identical traces.
+pub struct Simulator {
+    network: Rc<RefCell<SimNetwork>>,
+    consensus_rng: ChaCha8Rng,
+}
+
+impl Simulator {
+    pub fn new(config: SimConfig) -> Self {
+        // Separate streams, so adding a network event does not shift the PoF nonces.
+        let rng = ChaCha8Rng::seed_from_u64(config.seed);
+        let consensus_rng = ChaCha8Rng::seed_from_u64(config.seed ^ 0x5eed_c0de);
+        Simulator {
+            network: Rc::new(RefCell::new(SimNetwork {
+                config,
+                rng,
+                now: 0,
+                next_id: 0,
+                queues: BTreeMap::new(),
+                behaviours: BTreeMap::new(),
+                trace: Vec::new(),
+            })),
+            consensus_rng,
+        }
+    }
+
+    /// Makes `node` misbehave as `behaviour` for the rest of the simulation.
+    pub fn set_behaviour(&mut self, node: &str, behaviour: Box<dyn ByzantineBehaviour>) {
+        self.network.borrow_mut().behaviours.insert(node.to_string(), behaviour);
+    }
+
+    /// Current virtual time in ticks.
+    pub fn now(&self) -> u64 {
+        self.network.borrow().now
+    }
+
+    /// Moves the clock forward to `time` with the network idle. Does nothing if `time`
+    /// has already passed.
+    pub fn advance_to(&mut self, time: u64) {
+        let mut network = self.network.borrow_mut();
+        network.now = network.now.max(time);
+    }
+
+    /// The links between `nodes` as they stand now: every pair that no active partition
+    /// separates. Loss is not a partition, so lossy links are kept.
+    pub fn links(&self, nodes: &[String]) -> MultiPathFractalRouting {
+        let network = self.network.borrow();
+        let mut routing = MultiPathFractalRouting::new();
+        for (i, a) in nodes.iter().enumerate() {
+            for b in &nodes[i + 1..] {
+                if network.connected(a, b) {
+                    routing.add_link(a, b);
+                }
+            }
+        }
+        routing
+    }
+
+    /// A transport connecting `nodes` through the simulated network.
+    pub fn transport(&self, nodes: &[String]) -> SimTransport {
+        SimTransport {
+            network: self.network.clone(),
+            nodes: nodes.to_vec(),
+        }
+    }
+
+    /// Runs one round of consensus over the simulated network. Mining is forced onto a
+    /// single thread and Triad timestamps onto the configured start time, the two places
+    /// consensus would otherwise depend on the host.
+    pub fn run<P: FractalPuzzle + Clone + 'static>(&mut self, hrc: &mut HierarchicalRecursiveConsensus<P>) -> bool {
+        hrc.set_mining_threads(1);
+        let start_time = self.network.borrow().config.start_time;
+        set_timestamps(hrc, start_time);
+        let network = self.network.clone();
+        hrc.run_consensus_with(&mut self.consensus_rng, &mut |nodes: &[String]| SimTransport {
+            network: network.clone(),
+            nodes: nodes.to_vec(),
+        })
+    }
+
+    /// Everything that happened on the network so far, in order.
+    pub fn trace(&self) -> Vec<TraceEvent> {
+        self.network.borrow().trace.clone()
+    }
+
+    /// The trace as JSON lines, for storing and byte-for-byte comparison.
+    pub fn trace_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for event in &self.network.borrow().trace {
+            out.extend(serde_json::to_vec(event).expect("trace events serialize"));
+            out.push(b'\n');
+        }
+        out
+    }
+
+    /// SHA-256 of `trace_bytes`.
+    pub fn trace_digest(&self) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&Sha256::digest(self.trace_bytes()));
+        hash
+    }
+}
+
+fn set_timestamps<P: FractalPuzzle>(hrc: &mut HierarchicalRecursiveConsensus<P>, timestamp: u64) {
+    hrc.triad.timestamp = timestamp;
+    for child in &mut hrc.children {
+        set_timestamps(child, timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::consensus::aggregation::AggregationPolicy;
+    use crate::core::consensus::commitment::CommitmentProof;
+    use crate::core::consensus::metrics::FailureReason;
+    use crate::core::security::evidence::{Slasher, SlashingConfig};
+    use crate::core::security::keys::node_ids;
+    use crate::core::triad_matrix::evidence::Evidence;
+    use crate::core::triad_matrix::triad_structure::Triad;
+    use crate::interface::economics::waclanium_token::WaclaniumToken;
+    use crate::network::adversary::{DoubleVoter, EquivocatingLeader, InvalidPofProposer, Replayer, Silent};
+    use crate::network::partition::{reconcile, PartitionEvent, PartitionMonitor};
+
+    fn run(config: SimConfig, silent: &[&str], nodes: Vec<String>, depth: u32) -> (bool, Simulator) {
+        let mut sim = Simulator::new(config);
+        for node in silent {
+            sim.set_behaviour(node, Box::new(Silent));
+        }
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(nodes, 1, 1, depth);
+        let committed = sim.run(&mut hrc);
+        (committed, sim)
+    }
+
+    #[test]
+    fn test_same_seed_replays_byte_for_byte() {
+        let config = SimConfig { seed: 7, drop_rate: 0.05, ..SimConfig::default() };
+        let (first, a) = run(config.clone(), &["node5"], node_ids(12), 1);
+        let (second, b) = run(config.clone(), &["node5"], node_ids(12), 1);
+        assert!(first && second);
+        assert!(!a.trace().is_empty());
+        assert_eq!(a.trace_bytes(), b.trace_bytes());
+        assert_eq!(a.now(), b.now());
+
+        let (_, c) = run(SimConfig { seed: 8, ..config }, &["node5"], node_ids(12), 1);
+        assert_ne!(a.trace_digest(), c.trace_digest());
+    }
+
+    #[test]
+    fn test_latency_is_respected() {
+        let config = SimConfig { seed: 1, min_latency: 2, max_latency: 5, ..SimConfig::default() };
+        let (committed, sim) = run(config, &[], node_ids(4), 0);
+        assert!(committed);
+        let trace = sim.trace();
+        for event in &trace {
+            if let TraceKind::Scheduled { deliver_at } = event.kind {
+                let latency = deliver_at - event.time;
+                if event.from == event.to {
+                    assert_eq!(latency, 0);
+                } else {
+                    assert!((2..=5).contains(&latency), "latency {}", latency);
+                }
+            }
+        }
+        assert!(trace.iter().any(|e| e.kind == TraceKind::Delivered));
+    }
+
+    #[test]
+    fn test_silent_leader_is_traced_and_replaced() {
+        let (committed, sim) = run(SimConfig::default(), &["node1"], node_ids(4), 0);
+        assert!(committed);
+        let trace = sim.trace();
+        assert!(trace
+            .iter()
+            .filter(|e| e.from == "node1")
+            .all(|e| e.kind == TraceKind::Dropped(DropReason::Byzantine)));
+        assert!(trace.iter().any(|e| matches!(e.message, ConsensusMessage::NewView { .. })));
+    }
+
+    #[test]
+    fn test_partition_blocks_quorum_until_it_heals() {
+        let split = Partition {
+            start: 0,
+            end: u64::MAX,
+            groups: vec![node_ids(2), vec!["node3".to_string(), "node4".to_string()]],
+        };
+        let config = SimConfig { partitions: vec![split.clone()], ..SimConfig::default() };
+        let (committed, sim) = run(config, &[], node_ids(4), 0);
+        assert!(!committed);
+        assert!(sim.trace().iter().any(|e| e.kind == TraceKind::Dropped(DropReason::Partition)));
+
+        // The same split healing after 30 ticks lets the round finish after a view change.
+        let healing = Partition { end: 30, ..split };
+        let config = SimConfig { partitions: vec![healing], ..SimConfig::default() };
+        let (committed, sim) = run(config, &[], node_ids(4), 0);
+        assert!(committed);
+        assert!(sim.now() >= 30);
+    }
+
+    #[test]
+    fn test_partitioned_link_is_no_path() {
+        // node3 never hears node1, so the proposal cannot reach node2 through it, and the
+        // link between them is not in the topology the round is validated against.
+        let cut = Partition { start: 0, end: u64::MAX, groups: vec![vec!["node1".to_string()], vec!["node3".to_string()]] };
+        let mut sim = Simulator::new(SimConfig { partitions: vec![cut], ..SimConfig::default() });
+        let route = |path: &[&str]| sim.links(&node_ids(4)).is_route(&path.iter().map(|n| n.to_string()).collect::<Vec<_>>());
+        assert!(!route(&["node1", "node3", "node2"]));
+        assert!(route(&["node1", "node4", "node2"]));
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(node_ids(4), 1, 1, 0);
+        hrc.security.min_disjoint_paths = 3;
+        assert!(!sim.run(&mut hrc));
+        assert_eq!(hrc.metrics.failure, Some(FailureReason::TooFewPaths { agreeing: 2, required: 3 }));
+
+        hrc.security.min_disjoint_paths = 2;
+        assert!(sim.run(&mut hrc));
+    }
+
+    /// Runs a round on top of `branch` and appends the committed Triad to it.
+    fn extend(sim: &mut Simulator, hrc: &mut HierarchicalRecursiveConsensus, branch: &mut Vec<Triad>) -> bool {
+        hrc.triad = Triad::new();
+        hrc.triad.parent_hash = branch.last().unwrap().header_hash();
+        let committed = sim.run(hrc);
+        if committed {
+            branch.push(hrc.triad.clone());
+        }
+        committed
+    }
+
+    #[test]
+    fn test_split_network_reconciles_on_heal() {
+        let all = node_ids(8);
+        let (west, east) = (all[..4].to_vec(), all[4..].to_vec());
+        let split = Partition { start: 0, end: 10_000, groups: vec![west.clone(), east.clone()] };
+        let mut sim = Simulator::new(SimConfig { partitions: vec![split], ..SimConfig::default() });
+
+        let whole = HierarchicalRecursiveConsensus::simulated(all.clone(), 2, 1, 0);
+        let mut west_monitor = PartitionMonitor::from_consensus("node1", &whole);
+        let mut east_monitor = PartitionMonitor::from_consensus("node5", &whole);
+        let split_event = PartitionEvent::Split { subfractal: 0, at: 0, reachable: 4, quorum: 5 };
+        assert_eq!(west_monitor.observe(&sim.links(&all), sim.now()), vec![split_event.clone()]);
+        assert_eq!(east_monitor.observe(&sim.links(&all), sim.now()), vec![split_event]);
+        assert!(west_monitor.paths_to("node5").is_empty());
+
+        // Neither half holds the 5 votes the whole network needs.
+        let genesis = Triad::genesis(None);
+        let mut whole = whole;
+        assert!(!extend(&mut sim, &mut whole, &mut vec![genesis.clone()]));
+        assert!(sim.trace().iter().any(|e| e.kind == TraceKind::Dropped(DropReason::Partition)));
+
+        // Each side carries on as its own committee: the west solves harder puzzles,
+        // the east commits more Triads.
+        let mut west_branch = vec![genesis.clone()];
+        let mut east_branch = vec![genesis];
+        let mut west_hrc = HierarchicalRecursiveConsensus::simulated(west, 1, 2, 0);
+        let mut east_hrc = HierarchicalRecursiveConsensus::simulated(east, 1, 1, 0);
+        assert!(extend(&mut sim, &mut west_hrc, &mut west_branch));
+        for _ in 0..2 {
+            assert!(extend(&mut sim, &mut east_hrc, &mut east_branch));
+        }
+        assert!(sim.now() < 10_000);
+
+        sim.advance_to(10_000);
+        let healed = vec![PartitionEvent::Healed { subfractal: 0, at: 10_000 }];
+        assert_eq!(west_monitor.observe(&sim.links(&all), sim.now()), healed);
+        assert_eq!(east_monitor.observe(&sim.links(&all), sim.now()), healed);
+        assert_eq!(west_monitor.paths_to("node5").len(), 7);
+
+        // Both sides pick the heavier west branch, and the east's Triads are orphaned.
+        let west_view = reconcile(&west_branch, &east_branch).unwrap();
+        let east_view = reconcile(&east_branch, &west_branch).unwrap();
+        assert!(!west_view.adopted && east_view.adopted);
+        assert_eq!(west_view.branch.last().map(Triad::hash), east_view.branch.last().map(Triad::hash));
+        assert_eq!(east_view.fork_point, 1);
+        assert_eq!(east_view.orphaned.len(), 2);
+        assert_eq!(east_view.orphaned.last().map(Triad::hash), east_branch.last().map(Triad::hash));
+
+        // The re-merged network commits on top of the chosen branch.
+        let mut branch = east_view.branch;
+        assert!(extend(&mut sim, &mut whole, &mut branch));
+        assert_eq!(whole.final_commitment().map(|c| c.triad_hash), Some(whole.triad.hash()));
+        assert_eq!(reconcile(&branch, &west_branch).map(|r| r.adopted), Ok(false));
+    }
+
+    #[test]
+    fn test_monitor_flags_only_the_split_subfractal() {
+        let split = Partition { start: 0, end: u64::MAX, groups: vec![node_ids(10), node_ids(12)[10..].to_vec()] };
+        let mut sim = Simulator::new(SimConfig { partitions: vec![split], ..SimConfig::default() });
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(node_ids(12), 1, 1, 1);
+        let mut monitor = PartitionMonitor::from_consensus("node1", &hrc);
+        assert_eq!(monitor.subfractals.len(), 3);
+        monitor.observe(&sim.links(&node_ids(12)), sim.now());
+        assert_eq!(monitor.partitioned_subfractals(), vec![2]);
+
+        // The leaf the monitor flags is the one that fails to commit.
+        assert!(sim.run(&mut hrc));
+        assert_eq!(hrc.absent_children, monitor.partitioned_subfractals());
+    }
+
+    #[test]
+    fn test_adversaries_in_every_leaf() {
+        let config = SimConfig { seed: 11, drop_rate: 0.02, ..SimConfig::default() };
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(node_ids(12), 1, 1, 1);
+        hrc.set_aggregation_policy(AggregationPolicy::Unanimous);
+        let mut sim = Simulator::new(config);
+        sim.set_behaviour("node1", Box::new(EquivocatingLeader::new(hrc.keys.key("node1").unwrap().clone())));
+        sim.set_behaviour("node6", Box::new(DoubleVoter::new(hrc.keys.key("node6").unwrap().clone())));
+        sim.set_behaviour("node12", Box::new(Replayer::default()));
+        assert!(sim.run(&mut hrc));
+        assert_eq!(hrc.final_commitment().map(|c| c.verify(&hrc.shape(), &hrc.keyring())), Some(Ok(())));
+    }
+
+    #[test]
+    fn test_double_votes_are_recorded_and_slashed() {
+        // node6 backs the proposal of the first leaf, node1..node4, in its own leaf too.
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(node_ids(8), 1, 1, 1);
+        let mut sim = Simulator::new(SimConfig { seed: 5, ..SimConfig::default() });
+        sim.set_behaviour("node6", Box::new(DoubleVoter::new(hrc.keys.key("node6").unwrap().clone())));
+        assert!(sim.run(&mut hrc));
+        let first_leaf = hrc.children[0].triad.to_record().hash();
+        let leaf = &mut hrc.children[1];
+        assert!(leaf.triad.evidence.is_empty());
+        assert!(!leaf.evidence.is_empty());
+        for evidence in &leaf.evidence {
+            let Evidence::DoubleVote { first, second, .. } = evidence else { panic!("{:?}", evidence) };
+            assert_eq!(first.sender, "node6");
+            assert!(first.digest == first_leaf || second.digest == first_leaf);
+        }
+        assert!(hrc.children[0].evidence.is_empty());
+
+        // The next round puts the evidence on record, and committing it triggers the slash.
+        let offences = hrc.children[1].evidence.len();
+        assert!(sim.run(&mut hrc));
+        let leaf = &mut hrc.children[1];
+        assert_eq!(leaf.triad.evidence.len(), offences);
+        assert_eq!(leaf.final_commitment().map(|c| c.triad_hash), Some(leaf.triad.hash()));
+
+        let mut token = WaclaniumToken::new(0, 1_000_000, 1);
+        token.mint("node6", 1_000).unwrap();
+        token.stake("node6", 1_000).unwrap();
+        leaf.security.promote_node("node6");
+        let mut slasher = Slasher::with_puzzle(leaf.proof.puzzle, SlashingConfig::default());
+        let records = slasher.slash_triad(&leaf.triad, &leaf.keyring(), &mut token, &mut leaf.security);
+        assert_eq!(records.len(), offences);
+        assert!(token.get_stake("node6") < 1_000);
+        assert!(!leaf.security.is_node_promoted("node6"));
+
+        // Once on record, the evidence is not carried into later Triads; only what the
+        // last round turned up is.
+        let pending = hrc.children[1].evidence.clone();
+        let mut sim = Simulator::new(SimConfig { seed: 5, ..SimConfig::default() });
+        assert!(sim.run(&mut hrc));
+        assert_eq!(hrc.children[1].triad.evidence, pending);
+    }
+
+    #[test]
+    fn test_invalid_pof_proposals_are_recorded() {
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(node_ids(4), 1, 1, 0);
+        let mut sim = Simulator::new(SimConfig { seed: 5, ..SimConfig::default() });
+        sim.set_behaviour("node1", Box::new(InvalidPofProposer::new(hrc.keys.key("node1").unwrap().clone())));
+        assert!(sim.run(&mut hrc));
+        assert!(matches!(&hrc.evidence[..], [evidence @ Evidence::InvalidPof { .. }] if evidence.offender() == "node1"));
+        assert_eq!(hrc.evidence[0].validate_with(&hrc.proof.puzzle, &hrc.keyring()), Ok(()));
+
+        // A difficulty-0 proposal is self-consistent, so it is rejected without evidence.
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(node_ids(4), 1, 1, 0);
+        let mut sim = Simulator::new(SimConfig { seed: 5, ..SimConfig::default() });
+        sim.set_behaviour("node1", Box::new(InvalidPofProposer::zero_difficulty(hrc.keys.key("node1").unwrap().clone())));
+        assert!(sim.run(&mut hrc));
+        // The next leader's honest proposal took sequence 1, so nothing committed before it.
+        let proof = hrc.final_commitment().map(|c| &c.proof);
+        assert!(matches!(proof, Some(CommitmentProof::Quorum { certificate, .. }) if (certificate.view, certificate.sequence) == (1, 1)));
+        assert!(hrc.evidence.is_empty());
+    }
+
+    #[test]
+    fn test_heavy_loss_prevents_commit() {
+        let config = SimConfig { seed: 3, drop_rate: 1.0, ..SimConfig::default() };
+        let (committed, sim) = run(config, &[], node_ids(4), 0);
+        assert!(!committed);
+        assert!(sim
+            .trace()
+            .iter()
+            .filter(|e| e.from != e.to)
+            .all(|e| e.kind == TraceKind::Dropped(DropReason::Loss)));
+    }
+}
@@ -1,17 +1,14 @@
 use clap::Parser;
 use seirchain::core::consensus::hierarchical_recursive::HierarchicalRecursiveConsensus;
+use seirchain::core::consensus::metrics::ConsensusReport;
+use seirchain::core::security::admission::AdmissionControl;
+use seirchain::core::security::keys::Keystore;
 use seirchain::interface::economics::waclanium_token::WaclaniumToken;
 use warp::Filter;
-use warp::cors::CorsForbidden;
-use warp::http::Method;
-use warp::filters::cors::Builder;
 use serde::Serialize;
 use std::convert::Infallible;
-use tokio::sync::Mutex;
 use std::sync::Arc;
-use rand::Rng;
 use lazy_static::lazy_static;
-use serde_json::json;
 
 /// CLI arguments for server
 #[derive(Parser, Debug)]
@@ -26,11 +23,11 @@ struct Args {
     fault_tolerance: usize,
 
     /// Difficulty level for Proof-of-Fractal puzzle
-    #[arg(short, long, default_value_t = 4)]
+    #[arg(short, long, default_value_t = 2)]
     difficulty: u32,
 
     /// Depth level for consensus (added to match function signature)
-    #[arg(short, long, default_value_t = 3)]
+    #[arg(long, default_value_t = 3)]
     depth: u32,
 
     /// Server user ID to receive minted tokens
@@ -40,6 +37,10 @@ struct Args {
     /// Amount of Waclanium tokens to mint on successful mining
     #[arg(short = 'a', long, default_value_t = 100)]
     mint_amount: u64,
+
+    /// Leading zero bits of the work bond each node presents to be admitted
+    #[arg(long, default_value_t = 16)]
+    admission_difficulty: u32,
 }
 
 #[derive(Serialize, Clone)]
@@ -54,6 +55,10 @@ struct RecentActivityResponse {
     transactions: Vec<Transaction>,
 }
 
+/// The report of the last finished consensus round, or None while the first is running.
+/// Handlers read this snapshot, so serving never waits on a round in progress.
+type ReportSnapshot = Arc<RwLock<Option<ConsensusReport>>>;
+
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
@@ -66,19 +71,30 @@ async fn main() {
     println!("Server ID: {}", args.server_id);
     println!("Mint amount: {}", args.mint_amount);
 
-    // Create the consensus instance wrapped in Arc<Mutex> for shared state
-    let consensus = Arc::new(Mutex::new(HierarchicalRecursiveConsensus::new(
+    // Every node runs in this process, so each gets a fresh key for this run
+    let keys = Keystore::generate(&args.nodes, &mut rand::rngs::OsRng);
+
+    // Admit the nodes
+    let stakes = WaclaniumToken::new(0, 1_000_000, 0);
+    let admission = match AdmissionControl::admit_local(&keys, args.admission_difficulty, &stakes) {
+        Ok(admission) => admission,
+        Err(e) => {
+            eprintln!("Failed to admit nodes: {}", e);
+            return;
+        }
+    };
+
+    let mut hrc = HierarchicalRecursiveConsensus::new(
         args.nodes.clone(),
         args.fault_tolerance,
         args.difficulty,
         args.depth,
-    )));
-
-    // Clone consensus before moving into warp filter closure
-    let consensus_for_filter = consensus.clone();
+        keys,
+    );
+    hrc.set_admitted(admission.keyring());
 
-    // Clone consensus for use in warp filter
-    let consensus_filter = warp::any().map(move || consensus_for_filter.clone());
+    // The consensus task owns the instance and publishes a report when its round ends
+    let report: ReportSnapshot = Arc::new(RwLock::new(None));
 
     // Define CORS policy to allow frontend origin
     let cors = warp::cors()
@@ -90,17 +106,18 @@ async fn main() {
 
     // Define /api/recent_activity endpoint with CORS
     let recent_activity_route = warp::path!("api" / "recent_activity")
-        .and(consensus_filter.clone())
-        .and(wallet_store_filter.clone())
+        .and(wallet_store_filter)
         .and_then(handle_recent_activity)
         .with(cors.clone());
 
+    // Define /api/consensus_report endpoint with CORS
+    let consensus_report_route = consensus_report_route(report.clone()).with(cors.clone());
+
     // Define /api/send_transaction endpoint
     let send_transaction_route = warp::path!("api" / "send_transaction")
         .and(warp::post())
         .and(warp::body::json())
-        .and(consensus_filter.clone())
-        .and(wallet_store_filter.clone())
+        .and(wallet_store_filter)
         .and_then(handle_send_transaction)
         .with(cors.clone());
 
@@ -108,7 +125,7 @@ async fn main() {
     let sign_in_route = warp::path!("api" / "sign_in")
         .and(warp::post())
         .and(warp::body::json())
-        .and(wallet_store_filter.clone())
+        .and(wallet_store_filter)
         .and_then(handle_sign_in)
         .with(cors.clone());
 
@@ -116,30 +133,27 @@ async fn main() {
     let create_wallet_route = warp::path!("api" / "create_wallet")
         .and(warp::post())
         .and(warp::body::json())
-        .and(wallet_store_filter.clone())
+        .and(wallet_store_filter)
         .and_then(handle_create_wallet)
         .with(cors.clone());
 
     // Combine all routes
     let routes = recent_activity_route
+        .or(consensus_report_route)
         .or(send_transaction_route)
         .or(sign_in_route)
         .or(create_wallet_route);
 
-    // Run the warp server concurrently with consensus run
-    let server_future = warp::serve(routes).run(([0, 0, 0, 0], 8080));
-
-    // Clone consensus again for the async task to avoid move error
-    let consensus_for_task = consensus.clone();
-
-    // Run consensus in a separate async task
-    let consensus_future = tokio::spawn(async move {
-        let mut consensus = consensus_for_task.lock().await;
-        let mut rng = rand::thread_rng();
-        let result = consensus.run_consensus(&mut rng);
+    // Run consensus on a blocking thread; it is synchronous and would stall the server's workers
+    let consensus_task = tokio::task::spawn_blocking(move || {
+        let mut consensus = hrc;
+        let result = consensus.run_consensus(&mut rand::thread_rng());
 
         if result {
             println!("Consensus reached successfully.");
+            if let Some(commitment) = consensus.final_commitment() {
+                println!("Root commitment: {}", hex::encode(commitment.triad_hash));
+            }
 
             // Create WaclaniumToken instance with initial supply, max supply, and fee (fee set to 0)
             let mut token = WaclaniumToken::new(0, 1_000_000, 0);
@@ -155,28 +169,25 @@ async fn main() {
                 }
             }
         } else {
-            eprintln!("Consensus failed or not reached.");
-            eprintln!("Diagnostic info:");
-            eprintln!("Nodes: {:?}", consensus.nodes);
-            eprintln!("Fault tolerance: {}", consensus.fault_tolerance);
-            eprintln!("State: {:?}", consensus.state);
-            eprintln!("Security paths valid: {}", consensus.security.validate_paths());
-        }
-    });
-
-    // Run both futures concurrently
-    tokio::select! {
-        res = server_future => {
-            if let Err(e) = res {
-                eprintln!("Server task panicked: {:?}", e);
+            match consensus.metrics.failure {
+                Some(reason) => eprintln!("Consensus failed or not reached: {}", reason),
+                None => eprintln!("Consensus failed or not reached."),
             }
         }
-        res = consensus_future => {
-            if let Err(e) = res {
-                eprintln!("Consensus task panicked: {:?}", e);
-            }
+        let snapshot = consensus.report();
+        if let Ok(json) = snapshot.to_json() {
+            println!("Consensus report:\n{}", json);
         }
-    }
+        *report.blocking_write() = Some(snapshot);
+    });
+    tokio::spawn(async move {
+        if let Err(e) = consensus_task.await {
+            eprintln!("Consensus task panicked: {:?}", e);
+        }
+    });
+
+    // Keep serving, the final report included, after the round ends
+    warp::serve(routes).run(([0, 0, 0, 0], 8080)).await;
 }
 
 use std::collections::HashMap;
@@ -241,7 +252,6 @@ lazy_static! {
 }
 
 async fn handle_recent_activity(
-    _consensus: Arc<Mutex<HierarchicalRecursiveConsensus>>,
     wallet_store: Arc<WalletStore>,
 ) -> Result<impl warp::Reply, Infallible> {
     let transactions = wallet_store.recent_transactions().await;
@@ -249,9 +259,23 @@ async fn handle_recent_activity(
     Ok(warp::reply::json(&response))
 }
 
+fn consensus_report_route(
+    report: ReportSnapshot,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("api" / "consensus_report")
+        .and(warp::any().map(move || report.clone()))
+        .and_then(handle_consensus_report)
+}
+
+async fn handle_consensus_report(
+    report: ReportSnapshot,
+) -> Result<impl warp::Reply, Infallible> {
+    let report = report.read().await.clone();
+    Ok(warp::reply::json(&report))
+}
+
 async fn handle_send_transaction(
     body: serde_json::Value,
-    _consensus: Arc<Mutex<HierarchicalRecursiveConsensus>>,
     wallet_store: Arc<WalletStore>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let from_address = body.get("from_address").and_then(|v| v.as_str()).unwrap_or("");
@@ -294,3 +318,33 @@ async fn handle_create_wallet(
     let wallet = wallet_store.create_wallet(user_id).await;
     Ok(warp::reply::json(&serde_json::json!({"address": wallet.address})))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use seirchain::core::security::keys::node_ids;
+
+    async fn fetch(route: &(impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone + 'static)) -> serde_json::Value {
+        let response = warp::test::request().path("/api/consensus_report").reply(route).await;
+        assert_eq!(response.status(), 200);
+        serde_json::from_slice(response.body()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_consensus_report_serves_the_snapshot() {
+        let report: ReportSnapshot = Arc::new(RwLock::new(None));
+        let route = consensus_report_route(report.clone());
+        assert_eq!(fetch(&route).await, serde_json::Value::Null);
+
+        let mut hrc = HierarchicalRecursiveConsensus::simulated(node_ids(4), 1, 1, 0);
+        assert!(hrc.run_consensus(&mut ChaCha8Rng::seed_from_u64(1)));
+        *report.write().await = Some(hrc.report());
+
+        let served = fetch(&route).await;
+        assert_eq!(served["committed"], serde_json::Value::Bool(true));
+        let parsed: ConsensusReport = serde_json::from_value(served).unwrap();
+        assert_eq!(parsed, hrc.report());
+    }
+}
@@ -1,7 +1,12 @@
 // CLI entry point to start mining using HierarchicalRecursiveConsensus with CLI arguments
 
 use clap::Parser;
+use seirchain::core::consensus::finality::FinalityTracker;
 use seirchain::core::consensus::hierarchical_recursive::HierarchicalRecursiveConsensus;
+use seirchain::core::consensus::puzzle::SelfSimilarPuzzle;
+use seirchain::core::consensus::retarget::{is_retarget_point, retarget, RetargetConfig};
+use seirchain::core::security::admission::AdmissionControl;
+use seirchain::core::security::keys::Keystore;
 use seirchain::interface::economics::waclanium_token::WaclaniumToken;
 
 /// CLI arguments for miner
@@ -16,8 +21,8 @@ struct Args {
     #[arg(short, long, default_value_t = 1)]
     fault_tolerance: usize,
 
-    /// Difficulty level for Proof-of-Fractal puzzle
-    #[arg(short, long, default_value_t = 4)]
+    /// Difficulty level for Proof-of-Fractal puzzle; retargeted as rounds complete
+    #[arg(short, long, default_value_t = 2)]
     difficulty: u32,
 
     /// Depth of the fractal hierarchy
@@ -31,6 +36,33 @@ struct Args {
     /// Amount of Waclanium tokens to mint on successful mining
     #[arg(short = 'a', long, default_value_t = 100)]
     mint_amount: u64,
+
+    /// Number of worker threads used to solve the Proof-of-Fractal puzzle
+    #[arg(short, long, default_value_t = 1)]
+    threads: usize,
+
+    /// Leading zero bits of the work bond each node presents to be admitted
+    #[arg(long, default_value_t = 16)]
+    admission_difficulty: u32,
+
+    /// Number of Triads to mine, one after another
+    #[arg(short, long, default_value_t = 1)]
+    rounds: u64,
+
+    /// Seconds wanted between Triads; difficulty is retargeted toward it
+    #[arg(long, default_value_t = 10)]
+    target_interval: u64,
+
+    /// Number of Triads between difficulty retargets
+    #[arg(long, default_value_t = 16)]
+    retarget_window: usize,
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 fn main() {
@@ -43,35 +75,103 @@ fn main() {
     println!("Depth: {}", args.depth);
     println!("Miner ID: {}", args.miner_id);
     println!("Mint amount: {}", args.mint_amount);
+    println!("Threads: {}", args.threads);
+    println!("Rounds: {}", args.rounds);
+
+    // Every node runs in this process, so each gets a fresh key for this run
+    let keys = Keystore::generate(&args.nodes, &mut rand::rngs::OsRng);
+
+    // Create WaclaniumToken instance with initial supply, max supply and no transfer fee
+    let mut token = WaclaniumToken::new(0, 1_000_000, 0);
 
-    // Create the consensus instance
-    let mut consensus = HierarchicalRecursiveConsensus::new(args.nodes, args.fault_tolerance, args.difficulty, args.depth);
+    // Admit the nodes
+    let admission = match AdmissionControl::admit_local(&keys, args.admission_difficulty, &token) {
+        Ok(admission) => admission,
+        Err(e) => {
+            eprintln!("Failed to admit nodes: {}", e);
+            return;
+        }
+    };
+
+    let retarget_config = RetargetConfig {
+        target_interval_secs: args.target_interval,
+        window: args.retarget_window,
+        ..RetargetConfig::default()
+    };
+    let mut difficulty = args.difficulty;
+    // When mining started, then when each round ended
+    let mut timestamps = vec![unix_now()];
+
+    // One consensus instance for the whole run, so every round gets its own round context
+    let mut consensus = HierarchicalRecursiveConsensus::new(args.nodes.clone(), args.fault_tolerance, difficulty, args.depth, keys);
+    consensus.set_admitted(admission.keyring());
+    consensus.set_mining_threads(args.threads);
 
-    // Run the consensus (mining) process
-    let result = consensus.run_consensus(&mut rand::thread_rng());
+    // One tracker for the whole run. A Triad gains a confirmation from each layer that
+    // commits over it, so none becomes final unless the hierarchy has enough layers.
+    let mut finality = FinalityTracker::default();
+    let layers = consensus.shape().layers();
+    let mut finalized = 0;
+    if layers < finality.depth() {
+        println!("Triads need {} layers to be final but the hierarchy has {}; none will be", finality.depth(), layers);
+    }
 
-    if result {
-        println!("Mining succeeded and consensus reached.");
+    for round in 1..=args.rounds {
+        println!("Round {} at difficulty {}", round, difficulty);
+        consensus.set_difficulty(difficulty);
 
-        // Create WaclaniumToken instance with initial supply and max supply
-        let mut token = WaclaniumToken::new(0, 1_000_000);
+        // Run the consensus (mining) process
+        let result = consensus.run_consensus(&mut rand::thread_rng());
 
-        // Mint tokens to miner
-        match token.mint(&args.miner_id, args.mint_amount) {
-            Ok(_) => {
-                let balance = token.get_balance(&args.miner_id);
-                println!("Minted {} Waclanium tokens to {}. New balance: {}", args.mint_amount, args.miner_id, balance);
+        if result {
+            println!("Mining succeeded and consensus reached.");
+            if let Some(commitment) = consensus.final_commitment() {
+                println!("Root commitment: {}", hex::encode(commitment.triad_hash));
+                match finality.record_with_shape(commitment, &consensus.shape(), &consensus.keyring()) {
+                    Ok(newly_final) if layers >= finality.depth() => {
+                        finalized += newly_final.len();
+                        println!(
+                            "Triads final after {} layers: {} this round, {} so far",
+                            finality.depth(),
+                            newly_final.len(),
+                            finalized
+                        );
+                    }
+                    Ok(_) => {}
+                    Err(e) => println!("Root commitment failed verification: {}", e),
+                }
             }
-            Err(e) => {
-                println!("Failed to mint tokens: {}", e);
+
+            // Mint tokens to miner
+            match token.mint(&args.miner_id, args.mint_amount) {
+                Ok(_) => {
+                    let balance = token.get_balance(&args.miner_id);
+                    println!("Minted {} Waclanium tokens to {}. New balance: {}", args.mint_amount, args.miner_id, balance);
+                }
+                Err(e) => {
+                    println!("Failed to mint tokens: {}", e);
+                }
             }
+
+        } else {
+            match consensus.metrics.failure {
+                Some(reason) => eprintln!("Mining failed or consensus not reached: {}", reason),
+                None => eprintln!("Mining failed or consensus not reached."),
+            }
+        }
+
+        // Retarget once a window of rounds has run at this difficulty. A round that failed,
+        // e.g. because the PoF timed out, took its time all the same, so it counts too and
+        // a difficulty too high to solve comes down.
+        timestamps.push(unix_now());
+        if is_retarget_point(timestamps.len() as u64 - 1, &retarget_config) {
+            difficulty = retarget(&SelfSimilarPuzzle, difficulty, &timestamps, &retarget_config);
+            println!("Difficulty retargeted to {}", difficulty);
+        }
+
+        match consensus.report().to_json() {
+            Ok(json) => println!("Consensus report:\n{}", json),
+            Err(e) => eprintln!("Failed to serialize consensus report: {}", e),
         }
-    } else {
-        eprintln!("Mining failed or consensus not reached.");
-        eprintln!("Diagnostic info:");
-        eprintln!("Nodes: {:?}", consensus.nodes);
-        eprintln!("Fault tolerance: {}", consensus.fault_tolerance);
-        eprintln!("State: {:?}", consensus.state);
-        eprintln!("Security paths valid: {}", consensus.security.validate_paths());
     }
 }
@@ -1,4 +1,10 @@
-use seirchain::network::p2p::{P2PNode, P2PMessage};
+use seirchain::core::consensus::pbft::{ConsensusMessage, ConsensusTransport};
+use seirchain::core::consensus::puzzle::SelfSimilarPuzzle;
+use seirchain::core::triad_matrix::evidence::{Vote, VoteKind};
+use seirchain::core::security::keys::Keystore;
+use seirchain::core::triad_matrix::matrix::TriadMatrix;
+use seirchain::core::triad_matrix::triad_structure::Triad;
+use seirchain::network::p2p::{NodeStatus, P2PConsensusTransport, P2PNode, P2PMessage};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
@@ -39,3 +45,86 @@ async fn test_p2p_ping_pong() {
 
     sleep(Duration::from_secs(1)).await;
 }
+
+#[test]
+fn test_node_status_from_matrix() {
+    let mut matrix = TriadMatrix::new(Triad::genesis(None)).unwrap();
+    let mut triad = Triad::new();
+    triad.parent_hash = matrix.next_parent_hash();
+    matrix.insert(triad).unwrap();
+
+    let status = NodeStatus::from_matrix("node1".to_string(), &matrix, &SelfSimilarPuzzle);
+    assert_eq!(status.node_id, "node1");
+    assert_eq!(status.block_height, 1);
+    // Neither Triad carries a solved PoF, so no work has been done.
+    assert_eq!(status.total_difficulty, 0);
+}
+
+#[tokio::test]
+async fn test_consensus_messages_reach_peer_inbox() {
+    let node1 = Arc::new(P2PNode::new("127.0.0.1:0", "node1".to_string()).await.unwrap());
+    let node2 = Arc::new(P2PNode::new("127.0.0.1:0", "node2".to_string()).await.unwrap());
+    let addr2 = node2.listener.local_addr().unwrap();
+
+    let node2_clone = node2.clone();
+    tokio::spawn(async move {
+        node2_clone.run().await;
+    });
+    node1.add_peer(addr2).await;
+
+    let keys = Keystore::derive(&["node1".to_string()], &[0u8; 32]);
+    let vote = Vote::sign(VoteKind::Prepare, [0u8; 32], 0, 1, [7u8; 32], "node1", keys.key("node1").unwrap());
+    let mut transport1 = P2PConsensusTransport::new(&node1);
+    transport1.broadcast("node1", ConsensusMessage::Prepare(vote.clone()));
+    // The sender hears its own broadcast.
+    assert_eq!(transport1.receive("node1"), Some(ConsensusMessage::Prepare(vote.clone())));
+
+    sleep(Duration::from_secs(1)).await;
+
+    let mut transport2 = P2PConsensusTransport::new(&node2);
+    assert_eq!(transport2.receive("node1"), None);
+    assert_eq!(transport2.receive("node2"), Some(ConsensusMessage::Prepare(vote)));
+    assert_eq!(transport2.receive("node2"), None);
+}
+
+#[tokio::test]
+async fn test_unsigned_consensus_messages_are_dropped() {
+    let ids = vec!["node1".to_string(), "node2".to_string()];
+    let keys = Keystore::derive(&ids, &[0u8; 32]);
+    let node1 = Arc::new(P2PNode::new("127.0.0.1:0", "node1".to_string()).await.unwrap());
+    let node2 = Arc::new(
+        P2PNode::new("127.0.0.1:0", "node2".to_string())
+            .await
+            .unwrap()
+            .with_keyring(keys.keyring()),
+    );
+    let addr2 = node2.listener.local_addr().unwrap();
+
+    let node2_clone = node2.clone();
+    tokio::spawn(async move {
+        node2_clone.run().await;
+    });
+    node1.add_peer(addr2).await;
+
+    // node1 puts node2's name on a vote and a pre-prepare it signed itself.
+    let mut forged_vote = Vote::sign(VoteKind::Prepare, [0u8; 32], 0, 1, [7u8; 32], "node1", keys.key("node1").unwrap());
+    forged_vote.sender = "node2".to_string();
+    let proposal = Triad::new().to_record();
+    let forged_pre_prepare = match ConsensusMessage::pre_prepare([0u8; 32], 0, 1, proposal, "node1", keys.key("node1").unwrap()) {
+        ConsensusMessage::PrePrepare { context, view, sequence, proposal, signature, .. } => {
+            ConsensusMessage::PrePrepare { context, view, sequence, proposal, sender: "node2".to_string(), signature }
+        }
+        _ => unreachable!(),
+    };
+    let vote = Vote::sign(VoteKind::Prepare, [0u8; 32], 0, 1, [7u8; 32], "node1", keys.key("node1").unwrap());
+    let mut transport1 = P2PConsensusTransport::new(&node1);
+    transport1.broadcast("node1", ConsensusMessage::Prepare(forged_vote));
+    transport1.broadcast("node1", forged_pre_prepare);
+    transport1.broadcast("node1", ConsensusMessage::Prepare(vote.clone()));
+
+    sleep(Duration::from_secs(1)).await;
+
+    let mut transport2 = P2PConsensusTransport::new(&node2);
+    assert_eq!(transport2.receive("node2"), Some(ConsensusMessage::Prepare(vote)));
+    assert_eq!(transport2.receive("node2"), None);
+}
@@ -1,4 +1,10 @@
-use seirchain::core::security::redundant_paths::RedundantPathSecurity;
+use seirchain::core::consensus::committee::{eligible_nodes, CommitteeConfig};
+use seirchain::core::security::admission::{solve_work, AdmissionConfig, Bond, JoinRequest};
+use seirchain::core::security::keys::Keystore;
+use seirchain::core::security::node_scoring::{NodeEvent, PromotionChange, ScoringConfig};
+use seirchain::core::security::redundant_paths::{PathDelivery, RedundantPathSecurity};
+use seirchain::interface::economics::waclanium_token::WaclaniumToken;
+use seirchain::network::routing::multi_path_fractal::MultiPathFractalRouting;
 
 #[test]
 fn test_redundant_path_security() {
@@ -7,6 +13,9 @@ fn test_redundant_path_security() {
     // Test adding a path
     assert!(rps.add_path("path1"));
     assert!(!rps.add_path("path1"));
+    // A single path is not redundant
+    assert!(!rps.validate_paths());
+    assert!(rps.add_path("path2"));
     assert!(rps.validate_paths());
 
     // Test removing a path
@@ -24,3 +33,215 @@ fn test_redundant_path_security() {
     assert!(!rps.remove_promoted_node("node1"));
     assert!(!rps.is_node_promoted("node1"));
 }
+
+#[test]
+fn test_score_driven_promotion() {
+    let config = ScoringConfig { half_life_secs: 100, ..ScoringConfig::default() };
+    let mut rps = RedundantPathSecurity::with_scoring(config);
+
+    // Four PoF solves reach 40, short of the threshold; ten valid votes push it over.
+    for _ in 0..4 {
+        assert_eq!(rps.record_event("node1", NodeEvent::PofSolved, 1_000), None);
+    }
+    for _ in 0..9 {
+        rps.record_event("node1", NodeEvent::ValidVote, 1_000);
+    }
+    assert!(!rps.is_node_promoted("node1"));
+    assert_eq!(rps.record_event("node1", NodeEvent::ValidVote, 1_000), Some(PromotionChange::Promoted));
+    assert!(rps.is_node_promoted("node1"));
+
+    let stats = rps.scoring.stats("node1").unwrap();
+    assert_eq!((stats.pof_solves, stats.valid_votes, stats.heartbeats), (4, 10, 0));
+
+    // Falling between the thresholds keeps the promotion: 50 halves to 25 after one half-life.
+    assert!(rps.update_promotions(1_100).is_empty());
+    assert!(rps.is_node_promoted("node1"));
+    assert!((rps.scoring.score("node1", 1_100) - 25.0).abs() < 1e-9);
+
+    // Another half-life and the node is demoted.
+    let changes = rps.update_promotions(1_200);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].change, PromotionChange::Demoted);
+    assert!(!rps.is_node_promoted("node1"));
+
+    let history: Vec<(PromotionChange, u64)> =
+        rps.promotion_history_of("node1").iter().map(|r| (r.change, r.at)).collect();
+    assert_eq!(history, vec![(PromotionChange::Promoted, 1_000), (PromotionChange::Demoted, 1_200)]);
+    assert!(rps.promotion_history_of("node2").is_empty());
+}
+
+#[test]
+fn test_misbehaviour_demotes() {
+    let mut rps = RedundantPathSecurity::new();
+    for _ in 0..6 {
+        rps.record_event("node2", NodeEvent::PofSolved, 10);
+    }
+    assert!(rps.is_node_promoted("node2"));
+    for _ in 0..4 {
+        rps.record_event("node2", NodeEvent::Heartbeat, 10);
+    }
+
+    assert_eq!(rps.record_event("node2", NodeEvent::Misbehaviour, 10), Some(PromotionChange::Demoted));
+    assert!(!rps.is_node_promoted("node2"));
+    assert_eq!(rps.scoring.stats("node2").unwrap().misbehaviours, 1);
+    assert!(rps.scoring.score("node2", 10) < 20.0);
+
+    // A node promoted by hand is not touched by automatic re-evaluation.
+    rps.promote_node("node3");
+    rps.update_promotions(1_000_000);
+    assert!(rps.is_node_promoted("node3"));
+}
+
+fn route(nodes: &[&str]) -> Vec<String> {
+    nodes.iter().map(|n| n.to_string()).collect()
+}
+
+fn delivery(nodes: &[&str], triad_hash: [u8; 32]) -> PathDelivery {
+    PathDelivery { path: route(nodes), triad_hash }
+}
+
+/// Three routes from src to dst: through a, through b, and through c then d. a and b are also linked.
+fn topology() -> MultiPathFractalRouting {
+    let mut routing = MultiPathFractalRouting::new();
+    for (x, y) in [("src", "a"), ("a", "dst"), ("src", "b"), ("b", "dst"), ("src", "c"), ("c", "d"), ("d", "dst"), ("a", "b")] {
+        routing.add_link(x, y);
+    }
+    routing
+}
+
+#[test]
+fn test_triad_needs_disjoint_agreeing_paths() {
+    let routing = topology();
+    let rps = RedundantPathSecurity::new();
+    let hash = [1u8; 32];
+
+    // The same triad over one path twice, and over a second path through the same relay, is not redundant.
+    let validation = rps.validate_triad(&routing, "src", "dst", &hash, &[
+        delivery(&["src", "a", "dst"], hash),
+        delivery(&["src", "a", "dst"], hash),
+        delivery(&["src", "b", "a", "dst"], hash),
+    ]);
+    assert!(!validation.is_accepted());
+    assert_eq!(validation.agreeing, vec![route(&["src", "a", "dst"])]);
+    assert_eq!(validation.overlapping, vec![route(&["src", "b", "a", "dst"])]);
+
+    // Two paths sharing no relay are.
+    let validation = rps.validate_triad(&routing, "src", "dst", &hash, &[
+        delivery(&["src", "a", "dst"], hash),
+        delivery(&["src", "c", "d", "dst"], hash),
+    ]);
+    assert!(validation.is_accepted());
+    assert!(validation.disagreeing.is_empty());
+
+    // Paths the topology does not have, or between other nodes, do not count.
+    let validation = rps.validate_triad(&routing, "src", "dst", &hash, &[
+        delivery(&["src", "a", "dst"], hash),
+        delivery(&["src", "d", "dst"], hash),
+        delivery(&["a", "b", "dst"], hash),
+    ]);
+    assert!(!validation.is_accepted());
+    assert_eq!(validation.unrouted, vec![route(&["src", "d", "dst"]), route(&["a", "b", "dst"])]);
+}
+
+#[test]
+fn test_disjoint_paths_are_counted_whatever_the_delivery_order() {
+    let mut routing = MultiPathFractalRouting::new();
+    for (x, y) in [("src", "a"), ("a", "b"), ("b", "dst"), ("a", "d"), ("d", "dst"), ("src", "c"), ("c", "b")] {
+        routing.add_link(x, y);
+    }
+    let rps = RedundantPathSecurity::new();
+    let hash = [1u8; 32];
+
+    // Counting src-a-b-dst first would block both others, yet src-a-d-dst and src-c-b-dst share no relay.
+    let validation = rps.validate_triad(&routing, "src", "dst", &hash, &[
+        delivery(&["src", "a", "b", "dst"], hash),
+        delivery(&["src", "a", "d", "dst"], hash),
+        delivery(&["src", "c", "b", "dst"], hash),
+    ]);
+    assert!(validation.is_accepted());
+    assert_eq!(validation.agreeing.len(), 2);
+    assert_eq!(validation.overlapping, vec![route(&["src", "a", "b", "dst"])]);
+}
+
+#[test]
+fn test_disagreeing_paths_are_reported() {
+    let routing = topology();
+    let mut rps = RedundantPathSecurity::new();
+    rps.min_disjoint_paths = 3;
+    let (hash, forged) = ([1u8; 32], [2u8; 32]);
+
+    let paths = routing.disjoint_paths("src", "dst");
+    assert_eq!(paths.len(), 3);
+    let mut deliveries: Vec<PathDelivery> = paths
+        .iter()
+        .map(|path| PathDelivery { path: path.clone(), triad_hash: hash })
+        .collect();
+    assert!(rps.validate_triad(&routing, "src", "dst", &hash, &deliveries).is_accepted());
+
+    // c tampers with what it relays: two paths still agree, short of the three required.
+    deliveries[2].triad_hash = forged;
+    let validation = rps.validate_triad(&routing, "src", "dst", &hash, &deliveries);
+    assert!(!validation.is_accepted());
+    assert_eq!(validation.agreeing.len(), 2);
+    assert_eq!(validation.disagreeing, vec![delivery(&["src", "c", "d", "dst"], forged)]);
+
+    // Requiring two is enough to accept, and the disagreement is still reported.
+    rps.min_disjoint_paths = 2;
+    let validation = rps.validate_triad(&routing, "src", "dst", &hash, &deliveries);
+    assert!(validation.is_accepted());
+    assert_eq!(validation.disagreeing.len(), 1);
+}
+
+#[test]
+fn test_only_admitted_nodes_join() {
+    let config = AdmissionConfig { work_difficulty: 8, ..AdmissionConfig::default() };
+    let mut rps = RedundantPathSecurity::with_admission(config.clone());
+    let mut token = WaclaniumToken::new(0, 1_000_000, 1);
+    let nodes = vec!["node1".to_string(), "node2".to_string()];
+    let keys = Keystore::derive(&nodes, &[5u8; 32]);
+    for node in &nodes {
+        token.mint(node, 200).unwrap();
+        token.stake(node, 150).unwrap();
+    }
+
+    // Before admission a node can neither hold a path nor be promoted, by hand or by score.
+    assert!(!rps.is_admitted("node1"));
+    assert!(!rps.add_path("node1"));
+    assert!(!rps.promote_node("node1"));
+    for _ in 0..6 {
+        assert_eq!(rps.record_event("node1", NodeEvent::PofSolved, 10), None);
+    }
+    assert!(rps.promotion_history.is_empty());
+
+    let key = keys.key("node1").unwrap();
+    let nonce = solve_work("node1", &key.verifying_key(), config.work_difficulty).unwrap();
+    let request = JoinRequest::sign("node1", "192.168.1.10".parse().unwrap(), 10, Bond::Work { nonce }, key);
+    rps.admit_node(&request, request.address, &token, 10).unwrap();
+    assert!(rps.add_path("node1"));
+    assert_eq!(rps.record_event("node1", NodeEvent::PofSolved, 10), Some(PromotionChange::Promoted));
+
+    let request = JoinRequest::sign("node2", "192.168.2.10".parse().unwrap(), 10, Bond::Stake { amount: 150 }, keys.key("node2").unwrap());
+    // A request naming another address than the one it arrived from is refused.
+    assert!(rps.admit_node(&request, "192.168.1.10".parse().unwrap(), &token, 10).is_err());
+    rps.admit_node(&request, request.address, &token, 10).unwrap();
+    assert!(rps.promote_node("node2"));
+
+    let committee = CommitteeConfig::default();
+    assert_eq!(eligible_nodes(&nodes, &rps, &token, &committee), nodes);
+
+    // Unstaking below the bond drops the node from paths, promotion and committees.
+    rps.unstake(&mut token, "node2", 1).unwrap();
+    assert!(!rps.is_admitted("node2"));
+    assert!(!rps.is_node_promoted("node2"));
+    assert_eq!(eligible_nodes(&nodes, &rps, &token, &committee), vec!["node1".to_string()]);
+
+    // So does revoking admission outright.
+    assert!(rps.revoke_admission("node1"));
+    assert!(!rps.is_node_promoted("node1"));
+    assert!(!rps.add_path("node1"));
+
+    // Without admission control every ID is admitted, and admitting is an error.
+    let mut open = RedundantPathSecurity::new();
+    assert!(open.is_admitted("anyone"));
+    assert!(open.admit_node(&request, request.address, &token, 10).is_err());
+}